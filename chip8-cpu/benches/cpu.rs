@@ -0,0 +1,255 @@
+//! How fast the cpu runs, by class of instructions, when drawing a lot
+//! and on the games. Run with `cargo bench -p chip8-cpu --bench cpu`.
+//!
+//! The throughput is the number of instructions executed, so the elem/s
+//! printed are instructions per second.
+
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::palette::Color;
+use chip8_cpu::threaded::Threaded;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::time::{Duration, Instant};
+
+/// instructions executed by each iteration
+const CYCLES: usize = 1000;
+
+/// the bytes of a rom made of these opcodes
+fn rom(opcodes: &[u16]) -> Vec<u8> {
+    opcodes.iter().flat_map(|op| op.to_be_bytes()).collect()
+}
+
+/// run a rom which loops forever
+fn bench_rom(c: &mut Criterion, name: &str, mut cpu: Cpu, opcodes: &[u16]) {
+    cpu.load_rom(&rom(opcodes));
+    cpu.set_seed(0);
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for _ in 0..CYCLES {
+                cpu.cycle().unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+fn alu(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let rom = [
+        0x6005, 0x6107, 0x8014, 0x8115, 0x8016, 0x801E, 0x8012, 0x8021,
+        0x8033, 0x8107, 0x7003, 0x1200,
+    ];
+    bench_rom(c, "alu", Cpu::new(), &rom);
+}
+
+fn skips(c: &mut Criterion) {
+    // taken and not taken, the skipped instructions are never executed
+    #[rustfmt::skip]
+    let rom = [
+        0x6001, 0x3001, 0x6000, 0x4001, 0x5010, 0x9010, 0x6000, 0x1200,
+    ];
+    bench_rom(c, "skips", Cpu::new(), &rom);
+}
+
+fn memory(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let rom = [
+        0xA300, 0x6012, 0xF033, 0xF255, 0xF265, 0xF01E, 0xF029, 0x1200,
+    ];
+    bench_rom(c, "memory", Cpu::new(), &rom);
+}
+
+fn calls(c: &mut Criterion) {
+    #[rustfmt::skip]
+    let rom = [
+        0x2206, // call 0x206
+        0xB208, // jump to 0x208 + V0
+        0x0000,
+        0x00EE,
+        0x1200,
+    ];
+    bench_rom(c, "calls", Cpu::new(), &rom);
+}
+
+fn timers(c: &mut Criterion) {
+    bench_rom(
+        c,
+        "timers",
+        Cpu::new(),
+        &[0x6010, 0xF015, 0xF018, 0xF107, 0x1200],
+    );
+}
+
+fn random(c: &mut Criterion) {
+    bench_rom(c, "random", Cpu::new(), &[0xC0FF, 0xC10F, 0x1200]);
+}
+
+fn keys(c: &mut Criterion) {
+    let mut cpu = Cpu::new();
+    cpu.key[0] = true;
+    bench_rom(c, "keys", cpu, &[0xE09E, 0xE1A1, 0x6000, 0x1200]);
+}
+
+/// a 15 lines sprite of the font moving across the screen, wrapping
+/// around the edges
+const SPRITES: [u16; 5] = [0xA000, 0xD01F, 0x7003, 0x7105, 0x1200];
+
+fn sprites(c: &mut Criterion) {
+    bench_rom(c, "sprites", Cpu::new(), &SPRITES);
+}
+
+fn sprites_clipped(c: &mut Criterion) {
+    let mut cpu = Cpu::new();
+    let mut quirks = cpu.quirks();
+    quirks.clip = true;
+    cpu.set_quirks(quirks);
+    bench_rom(c, "sprites_clipped", cpu, &SPRITES);
+}
+
+/// clear the screen then draw a row of sprites, like a game drawing a frame
+#[rustfmt::skip]
+const FRAME: [u16; 14] = [
+    0x00E0, 0xA000, 0x6000, 0x6108,
+    0xD01F, 0x7008, 0xD01F, 0x7008, 0xD01F, 0x7008, 0xD01F, 0x7008, 0xD01F,
+    0x1200,
+];
+
+fn frame(c: &mut Criterion) {
+    bench_rom(c, "frame", Cpu::new(), &FRAME);
+}
+
+/// a frame drawn then converted for the screen like the frontends do, only
+/// on the lines which changed
+fn present(c: &mut Criterion) {
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&rom(&FRAME));
+    let mut output = vec![0; cpu.width() * cpu.height()];
+    let color = |index| match index {
+        0 => Color::from_argb(0xFF00_0000),
+        _ => Color::from_argb(0xFFFF_FFFF),
+    };
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(FRAME.len() as u64 - 1));
+    group.bench_function("present", |b| {
+        b.iter(|| {
+            for _ in 1..FRAME.len() {
+                cpu.cycle().unwrap();
+            }
+            if let Some(screen) = cpu.update() {
+                for lines in screen.changed_lines() {
+                    screen.lines_to_argb(lines, color, &mut output);
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Run a game with the keys pressed in turn, it's reset when it crashes.
+/// The keys change every 1000 instructions so the games go through their
+/// menus and play a bit.
+/// Only the runs which execute their 1000 instructions are timed, the ones
+/// ending on a fault and the reset are left out of the measure.
+fn bench_game(c: &mut Criterion, game: &str) {
+    let rom = std::fs::read(format!("../games/{}", game)).unwrap();
+    let mut group = c.benchmark_group("games");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    for threaded in [false, true] {
+        let name = match threaded {
+            true => format!("{}_threaded", game),
+            false => game.to_string(),
+        };
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&rom);
+        cpu.set_seed(0);
+        let mut backend = Threaded::new();
+        let mut key = 0;
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                let (mut measured, mut faults) = (0, 0);
+                while measured < iters {
+                    key = (key + 5) % 16;
+                    cpu.key = [false; 16];
+                    cpu.key[key] = true;
+                    let start = Instant::now();
+                    let result = match threaded {
+                        true => backend.run(&mut cpu, CYCLES),
+                        false => (0..CYCLES).try_for_each(|_| cpu.cycle()),
+                    };
+                    let time = start.elapsed();
+                    match result {
+                        Ok(()) => {
+                            elapsed += time;
+                            measured += 1;
+                            faults = 0;
+                        }
+                        Err(fault) => {
+                            // with every key, in case one of them crashes the game
+                            faults += 1;
+                            assert!(faults <= 16, "{} keeps crashing: {}", game, fault);
+                            cpu.reset();
+                        }
+                    }
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+macro_rules! games {
+    ($($name:ident: $game:expr),* $(,)?) => {
+        $(
+            fn $name(c: &mut Criterion) {
+                bench_game(c, $game);
+            }
+        )*
+
+        criterion_group!(games, $($name),*);
+    };
+}
+
+games! {
+    game_15puzzle: "15PUZZLE",
+    game_blinky: "BLINKY",
+    game_blitz: "BLITZ",
+    game_brix: "BRIX",
+    game_connect4: "CONNECT4",
+    game_guess: "GUESS",
+    game_hidden: "HIDDEN",
+    game_invaders: "INVADERS",
+    game_kaleid: "KALEID",
+    game_maze: "MAZE",
+    game_merlin: "MERLIN",
+    game_missile: "MISSILE",
+    game_pong: "PONG",
+    game_pong2: "PONG2",
+    game_puzzle: "PUZZLE",
+    game_syzygy: "SYZYGY",
+    game_tank: "TANK",
+    game_tetris: "TETRIS",
+    game_tictac: "TICTAC",
+    game_ufo: "UFO",
+    game_vbrix: "VBRIX",
+    game_vers: "VERS",
+    game_wipeoff: "WIPEOFF",
+}
+
+criterion_group!(
+    instructions,
+    alu,
+    skips,
+    memory,
+    calls,
+    timers,
+    random,
+    keys,
+    sprites,
+    sprites_clipped,
+    frame,
+    present
+);
+criterion_main!(instructions, games);
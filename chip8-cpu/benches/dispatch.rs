@@ -0,0 +1,129 @@
+//! The ways to run and to decode the instructions, compared on the same
+//! loop. Run with `cargo bench -p chip8-cpu --bench dispatch`.
+
+use bitmatch::bitmatch;
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::decode::{self, Instruction, Instruction::*};
+use chip8_cpu::threaded::Threaded;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+/// a loop going through most of the instructions which don't draw or wait
+const ROM: [u8; 32] = [
+    0x60, 0x05, // V0 = 5
+    0x71, 0x01, // V1 += 1
+    0x82, 0x14, // V2 += V1
+    0x83, 0x22, // V3 &= V2
+    0x84, 0x36, // V4 = V3 >> 1
+    0x30, 0x07, // skip if V0 == 7
+    0x45, 0x00, // skip if V5 != 0
+    0xA3, 0x00, // I = 0x300
+    0xF0, 0x1E, // I += V0
+    0xF1, 0x15, // delay = V1
+    0xF6, 0x07, // V6 = delay
+    0x58, 0x90, // skip if V8 == V9
+    0x00, 0x00, // not reached
+    0x8A, 0xB5, // VA -= VB
+    0xC7, 0xFF, // V7 = random
+    0x12, 0x00, // jump to the start
+];
+
+/// instructions executed or decoded by each iteration
+const CYCLES: usize = 1000;
+
+fn run(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    group.bench_function("cycle", |b| {
+        b.iter(|| {
+            for _ in 0..CYCLES {
+                cpu.cycle().unwrap();
+            }
+        })
+    });
+
+    let mut cpu = Cpu::new();
+    cpu.load_rom(&ROM);
+    let mut backend = Threaded::new();
+    group.bench_function("threaded", |b| {
+        b.iter(|| backend.run(&mut cpu, CYCLES).unwrap())
+    });
+    group.finish();
+}
+
+/// how the cpu matched the opcodes before the table, to compare with
+#[bitmatch]
+fn bitmatch(opcode: u16) -> Instruction {
+    #[bitmatch]
+    match opcode {
+        "0000_0000_1110_1110" => Op00EE,
+        "0000_0000_1110_0000" => Op00E0,
+        "0000_nnnn_nnnn_nnnn" => Op0NNN(n),
+        "0001_nnnn_nnnn_nnnn" => Op1NNN(n),
+        "0010_nnnn_nnnn_nnnn" => Op2NNN(n),
+        "0011_xxxx_nnnn_nnnn" => Op3XNN(x as u8, n as u8),
+        "0100_xxxx_nnnn_nnnn" => Op4XNN(x as u8, n as u8),
+        "0101_xxxx_yyyy_0000" => Op5XY0(x as u8, y as u8),
+        "0110_xxxx_nnnn_nnnn" => Op6XNN(x as u8, n as u8),
+        "0111_xxxx_nnnn_nnnn" => Op7XNN(x as u8, n as u8),
+        "1000_xxxx_yyyy_0000" => Op8XY0(x as u8, y as u8),
+        "1000_xxxx_yyyy_0001" => Op8XY1(x as u8, y as u8),
+        "1000_xxxx_yyyy_0010" => Op8XY2(x as u8, y as u8),
+        "1000_xxxx_yyyy_0011" => Op8XY3(x as u8, y as u8),
+        "1000_xxxx_yyyy_0100" => Op8XY4(x as u8, y as u8),
+        "1000_xxxx_yyyy_0101" => Op8XY5(x as u8, y as u8),
+        "1000_xxxx_yyyy_0110" => Op8XY6(x as u8, y as u8),
+        "1000_xxxx_yyyy_0111" => Op8XY7(x as u8, y as u8),
+        "1000_xxxx_yyyy_1110" => Op8XYE(x as u8, y as u8),
+        "1001_xxxx_yyyy_0000" => Op9XY0(x as u8, y as u8),
+        "1010_nnnn_nnnn_nnnn" => OpANNN(n),
+        "1011_nnnn_nnnn_nnnn" => OpBNNN(n),
+        "1100_xxxx_nnnn_nnnn" => OpCXNN(x as u8, n as u8),
+        "1101_xxxx_yyyy_nnnn" => OpDXYN(x as u8, y as u8, n as u8),
+        "1110_xxxx_1001_1110" => OpEX9E(x as u8),
+        "1110_xxxx_1010_0001" => OpEXA1(x as u8),
+        "1111_xxxx_0000_0111" => OpFX07(x as u8),
+        "1111_xxxx_0000_1010" => OpFX0A(x as u8),
+        "1111_xxxx_0001_0101" => OpFX15(x as u8),
+        "1111_xxxx_0001_1000" => OpFX18(x as u8),
+        "1111_xxxx_0001_1110" => OpFX1E(x as u8),
+        "1111_xxxx_0010_1001" => OpFX29(x as u8),
+        "1111_xxxx_0011_0011" => OpFX33(x as u8),
+        "1111_xxxx_0101_0101" => OpFX55(x as u8),
+        "1111_xxxx_0110_0101" => OpFX65(x as u8),
+        "aaaa_aaaa_aaaa_aaaa" => Unknown(a),
+    }
+}
+
+/// decode the opcodes of the rom, like a game spends its time doing
+fn opcodes() -> Vec<u16> {
+    ROM.chunks(2)
+        .map(|op| (op[0] as u16) << 8 | op[1] as u16)
+        .cycle()
+        .take(CYCLES)
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let opcodes = opcodes();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    let mut bench = |name, decode: fn(u16) -> Instruction| {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for opcode in &opcodes {
+                    black_box(decode(black_box(*opcode)));
+                }
+            })
+        });
+    };
+    bench("bitmatch", bitmatch);
+    bench("match", decode::decode);
+    #[cfg(feature = "decode-table")]
+    bench("table", decode::lookup);
+    group.finish();
+}
+
+criterion_group!(dispatch, run, decode);
+criterion_main!(dispatch);
@@ -0,0 +1,404 @@
+use crate::cpu::{Cpu, Fault};
+use crate::gdb::Gdb;
+use crate::machine::Machine;
+use crate::threaded::Threaded;
+use std::time::{Duration, Instant};
+
+/// how much slower the emulation run in slow motion
+const SLOW_MOTION: u32 = 4;
+/// a frame advance stop after this number of cycles if nothing was drawn
+const MAX_FRAME_CYCLES: usize = 10_000;
+/// the duration of a frame in the VIP timing
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// the instructions run at once by the threaded backend in turbo
+const TURBO_CYCLES: usize = 256;
+
+/// The state of the emulation controlled by the user with the hotkeys:
+/// pause, frame advance, reset, turbo and slow motion.
+/// Every frontend own one and use it to run the cpu.
+#[derive(Debug, Default)]
+pub struct Control {
+    paused: bool,
+    advance: usize, // cycles left to run in a frame advance
+    turbo: bool,
+    slow_motion: bool,
+    reset: bool,
+    gdb: Option<Gdb>, // when gdb is attached it decide when the cpu run
+    breakpoints: Vec<usize>,
+    breakpoint: Option<usize>,   // the breakpoint we're paused on
+    fault: Option<Fault>,        // the fault which paused the emulation
+    last_frame: Option<Instant>, // when the current frame started in the VIP timing
+    messages: Vec<String>,       // for the user, see `Control::take_messages`
+    threaded: Threaded,          // runs the turbo
+}
+
+impl Control {
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = 0;
+    }
+
+    /// pause the emulation and run it until the next frame is drawn
+    pub fn frame_advance(&mut self) {
+        self.paused = true;
+        self.advance = MAX_FRAME_CYCLES;
+    }
+
+    /// reset the cpu before the next cycle
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    /// the turbo is active as long as its key is held
+    pub fn set_turbo(&mut self, turbo: bool) {
+        self.turbo = turbo;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    /// let gdb control the cpu, see `Gdb::listen`. Only a chip 8 can be
+    /// debugged, see `Machine::cpu`
+    pub fn set_gdb(&mut self, gdb: Gdb) {
+        self.gdb = Some(gdb);
+    }
+
+    /// pause the emulation before executing the instructions at these
+    /// addresses, see `Symbols::resolve`
+    pub fn set_breakpoints(&mut self, breakpoints: Vec<usize>) {
+        self.breakpoints = breakpoints;
+    }
+
+    /// the emulation is paused by the user or stopped by gdb
+    pub fn paused(&self) -> bool {
+        let gdb = self.gdb.as_ref().is_some_and(|gdb| gdb.stopped());
+        (self.paused && self.advance == 0) || gdb
+    }
+
+    pub fn turbo(&self) -> bool {
+        self.turbo
+    }
+
+    /// scale a duration used to throttle the emulation with the current speed.
+    /// In turbo we don't wait at all.
+    pub fn throttle(&self, duration: Duration) -> Duration {
+        match (self.turbo, self.slow_motion) {
+            (true, _) => Duration::from_secs(0),
+            (false, true) => duration * SLOW_MOTION,
+            (false, false) => duration,
+        }
+    }
+
+    /// Execute one cycle of the cpu unless the emulation is paused, in
+    /// turbo a lot of them with the threaded backend.
+    /// In the VIP timing start a new frame every 1/60s and run everything
+    /// the cpu has the time to run in the frame.
+    pub fn run<M: Machine + ?Sized>(&mut self, chip: &mut M) {
+        if self.reset {
+            self.reset = false;
+            self.fault = None;
+            chip.reset();
+        }
+        if let (Some(gdb), Some(cpu)) = (&mut self.gdb, chip.cpu()) {
+            match gdb.poll(cpu) {
+                Ok(true) => (),
+                Ok(false) => {
+                    self.messages.push("gdb detached".to_string());
+                    self.gdb = None;
+                }
+                Err(e) => {
+                    let message = format!("Lost the connection with gdb : {}", e);
+                    self.messages.push(message);
+                    self.gdb = None;
+                }
+            }
+        }
+        if self.paused() {
+            return;
+        }
+        if chip.frame_budget().is_none() {
+            return match self.turbo {
+                true => self.run_threaded(chip),
+                false => self.step(chip),
+            };
+        }
+
+        let frame = self.throttle(FRAME);
+        if self.last_frame.is_none_or(|start| start.elapsed() >= frame) {
+            self.last_frame = Some(Instant::now());
+            chip.vblank();
+        }
+        while chip.frame_budget() > Some(0) && !self.paused() {
+            self.step(chip);
+        }
+    }
+
+    /// execute one instruction, or let gdb do it
+    fn step<M: Machine + ?Sized>(&mut self, chip: &mut M) {
+        let pc = chip.pc();
+        if self.breakpoints.contains(&pc) && self.breakpoint != Some(pc) {
+            self.paused = true;
+            self.advance = 0;
+            self.breakpoint = Some(pc);
+            let message = format!("Breakpoint\n{}", chip.stack_trace());
+            self.messages.push(message);
+            return;
+        }
+        self.breakpoint = None;
+
+        match (&mut self.gdb, chip.cpu()) {
+            (Some(gdb), Some(cpu)) => match gdb.cycle(cpu) {
+                Ok(None) => (),
+                Ok(Some(fault)) => {
+                    let message = format!("{}\n{}", fault, cpu.stack_trace());
+                    self.messages.push(message);
+                }
+                Err(e) => {
+                    let message = format!("Lost the connection with gdb : {}", e);
+                    self.messages.push(message);
+                    self.gdb = None;
+                }
+            },
+            _ => {
+                if let Err(fault) = chip.cycle() {
+                    return self.crashed(fault, chip.stack_trace());
+                }
+                self.fault = None;
+            }
+        }
+        self.check_trace(chip);
+        if self.advance > 0 {
+            self.advance -= 1;
+            if chip.drawing() {
+                self.advance = 0;
+            }
+        }
+    }
+
+    /// run `TURBO_CYCLES` instructions at once, unless gdb, a breakpoint or
+    /// a frame advance needs to look at each of them
+    fn run_threaded<M: Machine + ?Sized>(&mut self, chip: &mut M) {
+        let batch = self.gdb.is_none() && self.breakpoints.is_empty() && self.advance == 0;
+        let cpu = match chip.cpu() {
+            Some(cpu) if batch => cpu,
+            _ => return self.step(chip),
+        };
+        if let Err(fault) = self.threaded.run(cpu, TURBO_CYCLES) {
+            return self.crashed(fault, cpu.stack_trace());
+        }
+        self.fault = None;
+        self.check_trace(chip);
+    }
+
+    /// the game is paused so the user can look at what happened or reset it
+    fn crashed(&mut self, fault: Fault, stack_trace: String) {
+        self.messages.push(format!("{}\n{}", fault, stack_trace));
+        self.paused = true;
+        self.advance = 0;
+        self.fault = Some(fault);
+    }
+
+    fn check_trace<M: Machine + ?Sized>(&mut self, chip: &mut M) {
+        if let Some(e) = chip.cpu().and_then(Cpu::take_trace_error) {
+            let message = format!("Can't write the trace, stop tracing : {}", e);
+            self.messages.push(message);
+        }
+    }
+
+    /// What happened since the last call that the user should know: the
+    /// faults and breakpoints with their stack trace, gdb leaving and the
+    /// trace which couldn't be written. It's up to the frontend to display
+    /// them.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// a short description of the state of the emulation to display in the
+    /// title bar, `None` when the emulation run normally
+    pub fn status(&self) -> Option<&'static str> {
+        if self.gdb.as_ref().is_some_and(|gdb| gdb.stopped()) {
+            return Some("stopped by gdb");
+        }
+        match (self.paused, self.turbo, self.slow_motion) {
+            (true, _, _) if self.fault.is_some() => Some("crashed"),
+            (true, _, _) if self.breakpoint.is_some() => Some("breakpoint"),
+            (true, _, _) => Some("paused"),
+            (false, true, _) => Some("turbo"),
+            (false, false, true) => Some("slow motion"),
+            (false, false, false) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() -> Cpu {
+        let mut chip = Cpu::new();
+        chip.load_game("../games/PONG").unwrap();
+        chip.update();
+        chip
+    }
+
+    #[test]
+    fn pause() {
+        let mut control = Control::default();
+        let mut chip = init();
+        control.toggle_pause();
+        assert_eq!(control.status(), Some("paused"));
+        for _ in 0..100 {
+            control.run(&mut chip);
+        }
+        assert!(!chip.drawing());
+
+        control.toggle_pause();
+        assert_eq!(control.status(), None);
+        for _ in 0..100 {
+            control.run(&mut chip);
+        }
+        assert!(chip.drawing());
+    }
+
+    #[test]
+    fn frame_advance() {
+        let mut control = Control::default();
+        let mut chip = init();
+        control.frame_advance();
+        while !control.paused() {
+            control.run(&mut chip);
+        }
+        assert!(chip.drawing());
+        assert_eq!(control.status(), Some("paused"));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut control = Control::default();
+        let mut chip = init();
+        control.set_breakpoints(vec![0x204, 0x2D4]);
+        while !control.paused() {
+            control.run(&mut chip);
+        }
+        assert_eq!(chip.pc(), 0x204);
+        assert_eq!(control.status(), Some("breakpoint"));
+        let messages = control.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Breakpoint\n"));
+        assert!(control.take_messages().is_empty());
+
+        // resuming doesn't stop again on the same breakpoint
+        control.toggle_pause();
+        while !control.paused() {
+            control.run(&mut chip);
+        }
+        assert_eq!(chip.pc(), 0x2D4);
+        assert_eq!(chip.stack(), &[0x210]);
+    }
+
+    #[test]
+    fn fault() {
+        let mut control = Control::default();
+        let mut chip = init();
+        chip.write_memory(0x200, &[0x00, 0xEE]).unwrap();
+        control.run(&mut chip);
+        assert!(control.paused());
+        assert_eq!(control.status(), Some("crashed"));
+        assert_eq!(chip.pc(), 0x200);
+        let messages = control.take_messages();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with(&Fault::StackUnderflow.to_string()));
+
+        control.reset();
+        control.toggle_pause();
+        control.run(&mut chip);
+        assert_eq!(control.status(), None);
+        assert_eq!(chip.pc(), 0x202);
+    }
+
+    /// a writer which always fails, like a full disk
+    struct Full;
+
+    impl std::io::Write for Full {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_error() {
+        use crate::trace::{Format, Tracer};
+        let mut control = Control::default();
+        let mut chip = init();
+        let tracer = Tracer::new(Box::new(Full), Format::Text, Default::default());
+        chip.set_tracer(Some(tracer.unwrap()));
+        control.run(&mut chip);
+        control.run(&mut chip);
+        assert_eq!(
+            control.take_messages(),
+            ["Can't write the trace, stop tracing : disk full"]
+        );
+        assert_eq!(control.status(), None);
+    }
+
+    #[test]
+    fn vip_timing() {
+        let mut control = Control::default();
+        let mut chip = init();
+        chip.set_timing(crate::timing::Timing::Vip);
+        // in turbo every call is a new frame
+        control.set_turbo(true);
+        control.run(&mut chip);
+        assert_eq!(chip.frame_budget(), Some(0));
+        let pc = chip.pc();
+        assert!(pc > 0x204);
+
+        control.set_turbo(false);
+        control.run(&mut chip);
+        control.run(&mut chip);
+        assert_eq!(chip.pc(), pc);
+    }
+
+    #[test]
+    fn turbo() {
+        let mut control = Control::default();
+        let (mut chip, mut interpreted) = (init(), init());
+        chip.set_seed(1);
+        interpreted.set_seed(1);
+        control.set_turbo(true);
+        control.run(&mut chip);
+        for _ in 0..TURBO_CYCLES {
+            interpreted.cycle().unwrap();
+        }
+        assert_eq!(chip.pc(), interpreted.pc());
+        assert_eq!(chip.registers(), interpreted.registers());
+        assert_eq!(chip.index(), interpreted.index());
+
+        // a breakpoint is checked on every instruction
+        control.set_breakpoints(vec![0x202]);
+        control.reset();
+        control.run(&mut chip);
+        control.run(&mut chip);
+        assert_eq!(control.status(), Some("breakpoint"));
+        assert_eq!(chip.pc(), 0x202);
+    }
+
+    #[test]
+    fn speed() {
+        let mut control = Control::default();
+        let frame = Duration::from_millis(16);
+        assert_eq!(control.throttle(frame), frame);
+        control.toggle_slow_motion();
+        assert_eq!(control.throttle(frame), frame * 4);
+        control.set_turbo(true);
+        assert_eq!(control.status(), Some("turbo"));
+        assert_eq!(control.throttle(frame), Duration::from_secs(0));
+        control.set_turbo(false);
+        assert_eq!(control.status(), Some("slow motion"));
+    }
+}
@@ -1,6 +1,94 @@
+use crate::decode::{self, Instruction};
+use crate::framebuffer::Framebuffer;
+use crate::inputs::Inputs;
+use crate::machine::{MachineConfig, Platform};
+use crate::megachip::MegaChip;
 use crate::memory;
+use crate::quirks::Quirks;
+use crate::rca1802::Rca1802;
+#[cfg(feature = "std")]
+use crate::symbols::Symbols;
+use crate::timing::{self, Timing};
+#[cfg(feature = "std")]
+use crate::trace::{Entry, Tracer};
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
 use bitmatch::bitmatch;
-use rand::Rng;
+use core::fmt;
+use core::ops::Range;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// size of the screen in pixels, some platforms have a bigger one
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+/// the colors of the background of the CHIP-8X, cycled by 02A0: blue,
+/// black, green and red
+const CHIP8X_BACKGROUNDS: [u8; 4] = [2, 0, 4, 1];
+/// the color of the blocks before they are colored, red
+const CHIP8X_FOREGROUND: u8 = 1;
+
+// where the interpreter of the VIP keeps its state, from the end of the memory
+const VIP_STACK: usize = 0x131; // the stack of the interpreter, growing down
+const VIP_REGISTERS: usize = 0x110; // V0 to VF
+const VIP_DISPLAY: usize = 0x100; // one bit per pixel, line by line
+
+/// the machine cycles a machine code subroutine can run before it's
+/// considered stuck, a second of the VIP
+const MACHINE_CODE_LIMIT: u32 = 60 * timing::VIP_FRAME_CYCLES as u32;
+
+/// the random numbers of CXNN are seeded by the OS
+#[cfg(feature = "std")]
+fn rng() -> StdRng {
+    StdRng::from_entropy()
+}
+
+/// without an OS to seed them the random numbers are the same every time
+/// unless `Cpu::set_seed` is called
+#[cfg(not(feature = "std"))]
+fn rng() -> StdRng {
+    StdRng::seed_from_u64(0)
+}
+
+/// The errors which stop the cpu, the instruction at PC is not executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// a 2NNN with the 16 levels of the stack already used
+    StackOverflow,
+    /// a 00EE with nothing on the stack
+    StackUnderflow,
+    UnknownInstruction(u16),
+    /// a 0NNN whose machine code didn't return to the interpreter, the
+    /// memory it changed is not restored
+    MachineCode(u16),
+    /// a read or write at this address, outside of the memory
+    Memory(usize),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StackOverflow => write!(f, "Stack overflow"),
+            Fault::StackUnderflow => write!(f, "Stack underflow"),
+            Fault::UnknownInstruction(opcode) => write!(f, "Unknown instruction {:04X}", opcode),
+            Fault::MachineCode(address) => write!(
+                f,
+                "The machine code subroutine at {:03X} didn't return",
+                address
+            ),
+            Fault::Memory(address) => write!(f, "Access outside of the memory at {:03X}", address),
+        }
+    }
+}
+
+/// A call in the shadow call stack, see `Cpu::calls`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// the address of the 2NNN
+    pub call_site: usize,
+    /// the subroutine called
+    pub target: usize,
+}
 
 pub struct Cpu {
     opcode: u16,            // all the instruction are on two bytes
@@ -8,129 +96,673 @@ pub struct Cpu {
     V: [u8; 16],            // 15 register + one carry flag
     I: u16,                 // index register
     pc: usize,              // program counter
-    stack: [usize; 16],
-    sp: usize, // stack pointer
+    stack: Vec<usize>,      // as deep as the machine allows
+    sp: usize,              // stack pointer
+    calls: Vec<Frame>,      // the calls in the stack with their target
 
     delay_timer: u8, // timers -> goto zero
     sound_timer: u8, // when zero buzzer is triggered
 
-    pub key: [bool; 16],     // which key are pressed
-    screen: [bool; 64 * 32], // pixel array
+    pub key: [bool; 16],    // which key are pressed
+    inputs: Option<Inputs>, // replayed instead of the keys of the frontend
+    screen: Framebuffer,    // of the size given by the platform
 
     draw: bool, // indicate if we should draw the screen
+
+    #[cfg(feature = "std")]
+    tracer: Option<Tracer>, // log the executed instructions
+    #[cfg(feature = "std")]
+    trace_error: Option<std::io::Error>, // why the tracer was dropped
+    quirks: Quirks,
+    rng: StdRng, // used by CXNN, can be seeded to replay a game
+    #[cfg(feature = "std")]
+    symbols: Symbols, // the labels and sources of the game
+    config: MachineConfig,
+    rca: Rca1802, // runs the 0NNN when the machine allows it
+
+    timing: Timing,
+    budget: i32,         // cycles left in the frame in the VIP timing, can be negative
+    synced: bool,        // nothing was executed since the last vblank
+    machine_cycles: i32, // taken by the last machine code subroutine
+
+    background: u8,              // color of the background on the CHIP-8X
+    colors: Vec<u8>,             // color of each 8x1 block of pixels on the CHIP-8X
+    tone: u8,                    // frequency sent to the speaker by the CHIP-8X
+    waiting: bool,               // the CHIP-8E FX4F is waiting for the delay timer
+    mega: Option<Box<MegaChip>>, // only on the MegaChip-8
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
 }
 
 impl Cpu {
     pub fn new() -> Self {
+        Cpu::build(MachineConfig::default())
+    }
+
+    /// a cpu for another machine than the default one, see `MachineConfig`
+    pub fn with_config(config: MachineConfig) -> Result<Self, String> {
+        config.validate()?;
+        Ok(Cpu::build(config))
+    }
+
+    /// the config must be valid
+    fn build(config: MachineConfig) -> Self {
+        let (width, height) = config.platform.screen_size();
         Cpu {
             opcode: 0,
-            memory: memory::Memory::new(),
+            memory: memory::Memory::new(&config),
             V: [0; 16],
             I: 0,
-            pc: 0x200,
-            stack: [0; 16],
+            pc: config.start_address,
+            stack: vec![0; config.stack_depth],
             sp: 0,
+            calls: Vec::new(),
 
             delay_timer: 0,
             sound_timer: 0,
 
             key: [false; 16],
-            screen: [false; 64 * 32],
+            inputs: None,
+            screen: Framebuffer::new(width, height, 1),
 
             draw: true,
+
+            #[cfg(feature = "std")]
+            tracer: None,
+            #[cfg(feature = "std")]
+            trace_error: None,
+            quirks: Quirks::default(),
+            rng: rng(),
+            #[cfg(feature = "std")]
+            symbols: Symbols::default(),
+            config,
+            rca: Rca1802::default(),
+
+            timing: Timing::default(),
+            budget: 0,
+            synced: false,
+            machine_cycles: 0,
+
+            background: 0,
+            colors: vec![CHIP8X_FOREGROUND; width / 8 * height],
+            tone: 0,
+            waiting: false,
+            mega: match config.platform {
+                Platform::MegaChip => Some(Box::default()),
+                _ => None,
+            },
         }
     }
 
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    #[cfg(feature = "std")]
     pub fn load_game(&mut self, file: &str) -> std::io::Result<()> {
         self.memory.load_game(file)
     }
 
-    pub fn cycle(&mut self) {
-        self.handle_opcode();
+    /// load a game from its bytes instead of a file
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        self.memory.load_rom(rom)
+    }
+
+    /// Reinitialize all the registers and reload the game as if the chip
+    /// was just turned on, the tracer, the quirks, the random generator,
+    /// the symbols, the config of the machine and the timing are kept, and
+    /// the inputs are replayed from the start
+    pub fn reset(&mut self) {
+        self.memory.reset();
+        let memory = core::mem::replace(&mut self.memory, memory::Memory::new(&self.config));
+        let rng = core::mem::replace(&mut self.rng, StdRng::seed_from_u64(0));
+        *self = Cpu {
+            memory,
+            #[cfg(feature = "std")]
+            tracer: self.tracer.take(),
+            inputs: self.inputs.take().map(Inputs::rewind),
+            quirks: self.quirks,
+            rng,
+            #[cfg(feature = "std")]
+            symbols: core::mem::take(&mut self.symbols),
+            timing: self.timing,
+            ..Cpu::build(self.config)
+        };
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// make the random numbers reproducible, two cpus with the same seed
+    /// and the same inputs run a game exactly the same way
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// press the keys of the inputs instead of `Cpu::key` from now on, the
+    /// keys the frontend sets are overwritten before every instruction
+    pub fn set_inputs(&mut self, inputs: Option<Inputs>) {
+        self.inputs = inputs;
+    }
+
+    /// log every instruction executed from now on, `None` stop the logging
+    #[cfg(feature = "std")]
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// the error which made the cpu stop tracing, once
+    #[cfg(feature = "std")]
+    pub fn take_trace_error(&mut self) -> Option<std::io::Error> {
+        self.trace_error.take()
+    }
+
+    /// name the addresses in the traces and the stack traces
+    #[cfg(feature = "std")]
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// the calls in the stack, from the first one to the last one
+    pub fn calls(&self) -> &[Frame] {
+        &self.calls
+    }
+
+    /// Describe where the cpu is and the calls which led it here, with the
+    /// symbols when there are some:
+    /// ```text
+    ///     at 0x2D6 draw+0x2 (pong.8o:12)
+    ///     called from 0x210 main+0x10 (pong.8o:5)
+    /// ```
+    pub fn stack_trace(&self) -> String {
+        let mut trace = format!("    at {}", self.describe(self.pc));
+        for frame in self.calls.iter().rev() {
+            let call_site = self.describe(frame.call_site);
+            trace += &format!("\n    called from {}", call_site);
+        }
+        trace
+    }
+
+    #[cfg(feature = "std")]
+    fn describe(&self, address: usize) -> String {
+        self.symbols.describe(address)
+    }
+
+    /// there are no symbols without std
+    #[cfg(not(feature = "std"))]
+    fn describe(&self, address: usize) -> String {
+        format!("{:#05X}", address)
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// in the VIP timing the cpu waits for the next `Cpu::vblank` before
+    /// running anything
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.budget = 0;
+        self.synced = false;
+    }
+
+    /// the cycles left in the current frame in the VIP timing, `None` when
+    /// the instructions aren't timed
+    pub fn frame_budget(&self) -> Option<u32> {
+        match self.timing {
+            Timing::Instruction => None,
+            Timing::Vip => Some(self.budget.max(0) as u32),
+        }
+    }
 
+    /// Start a new frame in the VIP timing: the timers tick and the cpu get
+    /// the cycles of a frame, minus what the last instruction of the
+    /// previous frame took in advance. Do nothing in the instruction timing.
+    pub fn vblank(&mut self) {
+        if self.timing == Timing::Instruction {
+            return;
+        }
         self.delay_timer = self.delay_timer.saturating_sub(1);
         self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.budget = self.budget.min(0) + timing::VIP_BUDGET;
+        self.synced = true;
+    }
+
+    /// Execute the instruction at PC, on a fault the cpu is left as it was
+    /// before the instruction, see `Cpu::stack_trace`.
+    /// In the VIP timing nothing is executed once the budget of the frame
+    /// is spent.
+    pub fn cycle(&mut self) -> Result<(), Fault> {
+        let opcode = self.opcode_at(self.pc);
+        if self.timing == Timing::Vip {
+            // the interpreter waits for the display interrupt before drawing
+            if opcode >> 12 == 0xD && !self.synced {
+                self.budget = 0;
+            }
+            if self.budget <= 0 {
+                return Ok(());
+            }
+        }
+
+        let (pc, vx) = (self.pc, self.V[(opcode >> 8 & 0xF) as usize]);
+        #[cfg(feature = "std")]
+        let before = self.tracer.as_ref().map(|_| (self.pc, self.V, self.I));
+        if let Some(inputs) = &mut self.inputs {
+            inputs.apply(&mut self.key);
+        }
+        self.handle_opcode()?;
+        if let Some(inputs) = &mut self.inputs {
+            inputs.executed();
+        }
+        #[cfg(feature = "std")]
+        if let Some((pc, V, I)) = before {
+            self.trace(pc, V, I);
+        }
+
+        match self.timing {
+            Timing::Instruction => {
+                self.delay_timer = self.delay_timer.saturating_sub(1);
+                self.sound_timer = self.sound_timer.saturating_sub(1);
+            }
+            Timing::Vip => {
+                let skipped = self.pc == pc + 4;
+                self.budget -= timing::vip_cycles(opcode, skipped, vx);
+                self.budget -= core::mem::take(&mut self.machine_cycles);
+                self.synced = false;
+            }
+        }
+        Ok(())
     }
 
     pub fn sound(&self) -> bool {
         self.sound_timer == 1
     }
 
-    pub fn update(&mut self) -> Option<&[bool]> {
+    /// return true if the screen changed since the last call to `update`
+    pub fn drawing(&self) -> bool {
+        self.draw
+    }
+
+    /// the registers V0 to VF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.V
+    }
+
+    /// the index register I
+    pub fn index(&self) -> u16 {
+        self.I
+    }
+
+    /// the program counter
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// the return addresses of the subroutines currently called, the last
+    /// one is the top of the stack
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    /// the delay and sound timers
+    pub fn timers(&self) -> (u8, u8) {
+        (self.delay_timer, self.sound_timer)
+    }
+
+    /// the whole 4ko of memory
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    /// the pixels of the screen
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.screen
+    }
+
+    /// the size of the framebuffer
+    pub fn width(&self) -> usize {
+        self.screen.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.screen.height()
+    }
+
+    /// On the CHIP-8X the background color and the foreground color of each
+    /// block of 8x1 pixels, line by line. The colors are the ones of the
+    /// color board: black, red, blue, violet, green, yellow, aqua and white.
+    pub fn colors(&self) -> Option<(u8, &[u8])> {
+        match self.config.platform {
+            Platform::Chip8X => Some((CHIP8X_BACKGROUNDS[self.background as usize], &self.colors)),
+            _ => None,
+        }
+    }
+
+    /// the frequency of the speaker set by the CHIP-8X
+    pub fn tone(&self) -> u8 {
+        self.tone
+    }
+
+    /// the state of the MegaChip-8, `None` on the other platforms
+    pub fn mega(&self) -> Option<&MegaChip> {
+        self.mega.as_deref()
+    }
+
+    /// the screen as `0xAARRGGBB` when the megachip mode is on
+    pub fn argb(&self) -> Option<&[u32]> {
+        self.mega
+            .as_ref()
+            .filter(|mega| mega.enabled)
+            .map(|mega| mega.screen())
+    }
+
+    /// the sound started by the MegaChip-8 060N, see `MegaChip::audio`.
+    /// The buzzer is left to the frontends.
+    pub fn audio(&mut self, rate: u32, output: &mut [f32]) {
+        match &mut self.mega {
+            Some(mega) => mega.audio(self.memory.as_slice(), rate, output),
+            None => output.iter_mut().for_each(|s| *s = 0.0),
+        }
+    }
+
+    // The mutation API, for the debuggers and the tests.
+    // The values are checked against the registers, the memory and the
+    // screen of the machine, what the game does with them afterwards may
+    // still end in a `Fault`.
+
+    pub fn set_register(&mut self, x: usize, value: u8) -> Result<(), String> {
+        let register = self
+            .V
+            .get_mut(x)
+            .ok_or_else(|| format!("There is no register V{:X}", x))?;
+        *register = value;
+        Ok(())
+    }
+
+    /// I must point in the memory
+    pub fn set_index(&mut self, value: u16) -> Result<(), String> {
+        if value as usize >= self.memory().len() {
+            return Err(format!("I {:#X} is outside of the memory", value));
+        }
+        self.I = value;
+        Ok(())
+    }
+
+    /// the program counter must point to a whole instruction in the memory
+    pub fn set_pc(&mut self, pc: usize) -> Result<(), String> {
+        let len = self.memory().len();
+        if pc.checked_add(1).is_none_or(|end| end >= len) {
+            return Err(format!("The PC {:#X} is outside of the memory", pc));
+        }
+        self.pc = pc;
+        Ok(())
+    }
+
+    /// replace the content of the stack, the first address is the bottom
+    pub fn set_stack(&mut self, stack: &[usize]) -> Result<(), String> {
+        if stack.len() > self.stack.len() {
+            return Err(format!("The stack can't hold {} addresses", stack.len()));
+        }
+        self.stack[..stack.len()].copy_from_slice(stack);
+        self.sp = stack.len();
+        // we guess the targets from the instructions at the call sites
+        self.calls = stack
+            .iter()
+            .map(|call_site| Frame {
+                call_site: *call_site,
+                target: self.opcode_at(*call_site) as usize & 0x0FFF,
+            })
+            .collect();
+        Ok(())
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
+
+    /// write the bytes in memory starting at `address`
+    pub fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), String> {
+        let range = checked_range(address, bytes.len(), self.memory().len())?;
+        self.memory.as_mut_slice()[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// switch on or off a pixel, the screen is redrawn on the next update
+    pub fn set_pixel(&mut self, x: usize, y: usize, lit: bool) -> Result<(), String> {
+        if x >= self.width() || y >= self.height() {
+            return Err(format!("The pixel ({}, {}) is outside of the screen", x, y));
+        }
+        self.screen.split();
+        self.screen.set(x, y, lit as u8);
+        self.draw = true;
+        Ok(())
+    }
+
+    /// the screen if it changed since the last call, see
+    /// `Framebuffer::changed` for the pixels which changed
+    pub fn update(&mut self) -> Option<&Framebuffer> {
         let draw = self.draw;
         self.draw = false;
         match draw {
-            true => Some(&self.screen),
+            true => {
+                self.screen.present();
+                Some(&self.screen)
+            }
             false => None,
         }
     }
 
-    #[bitmatch]
-    fn handle_opcode(&mut self) {
+    /// log the instruction which was just executed, if we can't write the
+    /// trace we stop tracing instead of crashing the game, the error is kept
+    /// for `Cpu::take_trace_error`
+    #[cfg(feature = "std")]
+    fn trace(&mut self, pc: usize, V: [u8; 16], I: u16) {
+        let entry = Entry::new(pc, self.opcode, (&V, I), (&self.V, self.I));
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.record(&entry, &self.symbols) {
+                self.trace_error = Some(e);
+                self.tracer = None;
+            }
+        }
+    }
+
+    fn handle_opcode(&mut self) -> Result<(), Fault> {
+        if self.pc + 1 >= self.memory.as_slice().len() {
+            return Err(Fault::Memory(self.pc.max(self.memory.as_slice().len())));
+        }
         let opcode = ((self.memory[self.pc] as u16) << 8) | (self.memory[self.pc + 1] as u16);
         self.opcode = opcode;
 
-        #[bitmatch]
-        match opcode {
-            "0000_0000_1110_1110" => self.opcode_00EE(),
-            "0000_0000_1110_0000" => self.opcode_00E0(),
-            "0000_nnnn_nnnn_nnnn" => self.opcode_0NNN(n),
-            "0001_nnnn_nnnn_nnnn" => self.opcode_1NNN(n.into()),
-            "0010_nnnn_nnnn_nnnn" => self.opcode_2NNN(n.into()),
-            "0011_xxxx_nnnn_nnnn" => self.opcode_3XNN(x.into(), n as u8),
-            "0100_xxxx_nnnn_nnnn" => self.opcode_4XNN(x.into(), n as u8),
-            "0101_xxxx_yyyy_0000" => self.opcode_5XY0(x.into(), y.into()),
-            "0110_xxxx_nnnn_nnnn" => self.opcode_6XNN(x.into(), n as u8),
-            "0111_xxxx_nnnn_nnnn" => self.opcode_7XNN(x.into(), n as u8),
-            "1000_xxxx_yyyy_0000" => self.opcode_8XY0(x.into(), y.into()),
-            "1000_xxxx_yyyy_0001" => self.opcode_8XY1(x.into(), y.into()),
-            "1000_xxxx_yyyy_0010" => self.opcode_8XY2(x.into(), y.into()),
-            "1000_xxxx_yyyy_0011" => self.opcode_8XY3(x.into(), y.into()),
-            "1000_xxxx_yyyy_0100" => self.opcode_8XY4(x.into(), y.into()),
-            "1000_xxxx_yyyy_0101" => self.opcode_8XY5(x.into(), y.into()),
-            "1000_xxxx_yyyy_0110" => self.opcode_8XY6(x.into(), y.into()),
-            "1000_xxxx_yyyy_0111" => self.opcode_8XY7(x.into(), y.into()),
-            "1000_xxxx_yyyy_1110" => self.opcode_8XYE(x.into(), y.into()),
-            "1001_xxxx_yyyy_0000" => self.opcode_9XY0(x.into(), y.into()),
-            "1010_nnnn_nnnn_nnnn" => self.opcode_ANNN(n.into()),
-            "1011_nnnn_nnnn_nnnn" => self.opcode_BNNN(n.into()),
-            "1100_xxxx_nnnn_nnnn" => self.opcode_CXNN(x.into(), n as u8),
-            "1101_xxxx_yyyy_nnnn" => self.opcode_DXYN(x.into(), y.into(), n.into()),
-            "1110_xxxx_1001_1110" => self.opcode_EX9E(x.into()),
-            "1110_xxxx_1010_0001" => self.opcode_EXA1(x.into()),
-            "1111_xxxx_0000_0111" => self.opcode_FX07(x.into()),
-            "1111_xxxx_0000_1010" => self.opcode_FX0A(x.into()),
-            "1111_xxxx_0001_0101" => self.opcode_FX15(x.into()),
-            "1111_xxxx_0001_1000" => self.opcode_FX18(x.into()),
-            "1111_xxxx_0001_1110" => self.opcode_FX1E(x.into()),
-            "1111_xxxx_0010_1001" => self.opcode_FX29(x.into()),
-            "1111_xxxx_0011_0011" => self.opcode_FX33(x.into()),
-            "1111_xxxx_0101_0101" => self.opcode_FX55(x.into()),
-            "1111_xxxx_0110_0101" => self.opcode_FX65(x.into()),
-            "aaaa_aaaa_aaaa_aaaa" => panic!("Unknown instruction: {}", a),
+        // the instructions of the variants replace the usual ones
+        let variant = match self.config.platform {
+            Platform::Chip8 | Platform::Chip10 => None,
+            Platform::Chip8X => self.chip8x(opcode),
+            Platform::Chip8E => self.chip8e(opcode),
+            Platform::MegaChip => self.megachip(opcode),
+        };
+        if let Some(result) = variant {
+            return result;
         }
+
+        // the decoding is done once for all the opcodes, see `decode`
+        self.execute(decode::lookup(opcode))
+    }
+
+    /// execute an instruction of the original chip8
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Fault> {
+        use Instruction::*;
+        match instruction {
+            Op00EE => return self.opcode_00EE(),
+            Op00E0 => self.opcode_00E0(),
+            Op0NNN(n) => return self.opcode_0NNN(n),
+            Op1NNN(n) => self.opcode_1NNN(n.into()),
+            Op2NNN(n) => return self.opcode_2NNN(n.into()),
+            Op3XNN(x, n) => self.opcode_3XNN(x.into(), n),
+            Op4XNN(x, n) => self.opcode_4XNN(x.into(), n),
+            Op5XY0(x, y) => self.opcode_5XY0(x.into(), y.into()),
+            Op6XNN(x, n) => self.opcode_6XNN(x.into(), n),
+            Op7XNN(x, n) => self.opcode_7XNN(x.into(), n),
+            Op8XY0(x, y) => self.opcode_8XY0(x.into(), y.into()),
+            Op8XY1(x, y) => self.opcode_8XY1(x.into(), y.into()),
+            Op8XY2(x, y) => self.opcode_8XY2(x.into(), y.into()),
+            Op8XY3(x, y) => self.opcode_8XY3(x.into(), y.into()),
+            Op8XY4(x, y) => self.opcode_8XY4(x.into(), y.into()),
+            Op8XY5(x, y) => self.opcode_8XY5(x.into(), y.into()),
+            Op8XY6(x, y) => self.opcode_8XY6(x.into(), y.into()),
+            Op8XY7(x, y) => self.opcode_8XY7(x.into(), y.into()),
+            Op8XYE(x, y) => self.opcode_8XYE(x.into(), y.into()),
+            Op9XY0(x, y) => self.opcode_9XY0(x.into(), y.into()),
+            OpANNN(n) => self.opcode_ANNN(n),
+            OpBNNN(n) => self.opcode_BNNN(n.into()),
+            OpCXNN(x, n) => self.opcode_CXNN(x.into(), n),
+            OpDXYN(x, y, n) => return self.opcode_DXYN(x.into(), y.into(), n.into()),
+            OpEX9E(x) => self.opcode_EX9E(x.into()),
+            OpEXA1(x) => self.opcode_EXA1(x.into()),
+            OpFX07(x) => self.opcode_FX07(x.into()),
+            OpFX0A(x) => self.opcode_FX0A(x.into()),
+            OpFX15(x) => self.opcode_FX15(x.into()),
+            OpFX18(x) => self.opcode_FX18(x.into()),
+            OpFX1E(x) => self.opcode_FX1E(x.into()),
+            OpFX29(x) => self.opcode_FX29(x.into()),
+            OpFX33(x) => return self.opcode_FX33(x.into()),
+            OpFX55(x) => return self.opcode_FX55(x.into()),
+            OpFX65(x) => return self.opcode_FX65(x.into()),
+            Unknown(a) => return Err(Fault::UnknownInstruction(a)),
+        }
+        Ok(())
+    }
+
+    /// the threaded backend can run this cpu, it doesn't know the
+    /// instructions of the variants, the VIP timing or the tracing
+    pub(crate) fn threadable(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.tracer.is_some() {
+            return false;
+        }
+        if self.inputs.is_some() {
+            return false;
+        }
+        let platform = matches!(self.config.platform, Platform::Chip8 | Platform::Chip10);
+        platform && self.timing == Timing::Instruction
+    }
+
+    /// execute an instruction decoded by the threaded backend, with the
+    /// timers ticking like in `cycle`
+    pub(crate) fn step(&mut self, opcode: u16, instruction: Instruction) -> Result<(), Fault> {
+        self.opcode = opcode;
+        self.execute(instruction)?;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        Ok(())
+    }
+
+    /// see `Memory::take_written`
+    pub(crate) fn take_written(&mut self) -> Option<Range<usize>> {
+        self.memory.take_written()
+    }
+
+    /// the two bytes at `address`, zero outside of the memory
+    fn opcode_at(&self, address: usize) -> u16 {
+        let memory = self.memory.as_slice();
+        let byte = |a: usize| memory.get(a).copied().unwrap_or(0) as u16;
+        byte(address) << 8 | byte(address + 1)
     }
 
     /// Clears the screen.
     fn opcode_00E0(&mut self) {
-        for p in self.screen.iter_mut() {
-            *p = false;
-        }
+        self.screen.clear();
         self.pc += 2;
         self.draw = true;
     }
 
     /// Returns from a subroutine.
-    fn opcode_00EE(&mut self) {
+    fn opcode_00EE(&mut self) -> Result<(), Fault> {
+        if self.sp == 0 {
+            return Err(Fault::StackUnderflow);
+        }
         self.sp -= 1;
+        self.calls.pop();
         self.pc = self.stack[self.sp];
         self.pc += 2;
+        Ok(())
     }
 
     /// Calls RCA 1802 program at address NNN. Not necessary for most ROMs.
-    /// !!! **NOT IMPLEMENTED**  !!!
-    fn opcode_0NNN(&mut self, _n: u16) {
-        self.pc += 2;
+    /// Only when the machine allows the machine code, see
+    /// `MachineConfig::machine_code`, otherwise it's skipped.
+    /// The subroutine sees the memory and the registers of the 1802 as the
+    /// interpreter of the VIP left them and returns to it with `SEP R4`.
+    fn opcode_0NNN(&mut self, n: u16) -> Result<(), Fault> {
+        if !self.config.machine_code {
+            self.pc += 2;
+            return Ok(());
+        }
+        let top = self.memory.as_slice().len();
+        let registers = top - VIP_REGISTERS;
+        let display = top - VIP_DISPLAY;
+
+        // put the state of the chip 8 where the interpreter keeps it
+        let memory = self.memory.as_mut_slice();
+        memory[registers..registers + 16].copy_from_slice(&self.V);
+        let pixels = self.screen.pixels().chunks(8);
+        for (byte, pixels) in memory[display..top].iter_mut().zip(pixels) {
+            *byte = pixels.iter().fold(0, |byte, &p| byte << 1 | (p != 0) as u8);
+        }
+        let rca = &mut self.rca;
+        rca.r[2] = (top - VIP_STACK) as u16;
+        rca.r[3] = n;
+        rca.r[5] = (self.pc + 2) as u16;
+        rca.r[6] = (registers + (self.opcode as usize >> 8 & 0xF)) as u16;
+        rca.r[7] = (registers + (self.opcode as usize >> 4 & 0xF)) as u16;
+        rca.r[8] = (self.delay_timer as u16) << 8 | self.sound_timer as u16;
+        rca.r[0xA] = self.I;
+        rca.r[0xB] = (display as u16) & 0xFF00;
+        rca.p = 3;
+        rca.x = 2;
+
+        let mut cycles = 0;
+        while rca.p != 4 {
+            if cycles > MACHINE_CODE_LIMIT {
+                return Err(Fault::MachineCode(n));
+            }
+            cycles += rca.step(memory);
+            // there is no interrupt to wait for after an IDL
+            rca.idle = false;
+        }
+        self.machine_cycles = cycles as i32;
+
+        // and read it back, the subroutine can change any of it
+        self.V.copy_from_slice(&memory[registers..registers + 16]);
+        let width = self.screen.width();
+        self.screen.split();
+        for (i, byte) in memory[display..top].iter().enumerate() {
+            for x in 0..8 {
+                let pixel = i * 8 + x;
+                let lit = byte & (0x80 >> x) != 0;
+                self.screen.set(pixel % width, pixel / width, lit as u8);
+            }
+        }
+        self.I = rca.r[0xA];
+        self.pc = rca.r[5] as usize % top;
+        self.delay_timer = (rca.r[8] >> 8) as u8;
+        self.sound_timer = rca.r[8] as u8;
+        self.draw = true;
+        Ok(())
     }
 
     /// Jumps to address NNN.
@@ -139,10 +771,18 @@ impl Cpu {
     }
 
     /// Calls subroutine at NNN.
-    fn opcode_2NNN(&mut self, n: usize) {
+    fn opcode_2NNN(&mut self, n: usize) -> Result<(), Fault> {
+        if self.sp == self.stack.len() {
+            return Err(Fault::StackOverflow);
+        }
         self.stack[self.sp] = self.pc;
         self.sp += 1;
+        self.calls.push(Frame {
+            call_site: self.pc,
+            target: n,
+        });
         self.pc = n;
+        Ok(())
     }
 
     /// Skips the next instruction if VX equals NN.
@@ -193,18 +833,27 @@ impl Cpu {
     /// Sets VX to VX or VY. (Bitwise OR operation)
     fn opcode_8XY1(&mut self, x: usize, y: usize) {
         self.V[x] |= self.V[y];
+        if self.quirks.vf_reset {
+            self.V[0xF] = 0;
+        }
         self.pc += 2;
     }
 
     /// Sets VX to VX and VY. (Bitwise AND operation)
     fn opcode_8XY2(&mut self, x: usize, y: usize) {
         self.V[x] &= self.V[y];
+        if self.quirks.vf_reset {
+            self.V[0xF] = 0;
+        }
         self.pc += 2;
     }
 
     /// Sets VX to VX xor VY.
     fn opcode_8XY3(&mut self, x: usize, y: usize) {
         self.V[x] ^= self.V[y];
+        if self.quirks.vf_reset {
+            self.V[0xF] = 0;
+        }
         self.pc += 2;
     }
 
@@ -227,10 +876,14 @@ impl Cpu {
     }
 
     /// Stores the least significant bit of VX in VF and then shifts VX
-    /// to the right by 1.
-    fn opcode_8XY6(&mut self, x: usize, _y: usize) {
-        self.V[0xF] = self.V[x] & 0x1;
-        self.V[x] >>= 1;
+    /// to the right by 1. (With the `shift_vy` quirk VY is shifted instead)
+    fn opcode_8XY6(&mut self, x: usize, y: usize) {
+        let value = match self.quirks.shift_vy {
+            true => self.V[y],
+            false => self.V[x],
+        };
+        self.V[x] = value >> 1;
+        self.V[0xF] = value & 0x1;
         self.pc += 2;
     }
 
@@ -245,10 +898,14 @@ impl Cpu {
     }
 
     /// Stores the most significant bit of VX in VF and then shifts
-    /// VX to the left by 1.
-    fn opcode_8XYE(&mut self, x: usize, _y: usize) {
-        self.V[0xF] = self.V[x] >> 7;
-        self.V[x] <<= 1;
+    /// VX to the left by 1. (With the `shift_vy` quirk VY is shifted instead)
+    fn opcode_8XYE(&mut self, x: usize, y: usize) {
+        let value = match self.quirks.shift_vy {
+            true => self.V[y],
+            false => self.V[x],
+        };
+        self.V[x] = value << 1;
+        self.V[0xF] = value >> 7;
         self.pc += 2;
     }
 
@@ -267,15 +924,20 @@ impl Cpu {
         self.pc += 2;
     }
 
-    /// Jumps to the address NNN plus V0.
+    /// Jumps to the address NNN plus V0. (With the `jump_vx` quirk it's
+    /// VX, the first digit of NNN)
     fn opcode_BNNN(&mut self, n: usize) {
-        self.pc = n + self.V[0] as usize;
+        let x = match self.quirks.jump_vx {
+            true => n >> 8,
+            false => 0,
+        };
+        self.pc = n + self.V[x] as usize;
     }
 
     /// Sets VX to the result of a bitwise and operation on
     /// a random number (Typically: 0 to 255) and NN.
     fn opcode_CXNN(&mut self, x: usize, n: u8) {
-        let rand: u8 = rand::thread_rng().gen();
+        let rand: u8 = self.rng.gen();
 
         self.V[x] = rand & n;
         self.pc += 2;
@@ -287,46 +949,73 @@ impl Cpu {
     /// I value doesn’t change after the execution of this instruction.
     /// As described above, VF is set to 1 if any screen pixels are flipped
     /// from set to unset when the sprite is drawn, and to 0 if that doesn’t happen.
-    fn opcode_DXYN(&mut self, x: usize, y: usize, N: usize) {
-        let X = self.V[x] as usize;
-        let Y = self.V[y] as usize;
+    /// With the `clip` quirk the sprite start at (VX modulo 64, VY modulo 32)
+    /// and the pixels going out of the screen are dropped.
+    fn opcode_DXYN(&mut self, x: usize, y: usize, N: usize) -> Result<(), Fault> {
+        self.draw_sprite(x, y, N, 1)
+    }
+
+    /// draw a sprite of `rows` lines of `bytes` bytes, 8 pixels per byte,
+    /// see `opcode_DXYN`
+    fn draw_sprite(&mut self, x: usize, y: usize, rows: usize, bytes: usize) -> Result<(), Fault> {
+        let I = self.index_range(rows * bytes)?;
+        let mut X = self.V[x] as usize;
+        let mut Y = self.V[y] as usize;
+        let (width, height) = (self.width(), self.height());
+        if self.quirks.clip {
+            X %= width;
+            Y %= height;
+        }
 
         self.V[0xF] = 0;
-        for y in 0..N {
-            let pixel = self.memory[self.I as usize + y];
-            for x in 0..8 {
-                if (pixel & (0x80 >> x)) != 0 {
-                    let pos = (X + x + ((Y + y) * 64)) % 2048;
+        // each part of a sprite wrapping around the screen is redrawn on its own
+        let mut part = None;
+        for y in 0..rows {
+            for x in 0..8 * bytes {
+                if self.quirks.clip && (X + x >= width || Y + y >= height) {
+                    continue;
+                }
+                let pixel = self.memory[I + y * bytes + x / 8];
+                if (pixel & (0x80 >> (x % 8))) != 0 {
+                    let wrap = ((X + x) / width, ((X + x) / width + Y + y) / height);
+                    if part != Some(wrap) {
+                        part = Some(wrap);
+                        self.screen.split();
+                    }
+                    let pos = (X + x + ((Y + y) * width)) % (width * height);
 
-                    if self.screen[pos] {
+                    if self.screen.flip(pos % width, pos / width, 1) {
                         self.V[0xF] = 1
                     }
-                    self.screen[pos] = !self.screen[pos];
                 }
             }
         }
         self.pc += 2;
         self.draw = true;
+        Ok(())
     }
 
     /// Skips the next instruction if the key stored in VX is pressed.
     /// (Usually the next instruction is a jump to skip a code block)
     fn opcode_EX9E(&mut self, x: usize) {
-        match self.key[self.V[x] as usize] {
+        // like on the VIP only the low nibble selects the key
+        let key = self.V[x] as usize & 0xF;
+        match self.key[key] {
             true => self.pc += 4,
             false => self.pc += 2,
         }
-        self.key[self.V[x] as usize] = false;
+        self.key[key] = false;
     }
 
     /// Skips the next instruction if the key stored in VX isn't pressed.
     /// (Usually the next instruction is a jump to skip a code block)
     fn opcode_EXA1(&mut self, x: usize) {
-        match self.key[self.V[x] as usize] {
+        let key = self.V[x] as usize & 0xF;
+        match self.key[key] {
             true => self.pc += 2,
             false => self.pc += 4,
         }
-        self.key[self.V[x] as usize] = false;
+        self.key[key] = false;
     }
 
     /// Sets VX to the value of the delay timer.
@@ -363,16 +1052,21 @@ impl Cpu {
         self.pc += 2;
     }
 
-    /// Adds VX to I.
+    /// Adds VX to I. (With the `index_overflow` quirk VF is set to 1 when
+    /// I goes past 0xFFF, and to 0 when it doesn't)
     fn opcode_FX1E(&mut self, x: usize) {
+        let overflow = self.I as usize + self.V[x] as usize > 0xFFF;
         self.I = self.I.wrapping_add(self.V[x] as u16);
+        if self.quirks.index_overflow {
+            self.V[0xF] = overflow.into();
+        }
         self.pc += 2;
     }
 
     /// Sets I to the location of the sprite for the character in VX.
     /// Characters 0-F (in hexadecimal) are represented by a 4x5 font.
     fn opcode_FX29(&mut self, x: usize) {
-        self.I = self.V[x].wrapping_mul(0x5).into();
+        self.I = self.memory.font() as u16 + self.V[x].wrapping_mul(0x5) as u16;
         self.pc += 2;
     }
 
@@ -384,44 +1078,324 @@ impl Cpu {
     /// * Place the hundreds digit in memory at location in I
     /// * The tens digit at location I+1
     /// * And the ones digit at location I+2)
-    fn opcode_FX33(&mut self, x: usize) {
+    fn opcode_FX33(&mut self, x: usize) -> Result<(), Fault> {
         let VX = self.V[x];
+        let I = self.index_range(3)?;
 
-        self.memory[self.I] = VX / 100;
-        self.memory[self.I + 1] = (VX % 100) / 10;
-        self.memory[self.I + 2] = VX % 10;
+        self.memory[I] = VX / 100;
+        self.memory[I + 1] = (VX % 100) / 10;
+        self.memory[I + 2] = VX % 10;
         self.pc += 2;
+        Ok(())
     }
 
     /// Stores V0 to VX (including VX) in memory starting at address I.
     /// The offset from I is increased by 1 for each value written,
     /// but I itself is left unmodified.
-    fn opcode_FX55(&mut self, x: usize) {
+    fn opcode_FX55(&mut self, x: usize) -> Result<(), Fault> {
+        let I = self.index_range(x + 1)?;
         for i in 0..=x {
-            self.memory[self.I as usize + i] = self.V[i];
+            self.memory[I + i] = self.V[i];
+        }
+        if self.quirks.increment_i {
+            self.I = self.I.wrapping_add(x as u16 + 1);
         }
 
         self.pc += 2;
+        Ok(())
     }
 
     /// Fills V0 to VX (including VX) with values from memory starting
     /// at address I. The offset from I is increased by 1 for each
     /// value written, but I itself is left unmodified.
-    fn opcode_FX65(&mut self, x: usize) {
+    fn opcode_FX65(&mut self, x: usize) -> Result<(), Fault> {
+        let I = self.index_range(x + 1)?;
         for i in 0..=x {
-            self.V[i] = self.memory[self.I as usize + i];
+            self.V[i] = self.memory[I + i];
+        }
+        if self.quirks.increment_i {
+            self.I = self.I.wrapping_add(x as u16 + 1);
+        }
+
+        self.pc += 2;
+        Ok(())
+    }
+
+    /// I when the `len` bytes starting there are in the memory, otherwise
+    /// the fault for the first address outside of it
+    fn index_range(&self, len: usize) -> Result<usize, Fault> {
+        let (I, size) = (self.I as usize, self.memory.as_slice().len());
+        match I + len <= size {
+            true => Ok(I),
+            false => Err(Fault::Memory(I.max(size))),
+        }
+    }
+}
+
+/// The instructions of the variants of the chip 8, see `Platform`.
+/// They return `None` for the instructions they share with the chip 8.
+impl Cpu {
+    #[bitmatch]
+    fn chip8x(&mut self, opcode: u16) -> Option<Result<(), Fault>> {
+        #[bitmatch]
+        match opcode {
+            "0000_0010_1010_0000" => self.opcode_02A0(),
+            "0101_xxxx_yyyy_0001" => self.opcode_5XY1_8X(x.into(), y.into()),
+            "1011_xxxx_yyyy_0000" => self.opcode_BXY0(x.into(), y.into()),
+            "1011_xxxx_yyyy_nnnn" => self.opcode_BXYN(x.into(), y.into(), n.into()),
+            // the second keypad is not connected
+            "1110_????_1111_0010" => self.pc += 2,
+            "1110_????_1111_0101" => self.pc += 4,
+            "1111_xxxx_1111_1000" => self.opcode_FXF8(x.into()),
+            // nothing is connected to the input port
+            "1111_xxxx_1111_1011" => self.opcode_FXE7(x.into()),
+            _ => return None,
+        }
+        Some(Ok(()))
+    }
+
+    #[bitmatch]
+    fn chip8e(&mut self, opcode: u16) -> Option<Result<(), Fault>> {
+        #[bitmatch]
+        match opcode {
+            "0000_0000_1110_1101" => (), // stop
+            "0000_0000_1111_0010" => self.pc += 2,
+            "0000_0001_0101_0001" => self.opcode_0151(),
+            "0000_0001_1000_1000" => self.pc += 4,
+            "0101_xxxx_yyyy_0001" => self.opcode_5XY1_8E(x.into(), y.into()),
+            "0101_xxxx_yyyy_0010" => return Some(self.opcode_5XY2(x.into(), y.into())),
+            "0101_xxxx_yyyy_0011" => return Some(self.opcode_5XY3(x.into(), y.into())),
+            "1011_1011_nnnn_nnnn" => self.pc = (self.pc + 2).wrapping_sub(n.into()),
+            "1011_1111_nnnn_nnnn" => self.pc += 2 + n as usize,
+            // nothing is connected to the output port
+            "1111_????_0000_0011" => self.pc += 2,
+            "1111_xxxx_0001_1011" => self.pc += 2 + self.V[x as usize] as usize,
+            "1111_xxxx_0100_1111" => self.opcode_FX4F(x.into()),
+            "1111_xxxx_1110_0011" => self.opcode_FXE7(x.into()),
+            "1111_xxxx_1110_0111" => self.opcode_FXE7(x.into()),
+            _ => return None,
+        }
+        Some(Ok(()))
+    }
+
+    /// The MegaChip-8 runs the instructions of the SUPER-CHIP, its own
+    /// ones and replaces 00E0 and DXYN when its mode is on. The scrolls and
+    /// the big sprites of the SUPER-CHIP only work with the mode off, 00FD
+    /// which quits the interpreter isn't there.
+    fn megachip(&mut self, opcode: u16) -> Option<Result<(), Fault>> {
+        let mut mega = self.mega.take()?;
+        let result = self.opcode_mega(&mut mega, opcode);
+        self.mega = Some(mega);
+        result
+    }
+
+    #[bitmatch]
+    fn opcode_mega(&mut self, mega: &mut MegaChip, opcode: u16) -> Option<Result<(), Fault>> {
+        let I = self.I as usize;
+        #[bitmatch]
+        match opcode {
+            // the screen changes size with the mode
+            "0000_0000_0001_0000" => {
+                mega.enabled = false;
+                self.resize(mega.screen_size());
+            }
+            "0000_0000_0001_0001" => {
+                mega.enabled = true;
+                self.resize(mega.screen_size());
+            }
+            "0000_0000_1111_1110" => {
+                mega.hires = false;
+                self.resize(mega.screen_size());
+            }
+            "0000_0000_1111_1111" => {
+                mega.hires = true;
+                self.resize(mega.screen_size());
+            }
+            "0000_0000_1100_nnnn" if !mega.enabled => {
+                self.screen.scroll(-(n as isize));
+                self.draw = true;
+            }
+            "0000_0000_1111_1011" if !mega.enabled => {
+                self.screen.scroll_columns(-4);
+                self.draw = true;
+            }
+            "0000_0000_1111_1100" if !mega.enabled => {
+                self.screen.scroll_columns(4);
+                self.draw = true;
+            }
+            "1101_xxxx_yyyy_0000" if !mega.enabled => {
+                return Some(self.draw_sprite(x.into(), y.into(), 16, 2));
+            }
+            // only the digits 0 to 9
+            "1111_xxxx_0011_0000" => {
+                let digit = self.V[x as usize] as usize % 10;
+                self.I = (self.memory.big_font() + digit * 10) as u16;
+            }
+            "1111_xxxx_0111_0101" => mega.save_flags(&self.V[..=x as usize]),
+            "1111_xxxx_1000_0101" => mega.load_flags(&mut self.V[..=x as usize]),
+            // I only has 16 bits, the high byte of the address is dropped
+            "0000_0001_????_????" => {
+                self.I = self.opcode_at(self.pc + 2);
+                self.pc += 2;
+            }
+            "0000_0010_nnnn_nnnn" => mega.load_palette(self.memory.as_slice(), I, n.into()),
+            "0000_0011_nnnn_nnnn" => mega.set_sprite_width(n as u8),
+            "0000_0100_nnnn_nnnn" => mega.set_sprite_height(n as u8),
+            "0000_0101_nnnn_nnnn" => mega.set_alpha(n as u8),
+            "0000_0110_0000_nnnn" => mega.play(self.memory.as_slice(), I, n == 0),
+            "0000_0111_0000_0000" => mega.stop(),
+            "0000_1000_0000_nnnn" => mega.set_blend(n as u8),
+            "0000_1001_nnnn_nnnn" => mega.set_collision(n as u8),
+            "0000_0000_1011_nnnn" if mega.enabled => mega.scroll_up(n.into()),
+            "0000_0000_1110_0000" if mega.enabled => {
+                mega.present(&mut self.screen);
+                self.draw = true;
+            }
+            "1101_xxxx_yyyy_????" if mega.enabled => {
+                let (x, y) = (self.V[x as usize].into(), self.V[y as usize].into());
+                self.V[0xF] = mega.draw(self.memory.as_slice(), I, x, y) as u8;
+            }
+            _ => return None,
+        }
+        self.pc += 2;
+        Some(Ok(()))
+    }
+
+    /// a new screen when the size changes
+    fn resize(&mut self, (width, height): (usize, usize)) {
+        if (width, height) != (self.width(), self.height()) {
+            self.screen = Framebuffer::new(width, height, 1);
+            self.draw = true;
+        }
+    }
+
+    /// CHIP-8X: cycles the color of the background
+    fn opcode_02A0(&mut self) {
+        self.background = (self.background + 1) % CHIP8X_BACKGROUNDS.len() as u8;
+        self.pc += 2;
+        self.draw = true;
+    }
+
+    /// CHIP-8X: adds VY to VX, each nibble separately on 3 bits.
+    fn opcode_5XY1_8X(&mut self, x: usize, y: usize) {
+        self.V[x] = ((self.V[x] & 0x77) + (self.V[y] & 0x77)) & 0x77;
+        self.pc += 2;
+    }
+
+    /// CHIP-8X: colors the zones of 8x4 pixels with the color in VY.
+    /// The low nibble of VX is the first column of zones and its high nibble
+    /// the number of columns after it, V(X+1) gives the rows the same way.
+    fn opcode_BXY0(&mut self, x: usize, y: usize) {
+        let zones = |v: u8| v & 0xF..=(v & 0xF) + (v >> 4);
+        let (columns, rows) = (zones(self.V[x]), zones(self.V[(x + 1) % 16]));
+        let color = self.V[y] & 0x7;
+        let blocks = self.width() / 8;
+        for row in rows {
+            for line in row as usize * 4..row as usize * 4 + 4 {
+                for column in columns.clone() {
+                    let block = (column as usize % blocks) + (line % self.height()) * blocks;
+                    self.colors[block] = color;
+                }
+            }
+        }
+        self.pc += 2;
+        self.draw = true;
+    }
+
+    /// CHIP-8X: colors N lines of the block of 8x1 pixels at (VX, VY) with
+    /// the color in V(X+1).
+    fn opcode_BXYN(&mut self, x: usize, y: usize, N: usize) {
+        let color = self.V[(x + 1) % 16] & 0x7;
+        let (blocks, height) = (self.width() / 8, self.height());
+        let column = self.V[x] as usize / 8 % blocks;
+        for line in self.V[y] as usize..self.V[y] as usize + N {
+            self.colors[column + (line % height) * blocks] = color;
+        }
+        self.pc += 2;
+        self.draw = true;
+    }
+
+    /// CHIP-8X: sets the frequency of the speaker to VX.
+    fn opcode_FXF8(&mut self, x: usize) {
+        self.tone = self.V[x];
+        self.pc += 2;
+    }
+
+    /// CHIP-8E: waits until the delay timer is zero.
+    fn opcode_0151(&mut self) {
+        if self.delay_timer == 0 {
+            self.pc += 2;
+        }
+    }
+
+    /// CHIP-8E: skips the next instruction if VX is greater than VY.
+    fn opcode_5XY1_8E(&mut self, x: usize, y: usize) {
+        match self.V[x] > self.V[y] {
+            true => self.pc += 4,
+            false => self.pc += 2,
         }
+    }
+
+    /// CHIP-8E: stores VX to VY in memory starting at address I, I is
+    /// moved after them.
+    fn opcode_5XY2(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        self.index_range((y + 1).saturating_sub(x))?;
+        for i in x..=y {
+            self.memory[self.I as usize] = self.V[i];
+            self.I = self.I.wrapping_add(1);
+        }
+        self.pc += 2;
+        Ok(())
+    }
 
+    /// CHIP-8E: fills VX to VY with the memory starting at address I, I is
+    /// moved after them.
+    fn opcode_5XY3(&mut self, x: usize, y: usize) -> Result<(), Fault> {
+        self.index_range((y + 1).saturating_sub(x))?;
+        for i in x..=y {
+            self.V[i] = self.memory[self.I as usize];
+            self.I = self.I.wrapping_add(1);
+        }
+        self.pc += 2;
+        Ok(())
+    }
+
+    /// CHIP-8E: sets the delay timer to VX and waits until it's zero.
+    fn opcode_FX4F(&mut self, x: usize) {
+        if !self.waiting {
+            self.delay_timer = self.V[x];
+            self.waiting = true;
+        }
+        if self.delay_timer == 0 {
+            self.waiting = false;
+            self.pc += 2;
+        }
+    }
+
+    /// Reads the input port in VX, nothing is connected to it so it's 0.
+    fn opcode_FXE7(&mut self, x: usize) {
+        self.V[x] = 0;
         self.pc += 2;
     }
 }
 
+/// the range of `len` bytes starting at `address` if it fit in `size`
+fn checked_range(address: usize, len: usize, size: usize) -> Result<Range<usize>, String> {
+    match address.checked_add(len) {
+        Some(end) if end <= size => Ok(address..end),
+        _ => Err(format!(
+            "Can't access {} bytes at {:#X}, the memory stop at {:#X}",
+            len, address, size
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn init() -> Chip8 {
-        Chip8::new()
+    fn init() -> Cpu {
+        Cpu::new()
     }
 
     #[test]
@@ -434,10 +1408,8 @@ mod tests {
         let mut c = init();
         c.opcode_00E0();
         assert_eq!(c.pc, 0x202);
-        for p in c.screen.iter() {
-            assert_eq!(*p, 0);
-        }
-        assert_eq!(c.draw, true);
+        assert!(c.screen.pixels().iter().all(|p| *p == 0));
+        assert!(c.draw);
     }
 
     #[test]
@@ -445,7 +1417,7 @@ mod tests {
         let mut c = init();
         c.sp = 1;
         c.stack[0] = 40;
-        c.opcode_00EE();
+        c.opcode_00EE().unwrap();
         assert_eq!(c.sp, 0);
         assert_eq!(c.pc, 42);
     }
@@ -453,7 +1425,271 @@ mod tests {
     #[test]
     fn opcode_0NNN() {
         let mut c = init();
-        c.opcode_0NNN();
+        c.opcode_0NNN(0x000).unwrap();
+        assert_eq!(c.pc, 0x202);
+    }
+
+    fn platform(name: &str, rom: &[u8]) -> Cpu {
+        let mut c = Cpu::with_config(name.parse().unwrap()).unwrap();
+        c.load_rom(rom);
+        c
+    }
+
+    #[test]
+    fn chip8x() {
+        #[rustfmt::skip]
+        let mut c = platform("chip8x", &[
+            0x02, 0xA0, // the background is black
+            0x51, 0x21, // V1 = 0x35 + 0x57 nibble by nibble
+            0xB0, 0x30, // color the zone (V0, V1) with V3
+            0xB4, 0x52, // color 2 lines at (V4, V5) with V5
+            0xF3, 0xF8, // the tone is V3
+        ]);
+        assert_eq!(c.pc, 0x300);
+        assert_eq!(c.colors().unwrap().0, 2);
+        c.V[0x0] = 0x01;
+        c.V[0x1] = 0x35;
+        c.V[0x2] = 0x57;
+        c.V[0x3] = 0x04;
+        c.V[0x4] = 20;
+        c.V[0x5] = 6;
+        for _ in 0..3 {
+            c.cycle().unwrap();
+        }
+        let (background, colors) = c.colors().unwrap();
+        assert_eq!(background, 0);
+        assert_eq!(c.V[0x1], 0x04);
+        // the zones are 8x4, V1 selects the row 4 so the lines 16 to 19
+        assert_eq!(colors[1 + 16 * 8], 4);
+        assert_eq!(colors[1 + 19 * 8], 4);
+        assert_eq!(colors[1 + 20 * 8], CHIP8X_FOREGROUND);
+        assert_eq!(colors[2 + 16 * 8], CHIP8X_FOREGROUND);
+        c.cycle().unwrap();
+        let colors = c.colors().unwrap().1;
+        assert_eq!(colors[2 + 6 * 8], 6);
+        assert_eq!(colors[2 + 7 * 8], 6);
+        assert_eq!(colors[2 + 8 * 8], CHIP8X_FOREGROUND);
+        c.cycle().unwrap();
+        assert_eq!(c.tone(), 0x04);
+        assert_eq!(init().colors(), None);
+    }
+
+    #[test]
+    fn chip8e() {
+        #[rustfmt::skip]
+        let mut c = platform("chip8e", &[
+            0x51, 0x21, // skip, V1 > V2
+            0x00, 0x00,
+            0x51, 0x32, // store V1 to V3 at I
+            0x56, 0x83, // load V6 to V8 from I
+            0xBF, 0x02, // skip the next instruction
+            0x00, 0x00,
+            0xF9, 0x4F, // wait 2 frames
+            0xBB, 0x10, // back to 0x200
+        ]);
+        c.V[0x1] = 5;
+        c.V[0x2] = 4;
+        c.V[0x3] = 3;
+        c.I = 0x400;
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x204);
+        c.cycle().unwrap();
+        assert_eq!(c.memory()[0x400..0x403], [5, 4, 3]);
+        assert_eq!(c.I, 0x403);
+        c.I = 0x400;
+        c.cycle().unwrap();
+        assert_eq!(c.V[0x6..=0x8], [5, 4, 3]);
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x20C);
+        c.V[0x9] = 3;
+        for _ in 0..3 {
+            c.cycle().unwrap();
+            assert_eq!(c.pc, 0x20C);
+        }
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x20E);
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x200);
+    }
+
+    #[test]
+    fn chip10() {
+        let mut c = platform("chip10", &[0xD0, 0x11]);
+        assert_eq!((c.width(), c.height()), (128, 64));
+        assert_eq!(c.framebuffer().len(), 128 * 64);
+        c.V[0x0] = 100;
+        c.V[0x1] = 50;
+        c.I = 0x300;
+        c.memory[0x300usize] = 0x80;
+        c.cycle().unwrap();
+        assert!(c.framebuffer().lit(100, 50));
+        assert!(c.set_pixel(127, 63, true).is_ok());
+        assert!(c.set_pixel(128, 0, true).is_err());
+    }
+
+    #[test]
+    fn dirty_regions() {
+        use crate::framebuffer::Rect;
+        // two sprites then nothing
+        let mut c = platform("default", &[0xD0, 0x15, 0xD2, 0x35, 0x12, 0x04]);
+        c.V[0x2] = 60;
+        c.V[0x3] = 30;
+        c.I = 0; // the digit 0
+        assert_eq!(c.update().unwrap().regions(), [Rect::new(0, 0, 64, 32)]);
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        // the second sprite wraps to the top of the screen
+        let screen = c.update().unwrap();
+        assert_eq!(screen.regions().len(), 3);
+        assert_eq!(screen.regions()[0], Rect::new(0, 0, 4, 5));
+        assert_eq!(screen.regions()[1], Rect::new(60, 30, 4, 2));
+        assert_eq!(screen.changed_lines(), [0..5, 30..32]);
+        c.cycle().unwrap();
+        assert!(c.update().is_none());
+    }
+
+    #[test]
+    fn megachip() {
+        #[rustfmt::skip]
+        let mut c = platform("megachip", &[
+            0x00, 0x11,             // megachip mode on
+            0x01, 0x00, 0x03, 0x00, // I = 0x300
+            0x02, 0x01,             // load one color
+            0x01, 0x00, 0x03, 0x10, // I = 0x310
+            0x03, 0x02, 0x04, 0x01, // 2x1 sprites
+            0x09, 0x01,             // collision with the color 1
+            0xD0, 0x10,             // draw at (V0, V1)
+            0xD0, 0x10,             // again, on the color 1
+            0x00, 0xE0,             // show the screen
+        ]);
+        c.write_memory(0x300, &[0xFF, 0x12, 0x34, 0x56]).unwrap();
+        c.write_memory(0x310, &[0x01, 0x00]).unwrap();
+        c.V[0x0] = 200;
+        c.V[0x1] = 150;
+        assert_eq!(c.argb(), None);
+        for _ in 0..8 {
+            c.cycle().unwrap();
+        }
+        assert_eq!(c.I, 0x310);
+        assert_eq!(c.V[0xF], 0);
+        assert_eq!(c.argb().unwrap()[200 + 150 * 256], 0xFF00_0000);
+        c.cycle().unwrap();
+        assert_eq!(c.V[0xF], 1);
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x218);
+        assert_eq!(c.argb().unwrap()[200 + 150 * 256], 0xFF12_3456);
+        assert!(c.framebuffer().lit(200, 150));
+        assert!(!c.framebuffer().lit(201, 150));
+        assert!(c.update().is_some());
+    }
+
+    #[test]
+    fn megachip_screen() {
+        #[rustfmt::skip]
+        let mut c = platform("megachip", &[
+            0xF2, 0x29,             // I = the 0 of the font
+            0xD0, 0x11,             // draw its first line at (V0, V1)
+            0x00, 0x11,             // megachip mode on
+            0x00, 0x10,             // and off
+        ]);
+        // without the megachip mode the sprites wrap around a 64x32 screen,
+        // the sprite starts at (70 % 64, 40 % 32) with the clip quirk
+        assert_eq!((c.width(), c.height()), (64, 32));
+        let mut quirks = c.quirks();
+        quirks.clip = true;
+        c.set_quirks(quirks);
+        c.V[0x0] = 70;
+        c.V[0x1] = 40;
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        assert!(c.framebuffer().lit(6, 8));
+        assert!(c.framebuffer().lit(9, 8));
+        assert!(!c.framebuffer().lit(10, 8));
+
+        c.cycle().unwrap();
+        assert_eq!((c.width(), c.height()), (256, 192));
+        assert!(c.argb().is_some());
+        c.cycle().unwrap();
+        assert_eq!((c.width(), c.height()), (64, 32));
+        assert!(!c.framebuffer().lit(6, 8));
+        c.reset();
+        assert_eq!((c.width(), c.height()), (64, 32));
+    }
+
+    #[test]
+    fn superchip() {
+        #[rustfmt::skip]
+        let mut c = platform("megachip", &[
+            0x00, 0xFF, // 128x64 screen
+            0xA3, 0x00, // I = 0x300
+            0xD0, 0x10, // a 16x16 sprite at (V0, V1)
+            0x00, 0xC2, // scroll 2 lines down
+            0x00, 0xFB, // 4 pixels right
+            0x00, 0xFC, // and back
+            0xF1, 0x75, // save V0 and V1
+            0xF1, 0x85, // restore them
+            0xF2, 0x30, // I = the big digit in V2
+            0x00, 0xFE, // 64x32 screen
+        ]);
+        // the columns 0 and 15 of the sprite are lit
+        let sprite: Vec<u8> = (0..16).flat_map(|_| [0x80, 0x01]).collect();
+        c.write_memory(0x300, &sprite).unwrap();
+        c.V[0x0] = 100;
+        c.V[0x1] = 10;
+        c.V[0x2] = 7;
+        c.cycle().unwrap();
+        assert_eq!((c.width(), c.height()), (128, 64));
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        let lit = |c: &Cpu, x, y| c.framebuffer().lit(x, y);
+        assert!(lit(&c, 100, 10) && lit(&c, 115, 25) && !lit(&c, 101, 10));
+        c.cycle().unwrap();
+        assert!(lit(&c, 100, 12) && lit(&c, 115, 27) && !lit(&c, 100, 10));
+        c.cycle().unwrap();
+        assert!(lit(&c, 104, 12) && lit(&c, 119, 27) && !lit(&c, 100, 12));
+        c.cycle().unwrap();
+        assert!(lit(&c, 100, 12) && !lit(&c, 104, 12));
+
+        c.cycle().unwrap();
+        c.V[0x0] = 0;
+        c.V[0x1] = 0;
+        c.cycle().unwrap();
+        assert_eq!(c.registers()[..2], [100, 10]);
+        c.cycle().unwrap();
+        assert_eq!(c.index(), 0x50 + 7 * 10);
+        assert_eq!(c.memory()[c.index() as usize], 0xFF);
+        c.cycle().unwrap();
+        assert_eq!((c.width(), c.height()), (64, 32));
+    }
+
+    #[test]
+    fn machine_code() {
+        let config = MachineConfig::preset("vip-hybrid").unwrap();
+        let mut c = Cpu::with_config(config).unwrap();
+        // 0300 then 0320 which never returns
+        c.load_rom(&[0x03, 0x00, 0x03, 0x20]);
+        #[rustfmt::skip]
+        c.write_memory(0x300, &[
+            0xF8, 0x42, 0x5A,       // LDI 42, STR RA: M(I) = 42
+            0xF8, 0x07, 0x56,       // LDI 07, STR R6: V3 = 07
+            0x9B, 0xBF, 0xF8, 0x01, 0xAF, 0xF8, 0x80, 0x5F, // the pixel (8, 0)
+            0x15, 0x15,             // INC R5 twice: skip the next chip 8 instruction
+            0xD4,                   // SEP R4
+        ]).unwrap();
+        c.write_memory(0x320, &[0x30, 0x20]).unwrap();
+        c.I = 0x350;
+        c.V[0] = 0x03;
+        c.screen.set(0, 0, 1);
+        c.cycle().unwrap();
+        assert_eq!(c.memory()[0x350], 0x42);
+        assert_eq!(c.V[0x3], 0x07);
+        assert_eq!(c.V[0x0], 0x03);
+        assert!(c.screen.lit(0, 0) && c.screen.lit(8, 0) && !c.screen.lit(1, 0));
+        assert_eq!(c.pc, 0x204);
+        assert!(c.machine_cycles > 0);
+
+        c.pc = 0x202;
+        assert_eq!(c.cycle(), Err(Fault::MachineCode(0x320)));
         assert_eq!(c.pc, 0x202);
     }
 
@@ -461,7 +1697,7 @@ mod tests {
     fn opcode_1NNN() {
         let mut c = init();
         c.opcode = 0x1B0B;
-        c.opcode_1NNN();
+        c.opcode_1NNN(0xB0B);
         assert_eq!(c.pc, 0xB0B);
     }
 
@@ -469,7 +1705,7 @@ mod tests {
     fn opcode_2NNN() {
         let mut c = init();
         c.opcode = 0x2B0B;
-        c.opcode_2NNN();
+        c.opcode_2NNN(0xB0B).unwrap();
         assert_eq!(
             c.stack,
             [0x200, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
@@ -483,7 +1719,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x3ABB;
         c.V[0xA] = 0xAA;
-        c.opcode_3XNN();
+        c.opcode_3XNN(0xA, 0xBB);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -492,7 +1728,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x3ABB;
         c.V[0x0A] = 0xBB;
-        c.opcode_3XNN();
+        c.opcode_3XNN(0xA, 0xBB);
         assert_eq!(c.pc, 0x204);
     }
 
@@ -501,7 +1737,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x4ABB;
         c.V[0xA] = 0xAA;
-        c.opcode_4XNN();
+        c.opcode_4XNN(0xA, 0xBB);
         assert_eq!(c.pc, 0x204);
     }
 
@@ -510,7 +1746,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x4ABB;
         c.V[0x0A] = 0xBB;
-        c.opcode_4XNN();
+        c.opcode_4XNN(0xA, 0xBB);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -520,7 +1756,7 @@ mod tests {
         c.opcode = 0x5AB0;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0xBB;
-        c.opcode_5XY0();
+        c.opcode_5XY0(0xA, 0xB);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -530,7 +1766,7 @@ mod tests {
         c.opcode = 0x5AB0;
         c.V[0xA] = 0xBB;
         c.V[0xB] = 0xBB;
-        c.opcode_5XY0();
+        c.opcode_5XY0(0xA, 0xB);
         assert_eq!(c.pc, 0x204);
     }
 
@@ -538,7 +1774,7 @@ mod tests {
     fn opcode_6XNN() {
         let mut c = init();
         c.opcode = 0x6ABB;
-        c.opcode_6XNN();
+        c.opcode_6XNN(0xA, 0xBB);
         assert_eq!(c.V[0xA], 0xBB);
         assert_eq!(c.pc, 0x202);
     }
@@ -548,7 +1784,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x7ABB;
         c.V[0xA] = 0x11;
-        c.opcode_7XNN();
+        c.opcode_7XNN(0xA, 0xBB);
         assert_eq!(c.V[0xA], 0xCC);
         assert_eq!(c.pc, 0x202);
     }
@@ -558,7 +1794,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x7AEE;
         c.V[0xA] = 0x12;
-        c.opcode_7XNN();
+        c.opcode_7XNN(0xA, 0xEE);
         assert_eq!(c.V[0xA], 0x00);
         assert_eq!(c.pc, 0x202);
     }
@@ -569,7 +1805,7 @@ mod tests {
         c.opcode = 0x8AB0;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0xBB;
-        c.opcode_8XY0();
+        c.opcode_8XY0(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xBB);
         assert_eq!(c.pc, 0x202);
     }
@@ -580,7 +1816,7 @@ mod tests {
         c.opcode = 0x8AB1;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0xBB;
-        c.opcode_8XY1();
+        c.opcode_8XY1(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xAA | 0xBB);
         assert_eq!(c.pc, 0x202);
     }
@@ -591,7 +1827,7 @@ mod tests {
         c.opcode = 0x8AB2;
         c.V[0xA] = 0xEE;
         c.V[0xB] = 0x55;
-        c.opcode_8XY2();
+        c.opcode_8XY2(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE & 0x55);
         assert_eq!(c.pc, 0x202);
     }
@@ -602,7 +1838,7 @@ mod tests {
         c.opcode = 0x8AB3;
         c.V[0xA] = 0xEE;
         c.V[0xB] = 0x55;
-        c.opcode_8XY3();
+        c.opcode_8XY3(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE ^ 0x55);
         assert_eq!(c.pc, 0x202);
     }
@@ -613,7 +1849,7 @@ mod tests {
         c.opcode = 0x8AB4;
         c.V[0xA] = 0x11;
         c.V[0xB] = 0xAA;
-        c.opcode_8XY4();
+        c.opcode_8XY4(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xBB);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x202);
@@ -625,7 +1861,7 @@ mod tests {
         c.opcode = 0x8AB4;
         c.V[0xA] = 0x12;
         c.V[0xB] = 0xFF;
-        c.opcode_8XY4();
+        c.opcode_8XY4(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x11);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
@@ -637,13 +1873,13 @@ mod tests {
         c.opcode = 0x8AB4;
         c.V[0xA] = 0x12;
         c.V[0xB] = 0xFF;
-        c.opcode_8XY4();
+        c.opcode_8XY4(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x11);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
 
         c.V[0xB] = 0x22;
-        c.opcode_8XY4();
+        c.opcode_8XY4(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x33);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x204);
@@ -655,7 +1891,7 @@ mod tests {
         c.opcode = 0x8AB5;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0x11;
-        c.opcode_8XY5();
+        c.opcode_8XY5(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x99);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
@@ -667,7 +1903,7 @@ mod tests {
         c.opcode = 0x8AB5;
         c.V[0xA] = 0x10;
         c.V[0xB] = 0x22;
-        c.opcode_8XY5();
+        c.opcode_8XY5(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x202);
@@ -679,13 +1915,13 @@ mod tests {
         c.opcode = 0x8AB5;
         c.V[0xA] = 0x10;
         c.V[0xB] = 0x22;
-        c.opcode_8XY5();
+        c.opcode_8XY5(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x202);
 
         c.V[0xB] = 0x22;
-        c.opcode_8XY5();
+        c.opcode_8XY5(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xCC);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x204);
@@ -696,7 +1932,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x8AB6;
         c.V[0xA] = 0xFF;
-        c.opcode_8XY6();
+        c.opcode_8XY6(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xFF >> 1);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
@@ -708,7 +1944,7 @@ mod tests {
         c.opcode = 0x8AB7;
         c.V[0xA] = 0x11;
         c.V[0xB] = 0xAA;
-        c.opcode_8XY7();
+        c.opcode_8XY7(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x99);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
@@ -720,7 +1956,7 @@ mod tests {
         c.opcode = 0x8AB7;
         c.V[0xA] = 0x22;
         c.V[0xB] = 0x10;
-        c.opcode_8XY7();
+        c.opcode_8XY7(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x202);
@@ -732,13 +1968,13 @@ mod tests {
         c.opcode = 0x8AB7;
         c.V[0xA] = 0x22;
         c.V[0xB] = 0x10;
-        c.opcode_8XY7();
+        c.opcode_8XY7(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEE);
         assert_eq!(c.V[0xF], 0x00);
         assert_eq!(c.pc, 0x202);
 
         c.V[0xB] = 0xFF;
-        c.opcode_8XY7();
+        c.opcode_8XY7(0xA, 0xB);
         assert_eq!(c.V[0xA], 0x11);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x204);
@@ -749,7 +1985,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0x8ABE;
         c.V[0xA] = 0xEF;
-        c.opcode_8XYE();
+        c.opcode_8XYE(0xA, 0xB);
         assert_eq!(c.V[0xA], 0xEF << 1);
         assert_eq!(c.V[0xF], 0x01);
         assert_eq!(c.pc, 0x202);
@@ -761,7 +1997,7 @@ mod tests {
         c.opcode = 0x9AB0;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0xBB;
-        c.opcode_9XY0();
+        c.opcode_9XY0(0xA, 0xB);
         assert_eq!(c.pc, 0x204);
     }
 
@@ -771,7 +2007,7 @@ mod tests {
         c.opcode = 0x9AB0;
         c.V[0xA] = 0xAA;
         c.V[0xB] = 0xAA;
-        c.opcode_9XY0();
+        c.opcode_9XY0(0xA, 0xB);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -779,7 +2015,7 @@ mod tests {
     fn opcode_ANNN() {
         let mut c = init();
         c.opcode = 0xA777;
-        c.opcode_ANNN();
+        c.opcode_ANNN(0x777);
         assert_eq!(c.I, 0x777);
         assert_eq!(c.pc, 0x202);
     }
@@ -789,7 +2025,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0xB777;
         c.V[0x0] = 0x11;
-        c.opcode_BNNN();
+        c.opcode_BNNN(0x777);
         assert_eq!(c.pc, 0x788);
     }
 
@@ -797,7 +2033,7 @@ mod tests {
     fn opcode_CXNN() {
         let mut c = init();
         c.opcode = 0xC7F0; // the right part should be to zero event after the and
-        c.opcode_CXNN();
+        c.opcode_CXNN(0x7, 0xF0);
         assert_eq!(c.V[0x7] & 0x0F, 0x0);
         // can't test a lot more because of random
         assert_eq!(c.pc, 0x202);
@@ -807,9 +2043,9 @@ mod tests {
     fn opcode_DXYN() {
         let mut c = init();
         c.opcode = 0xDABC;
-        c.opcode_DXYN();
+        c.opcode_DXYN(0xA, 0xB, 0xC).unwrap();
         assert_eq!(c.pc, 0x202);
-        assert_eq!(c.draw, true);
+        assert!(c.draw);
         // TODO test more things TODO
     }
 
@@ -819,8 +2055,14 @@ mod tests {
         c.opcode = 0xEA9E;
         c.V[0xA] = 0x07;
         c.key[0x7] = true;
-        c.opcode_EX9E();
+        c.opcode_EX9E(0xA);
         assert_eq!(c.pc, 0x204);
+
+        // the high nibble of VX is ignored
+        c.set_register(0xA, 0x17).unwrap();
+        c.key[0x7] = true;
+        c.opcode_EX9E(0xA);
+        assert_eq!(c.pc, 0x208);
     }
 
     #[test]
@@ -829,7 +2071,7 @@ mod tests {
         c.opcode = 0xEA9E;
         c.V[0xA] = 0x07;
         c.key[0x7] = false;
-        c.opcode_EX9E();
+        c.opcode_EX9E(0xA);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -839,7 +2081,7 @@ mod tests {
         c.opcode = 0xEA9E;
         c.V[0xA] = 0x07;
         c.key[0x7] = true;
-        c.opcode_EXA1();
+        c.opcode_EXA1(0xA);
         assert_eq!(c.pc, 0x202);
     }
 
@@ -849,7 +2091,7 @@ mod tests {
         c.opcode = 0xEA9E; // the right part should be to zero event after the and
         c.V[0xA] = 0x07;
         c.key[0x7] = false;
-        c.opcode_EXA1();
+        c.opcode_EXA1(0xA);
         assert_eq!(c.pc, 0x204);
     }
 
@@ -859,7 +2101,7 @@ mod tests {
         c.opcode = 0xFA07; // the right part should be to zero event after the and
         c.V[0xA] = 0x07;
         c.delay_timer = 0x12;
-        c.opcode_FX07();
+        c.opcode_FX07(0xA);
         assert_eq!(c.V[0xA], 0x12);
         assert_eq!(c.pc, 0x202);
     }
@@ -869,18 +2111,18 @@ mod tests {
         let mut c = init();
         c.opcode = 0xEA9E; // the right part should be to zero event after the and
         c.V[0xA] = 0x07;
-        c.opcode_FX0A();
+        c.opcode_FX0A(0xA);
         assert_eq!(c.V[0xA], 0x07);
         assert_eq!(c.pc, 0x200);
 
         c.key[0x2] = true;
-        c.opcode_FX0A();
+        c.opcode_FX0A(0xA);
         assert_eq!(c.V[0xA], 0x02);
         assert_eq!(c.pc, 0x202);
 
         c.key[0x2] = false;
         c.key[0x8] = true;
-        c.opcode_FX0A();
+        c.opcode_FX0A(0xA);
         assert_eq!(c.V[0xA], 0x08);
         assert_eq!(c.pc, 0x204);
     }
@@ -890,7 +2132,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0xFA15;
         c.V[0xA] = 0x77;
-        c.opcode_FX15();
+        c.opcode_FX15(0xA);
         assert_eq!(c.delay_timer, 0x77);
         assert_eq!(c.pc, 0x202);
     }
@@ -900,7 +2142,7 @@ mod tests {
         let mut c = init();
         c.opcode = 0xFA18;
         c.V[0xA] = 0x77;
-        c.opcode_FX18();
+        c.opcode_FX18(0xA);
         assert_eq!(c.sound_timer, 0x77);
         assert_eq!(c.pc, 0x202);
     }
@@ -911,7 +2153,7 @@ mod tests {
         c.opcode = 0xFA1E;
         c.V[0xA] = 0x11;
         c.I = 0xAA;
-        c.opcode_FX1E();
+        c.opcode_FX1E(0xA);
         assert_eq!(c.I, 0xBB);
         assert_eq!(c.V[0xF], 0x0);
         assert_eq!(c.pc, 0x202);
@@ -920,22 +2162,49 @@ mod tests {
     #[test]
     fn opcode_FX1E_with_carry() {
         let mut c = init();
+        c.quirks.index_overflow = true;
         c.opcode = 0xFA1E;
         c.V[0xA] = 0x23;
         c.I = 0xFFEE;
-        c.opcode_FX1E();
+        c.opcode_FX1E(0xA);
         assert_eq!(c.I, 0x11);
         assert_eq!(c.V[0xF], 0x1);
         assert_eq!(c.pc, 0x202);
     }
 
+    #[test]
+    fn opcode_FX1E_index_overflow() {
+        let mut c = init();
+        c.quirks.index_overflow = true;
+        c.opcode = 0xFA1E;
+        c.V[0xA] = 0x0F;
+        c.V[0xF] = 0x2;
+        c.I = 0xFF0;
+        c.opcode_FX1E(0xA);
+        assert_eq!(c.I, 0xFFF);
+        assert_eq!(c.V[0xF], 0x0);
+
+        // one past the 4ko of memory
+        c.V[0xA] = 0x01;
+        c.opcode_FX1E(0xA);
+        assert_eq!(c.I, 0x1000);
+        assert_eq!(c.V[0xF], 0x1);
+
+        // without the quirk VF is left alone
+        c.quirks.index_overflow = false;
+        c.V[0xF] = 0x2;
+        c.opcode_FX1E(0xA);
+        assert_eq!(c.I, 0x1001);
+        assert_eq!(c.V[0xF], 0x2);
+    }
+
     #[test]
     fn opcode_FX29() {
         let mut c = init();
         c.opcode = 0xFA29;
         c.V[0xA] = 0x11;
         c.I = 0xAA;
-        c.opcode_FX29();
+        c.opcode_FX29(0xA);
         assert_eq!(c.I, 0x55);
         assert_eq!(c.pc, 0x202);
     }
@@ -944,7 +2213,7 @@ mod tests {
     fn opcode_FX33() {
         let mut c = init();
         c.opcode = 0xFA33;
-        c.opcode_FX33();
+        c.opcode_FX33(0xA).unwrap();
         assert_eq!(c.pc, 0x202);
         // TODO do something
     }
@@ -958,15 +2227,15 @@ mod tests {
         c.V[2] = 0x22;
         c.V[3] = 0x33;
         c.I = 0xAA;
-        c.memory[0xAA + 0x4] = 0xFF;
+        c.memory[c.I + 0x4] = 0xFF;
 
-        c.opcode_FX55();
+        c.opcode_FX55(0x3).unwrap();
 
-        assert_eq!(c.memory[0xAA + 0], 0x00);
-        assert_eq!(c.memory[0xAA + 1], 0x11);
-        assert_eq!(c.memory[0xAA + 2], 0x22);
-        assert_eq!(c.memory[0xAA + 3], 0x33);
-        assert_eq!(c.memory[0xAA + 4], 0xFF);
+        assert_eq!(c.memory[c.I], 0x00);
+        assert_eq!(c.memory[c.I + 1], 0x11);
+        assert_eq!(c.memory[c.I + 2], 0x22);
+        assert_eq!(c.memory[c.I + 3], 0x33);
+        assert_eq!(c.memory[c.I + 4], 0xFF);
         assert_eq!(c.I, 0xAA);
         assert_eq!(c.pc, 0x202);
     }
@@ -976,13 +2245,13 @@ mod tests {
         let mut c = init();
         c.opcode = 0xF333;
         c.I = 0xAA;
-        c.memory[c.I + 0] = 0x00;
+        c.memory[c.I] = 0x00;
         c.memory[c.I + 1] = 0x11;
         c.memory[c.I + 2] = 0x22;
         c.memory[c.I + 3] = 0x33;
         c.V[0x4] = 0xFF;
 
-        c.opcode_FX65();
+        c.opcode_FX65(0x3).unwrap();
 
         assert_eq!(c.V[0], 0x00);
         assert_eq!(c.V[1], 0x11);
@@ -993,12 +2262,201 @@ mod tests {
         assert_eq!(c.pc, 0x202);
     }
 
+    #[test]
+    fn reset() {
+        let mut c = init();
+        c.memory.load_rom(&std::fs::read("../games/PONG").unwrap());
+        let rom = c.memory[0x200usize];
+        c.V[0x3] = 0x42;
+        c.I = 0x300;
+        c.pc = 0x250;
+        c.opcode_2NNN(0x300).unwrap();
+        c.memory[0x200usize] = !rom;
+        c.screen.set(12, 0, 1);
+
+        c.reset();
+        assert_eq!(c.V[0x3], 0);
+        assert_eq!(c.I, 0);
+        assert_eq!(c.pc, 0x200);
+        assert_eq!(c.sp, 0);
+        assert_eq!(c.memory[0x200usize], rom);
+        assert!(!c.screen.lit(12, 0));
+        assert!(c.draw);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn stack_trace() {
+        let mut c = init();
+        assert_eq!(c.stack_trace(), "    at 0x200");
+        let symbols = "label main 200\nlabel draw 2D4\nline 2D6 draw.8o:12";
+        c.set_symbols(Symbols::parse(symbols, std::path::Path::new("")).unwrap());
+        c.pc = 0x210;
+        c.opcode_2NNN(0x2D4).unwrap();
+        c.pc += 2;
+        assert_eq!(
+            c.stack_trace(),
+            "    at 0x2D6 draw+0x2 (draw.8o:12)\n    called from 0x210 main+0x10"
+        );
+    }
+
+    #[test]
+    fn faults() {
+        let mut c = init();
+        c.memory.load_rom(&[0x22, 0x00, 0x00, 0xEE, 0xFF, 0xFF]);
+        for depth in 0..16 {
+            c.cycle().unwrap();
+            assert_eq!(c.calls().len(), depth + 1);
+        }
+        assert_eq!(
+            c.calls()[15],
+            Frame {
+                call_site: 0x200,
+                target: 0x200
+            }
+        );
+        assert_eq!(c.cycle(), Err(Fault::StackOverflow));
+        assert_eq!(c.pc, 0x200);
+        assert_eq!(c.sp, 16);
+
+        c.reset();
+        c.pc = 0x202;
+        assert_eq!(c.cycle(), Err(Fault::StackUnderflow));
+        assert_eq!(c.pc, 0x202);
+        c.pc = 0x204;
+        assert_eq!(c.cycle(), Err(Fault::UnknownInstruction(0xFFFF)));
+        assert_eq!(c.pc, 0x204);
+    }
+
+    #[test]
+    fn shadow_stack() {
+        let mut c = init();
+        c.memory
+            .load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0xEE, 0x00, 0xEE]);
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        let frames = [
+            Frame {
+                call_site: 0x200,
+                target: 0x204,
+            },
+            Frame {
+                call_site: 0x204,
+                target: 0x208,
+            },
+        ];
+        assert_eq!(c.calls(), &frames);
+        c.cycle().unwrap();
+        assert_eq!(c.calls(), &frames[..1]);
+
+        c.set_stack(&[0x200, 0x204]).unwrap();
+        assert_eq!(c.calls(), &frames);
+    }
+
+    #[test]
+    fn machine() {
+        let config = MachineConfig {
+            stack_depth: 12,
+            start_address: 0x600,
+            font_address: 0x100,
+            ..MachineConfig::default()
+        };
+        let mut c = Cpu::with_config(config).unwrap();
+        assert_eq!(c.pc(), 0x600);
+        c.load_rom(&[0x26, 0x00, 0xFA, 0x29]);
+        assert_eq!(c.memory()[0x600], 0x26);
+        assert_eq!(c.memory()[0x100], 0xF0);
+        for _ in 0..12 {
+            c.cycle().unwrap();
+        }
+        assert_eq!(c.cycle(), Err(Fault::StackOverflow));
+        assert!(c.set_stack(&[0x600; 13]).is_err());
+
+        c.reset();
+        assert_eq!(c.pc(), 0x600);
+        assert_eq!(c.config(), &config);
+        c.pc = 0x602;
+        c.V[0xA] = 0xA;
+        c.cycle().unwrap();
+        assert_eq!(c.index(), 0x100 + 0xA * 5);
+
+        let config = MachineConfig {
+            stack_depth: 0,
+            ..MachineConfig::default()
+        };
+        assert!(Cpu::with_config(config).is_err());
+    }
+
+    #[test]
+    fn memory_fault() {
+        let small = MachineConfig {
+            memory_size: 0x800,
+            ..MachineConfig::default()
+        };
+        // a jump outside of the memory faults on the next fetch
+        let mut c = Cpu::with_config(small).unwrap();
+        c.load_rom(&[0x19, 0x00]);
+        c.cycle().unwrap();
+        assert_eq!(c.cycle(), Err(Fault::Memory(0x900)));
+        assert_eq!(c.pc(), 0x900);
+
+        // so does a sprite read outside of the memory
+        let mut c = Cpu::with_config(small).unwrap();
+        c.load_rom(&[0xAA, 0x00, 0xD0, 0x05]);
+        c.cycle().unwrap();
+        assert_eq!(c.cycle(), Err(Fault::Memory(0xA00)));
+        assert_eq!(c.pc(), 0x202);
+
+        // the bytes after I must all be in the memory
+        let mut c = init();
+        c.I = 0xFFE;
+        assert_eq!(c.opcode_FX33(0), Err(Fault::Memory(0x1000)));
+        assert_eq!(c.opcode_FX55(2), Err(Fault::Memory(0x1000)));
+        assert_eq!(c.opcode_FX65(1), Ok(()));
+        assert_eq!(c.opcode_DXYN(0, 0, 3), Err(Fault::Memory(0x1000)));
+        assert_eq!(c.pc(), 0x202);
+    }
+
+    #[test]
+    fn vip_timing() {
+        let mut c = init();
+        // LD V0, #01 ; LD V1, #02 ; DRW V0, V1, 1 ; JP #200
+        c.load_rom(&[0x60, 0x01, 0x61, 0x02, 0xD0, 0x11, 0x12, 0x00]);
+        c.set_timers(2, 0);
+        c.set_timing(Timing::Vip);
+        assert_eq!(c.frame_budget(), Some(0));
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x200);
+
+        c.vblank();
+        let budget = timing::VIP_BUDGET as u32;
+        assert_eq!(c.frame_budget(), Some(budget));
+        assert_eq!(c.timers(), (1, 0));
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        let load = timing::vip_cycles(0x6001, false, 0) as u32;
+        assert_eq!(c.frame_budget(), Some(budget - 2 * load));
+        assert_eq!(c.timers(), (1, 0));
+        // the draw waits for the next frame
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x204);
+        assert_eq!(c.frame_budget(), Some(0));
+
+        c.vblank();
+        c.cycle().unwrap();
+        assert_eq!(c.pc, 0x206);
+        let draw = timing::vip_cycles(0xD011, false, 1) as u32;
+        assert_eq!(c.frame_budget(), Some(budget - draw));
+        c.set_timing(Timing::Instruction);
+        assert_eq!(c.frame_budget(), None);
+    }
+
     #[test]
     fn return_after_call() {
         let mut c = init();
         c.opcode = 0x2B0B;
-        c.opcode_2NNN();
-        c.opcode_00EE();
+        c.opcode_2NNN(0xB0B).unwrap();
+        c.opcode_00EE().unwrap();
         assert_eq!(
             c.stack, // we don't clear the stack after returning
             [0x200, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
@@ -1006,4 +2464,117 @@ mod tests {
         assert_eq!(c.sp, 0);
         assert_eq!(c.pc, 0x202);
     }
+
+    #[test]
+    fn inspect() {
+        let mut c = init();
+        c.load_rom(&[0x6A, 0x02, 0xA2, 0x20]);
+        c.cycle().unwrap();
+        c.cycle().unwrap();
+        assert_eq!(c.registers()[0xA], 2);
+        assert_eq!(c.index(), 0x220);
+        assert_eq!(c.pc(), 0x204);
+        assert!(c.stack().is_empty());
+        assert_eq!(&c.memory()[0x200..0x204], &[0x6A, 0x02, 0xA2, 0x20]);
+        assert_eq!(c.framebuffer().len(), c.width() * c.height());
+    }
+
+    #[test]
+    fn mutate() {
+        let mut c = init();
+        assert!(c.set_register(0xF, 1).is_ok());
+        assert!(c.set_register(0x10, 1).is_err());
+        assert_eq!(c.registers()[0xF], 1);
+
+        assert!(c.set_pc(0x300).is_ok());
+        assert!(c.set_pc(0xFFF).is_err());
+        assert!(c.set_pc(usize::MAX).is_err());
+        assert_eq!(c.pc(), 0x300);
+
+        assert!(c.set_index(0xFFF).is_ok());
+        assert!(c.set_index(0x1000).is_err());
+        assert_eq!(c.index(), 0xFFF);
+
+        assert!(c.set_stack(&[0x200, 0x250]).is_ok());
+        assert!(c.set_stack(&[0; 17]).is_err());
+        assert_eq!(c.stack(), &[0x200, 0x250]);
+
+        assert!(c.write_memory(0xFFE, &[1, 2]).is_ok());
+        assert!(c.write_memory(0xFFF, &[1, 2]).is_err());
+        assert_eq!(&c.memory()[0xFFE..], &[1, 2]);
+
+        c.update();
+        assert!(c.set_pixel(63, 31, true).is_ok());
+        assert!(c.set_pixel(64, 0, true).is_err());
+        assert!(c.update().unwrap().lit(63, 31));
+    }
+
+    #[test]
+    fn quirks() {
+        let mut c = init();
+        c.set_quirks(Quirks::preset("cosmac").unwrap());
+        c.V[0x1] = 0x0F;
+        c.V[0x2] = 0x81;
+        c.V[0xF] = 1;
+        c.opcode_8XY1(0x1, 0x2);
+        assert_eq!(c.V[0xF], 0);
+
+        c.opcode_8XY6(0x3, 0x2);
+        assert_eq!((c.V[0x3], c.V[0xF]), (0x40, 1));
+        c.opcode_8XYE(0x3, 0x2);
+        assert_eq!((c.V[0x3], c.V[0xF]), (0x02, 1));
+
+        c.I = 0x300;
+        c.opcode_FX55(0x2).unwrap();
+        assert_eq!(c.I, 0x303);
+        c.opcode_FX65(0x2).unwrap();
+        assert_eq!(c.I, 0x306);
+
+        // an 8x1 sprite at the right edge lose its last pixels
+        c.V[0x4] = 60;
+        c.V[0x5] = 0;
+        c.I = 0x300;
+        c.memory[0x300usize] = 0xFF;
+        c.opcode_DXYN(0x4, 0x5, 1).unwrap();
+        assert!(c.screen.lit(63, 0));
+        assert!(!c.screen.lit(0, 1));
+
+        c.set_quirks(Quirks::preset("schip").unwrap());
+        c.V[0x2] = 0x10;
+        c.opcode_BNNN(0x220);
+        assert_eq!(c.pc, 0x230);
+    }
+
+    #[test]
+    fn seed() {
+        let run = |seed| {
+            let mut c = init();
+            c.set_seed(seed);
+            (0..8)
+                .map(|_| {
+                    c.opcode_CXNN(0x0, 0xFF);
+                    c.V[0x0]
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn inputs() {
+        let mut c = init();
+        c.load_rom(&[0x60, 0x00, 0x60, 0x00, 0x60, 0x00]);
+        c.set_inputs(Some(Inputs::parse("1 down 5").unwrap()));
+        assert!(!c.threadable());
+        c.key[0x3] = true;
+        c.cycle().unwrap();
+        assert_eq!(c.key, [false; 16]);
+        c.cycle().unwrap();
+        assert!(c.key[0x5]);
+
+        c.reset();
+        c.cycle().unwrap();
+        assert!(!c.key[0x5]);
+    }
 }
@@ -0,0 +1,154 @@
+/// A chip8 instruction with its operands already extracted, the variants
+/// are named after the opcodes they come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Op00E0,
+    Op00EE,
+    Op0NNN(u16),
+    Op1NNN(u16),
+    Op2NNN(u16),
+    Op3XNN(u8, u8),
+    Op4XNN(u8, u8),
+    Op5XY0(u8, u8),
+    Op6XNN(u8, u8),
+    Op7XNN(u8, u8),
+    Op8XY0(u8, u8),
+    Op8XY1(u8, u8),
+    Op8XY2(u8, u8),
+    Op8XY3(u8, u8),
+    Op8XY4(u8, u8),
+    Op8XY5(u8, u8),
+    Op8XY6(u8, u8),
+    Op8XY7(u8, u8),
+    Op8XYE(u8, u8),
+    Op9XY0(u8, u8),
+    OpANNN(u16),
+    OpBNNN(u16),
+    OpCXNN(u8, u8),
+    OpDXYN(u8, u8, u8),
+    OpEX9E(u8),
+    OpEXA1(u8),
+    OpFX07(u8),
+    OpFX0A(u8),
+    OpFX15(u8),
+    OpFX18(u8),
+    OpFX1E(u8),
+    OpFX29(u8),
+    OpFX33(u8),
+    OpFX55(u8),
+    OpFX65(u8),
+    Unknown(u16),
+}
+
+/// Decode an opcode of the original chip8, the instructions of the
+/// variants are handled before by the cpu.
+pub const fn decode(opcode: u16) -> Instruction {
+    use Instruction::*;
+
+    let x = (opcode >> 8 & 0xF) as u8;
+    let y = (opcode >> 4 & 0xF) as u8;
+    let n = (opcode & 0xF) as u8;
+    let nn = opcode as u8;
+    let nnn = opcode & 0xFFF;
+    match (opcode >> 12, n) {
+        (0x0, _) => match nnn {
+            0x0E0 => Op00E0,
+            0x0EE => Op00EE,
+            _ => Op0NNN(nnn),
+        },
+        (0x1, _) => Op1NNN(nnn),
+        (0x2, _) => Op2NNN(nnn),
+        (0x3, _) => Op3XNN(x, nn),
+        (0x4, _) => Op4XNN(x, nn),
+        (0x5, 0x0) => Op5XY0(x, y),
+        (0x6, _) => Op6XNN(x, nn),
+        (0x7, _) => Op7XNN(x, nn),
+        (0x8, 0x0) => Op8XY0(x, y),
+        (0x8, 0x1) => Op8XY1(x, y),
+        (0x8, 0x2) => Op8XY2(x, y),
+        (0x8, 0x3) => Op8XY3(x, y),
+        (0x8, 0x4) => Op8XY4(x, y),
+        (0x8, 0x5) => Op8XY5(x, y),
+        (0x8, 0x6) => Op8XY6(x, y),
+        (0x8, 0x7) => Op8XY7(x, y),
+        (0x8, 0xE) => Op8XYE(x, y),
+        (0x9, 0x0) => Op9XY0(x, y),
+        (0xA, _) => OpANNN(nnn),
+        (0xB, _) => OpBNNN(nnn),
+        (0xC, _) => OpCXNN(x, nn),
+        (0xD, _) => OpDXYN(x, y, n),
+        (0xE, _) => match nn {
+            0x9E => OpEX9E(x),
+            0xA1 => OpEXA1(x),
+            _ => Unknown(opcode),
+        },
+        (0xF, _) => match nn {
+            0x07 => OpFX07(x),
+            0x0A => OpFX0A(x),
+            0x15 => OpFX15(x),
+            0x18 => OpFX18(x),
+            0x1E => OpFX1E(x),
+            0x29 => OpFX29(x),
+            0x33 => OpFX33(x),
+            0x55 => OpFX55(x),
+            0x65 => OpFX65(x),
+            _ => Unknown(opcode),
+        },
+        _ => Unknown(opcode),
+    }
+}
+
+/// every opcode decoded once at compile time, so the cpu only does an
+/// indexing instead of comparing the opcode with each pattern
+#[cfg(feature = "decode-table")]
+static TABLE: [Instruction; 0x10000] = {
+    let mut table = [Instruction::Unknown(0); 0x10000];
+    let mut opcode = 0;
+    while opcode < table.len() {
+        table[opcode] = decode(opcode as u16);
+        opcode += 1;
+    }
+    table
+};
+
+/// same as `decode` but from the precomputed table
+#[cfg(feature = "decode-table")]
+#[inline]
+pub fn lookup(opcode: u16) -> Instruction {
+    TABLE[opcode as usize]
+}
+
+/// without the `decode-table` feature it's only `decode`
+#[cfg(not(feature = "decode-table"))]
+#[inline]
+pub fn lookup(opcode: u16) -> Instruction {
+    decode(opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        use Instruction::*;
+        assert_eq!(lookup(0x00E0), Op00E0);
+        assert_eq!(lookup(0x00EE), Op00EE);
+        assert_eq!(lookup(0x0123), Op0NNN(0x123));
+        assert_eq!(lookup(0x5AB0), Op5XY0(0xA, 0xB));
+        assert_eq!(lookup(0x5AB1), Unknown(0x5AB1));
+        assert_eq!(lookup(0x8ABE), Op8XYE(0xA, 0xB));
+        assert_eq!(lookup(0x8AB8), Unknown(0x8AB8));
+        assert_eq!(lookup(0xD12F), OpDXYN(1, 2, 0xF));
+        assert_eq!(lookup(0xE3A1), OpEXA1(3));
+        assert_eq!(lookup(0xF465), OpFX65(4));
+        assert_eq!(lookup(0xF4FF), Unknown(0xF4FF));
+    }
+
+    #[test]
+    fn table() {
+        for opcode in 0..=u16::MAX {
+            assert_eq!(lookup(opcode), super::decode(opcode));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(feature = "std")]
+use crate::symbols::Symbols;
+use alloc::{format, string::String};
+use bitmatch::bitmatch;
+
+/// Return the mnemonic of an instruction, with the syntax of the Cowgod's
+/// chip8 technical reference. The unknown instructions are displayed as
+/// raw data with `DW`.
+#[bitmatch]
+pub fn disassemble(opcode: u16) -> String {
+    #[bitmatch]
+    match opcode {
+        "0000_0000_1110_1110" => String::from("RET"),
+        "0000_0000_1110_0000" => String::from("CLS"),
+        "0000_nnnn_nnnn_nnnn" => format!("SYS #{:03X}", n),
+        "0001_nnnn_nnnn_nnnn" => format!("JP #{:03X}", n),
+        "0010_nnnn_nnnn_nnnn" => format!("CALL #{:03X}", n),
+        "0011_xxxx_nnnn_nnnn" => format!("SE V{:X}, #{:02X}", x, n),
+        "0100_xxxx_nnnn_nnnn" => format!("SNE V{:X}, #{:02X}", x, n),
+        "0101_xxxx_yyyy_0000" => format!("SE V{:X}, V{:X}", x, y),
+        "0110_xxxx_nnnn_nnnn" => format!("LD V{:X}, #{:02X}", x, n),
+        "0111_xxxx_nnnn_nnnn" => format!("ADD V{:X}, #{:02X}", x, n),
+        "1000_xxxx_yyyy_0000" => format!("LD V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0001" => format!("OR V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0010" => format!("AND V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0011" => format!("XOR V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0100" => format!("ADD V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0101" => format!("SUB V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0110" => format!("SHR V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_0111" => format!("SUBN V{:X}, V{:X}", x, y),
+        "1000_xxxx_yyyy_1110" => format!("SHL V{:X}, V{:X}", x, y),
+        "1001_xxxx_yyyy_0000" => format!("SNE V{:X}, V{:X}", x, y),
+        "1010_nnnn_nnnn_nnnn" => format!("LD I, #{:03X}", n),
+        "1011_nnnn_nnnn_nnnn" => format!("JP V0, #{:03X}", n),
+        "1100_xxxx_nnnn_nnnn" => format!("RND V{:X}, #{:02X}", x, n),
+        "1101_xxxx_yyyy_nnnn" => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        "1110_xxxx_1001_1110" => format!("SKP V{:X}", x),
+        "1110_xxxx_1010_0001" => format!("SKNP V{:X}", x),
+        "1111_xxxx_0000_0111" => format!("LD V{:X}, DT", x),
+        "1111_xxxx_0000_1010" => format!("LD V{:X}, K", x),
+        "1111_xxxx_0001_0101" => format!("LD DT, V{:X}", x),
+        "1111_xxxx_0001_1000" => format!("LD ST, V{:X}", x),
+        "1111_xxxx_0001_1110" => format!("ADD I, V{:X}", x),
+        "1111_xxxx_0010_1001" => format!("LD F, V{:X}", x),
+        "1111_xxxx_0011_0011" => format!("LD B, V{:X}", x),
+        "1111_xxxx_0101_0101" => format!("LD [I], V{:X}", x),
+        "1111_xxxx_0110_0101" => format!("LD V{:X}, [I]", x),
+        "aaaa_aaaa_aaaa_aaaa" => format!("DW #{:04X}", a),
+    }
+}
+
+/// A line of a disassembly listing at `address`: the instruction with the
+/// label of its target instead of the address, or the bytes with `DB` when
+/// the symbols say it's data.
+#[cfg(feature = "std")]
+pub fn listing(memory: &[u8], address: usize, symbols: &Symbols) -> String {
+    let byte = |a: usize| memory.get(a).copied().unwrap_or(0);
+    if symbols.is_data(address) {
+        return match symbols.is_data(address + 1) {
+            true => format!("DB #{:02X}, #{:02X}", byte(address), byte(address + 1)),
+            false => format!("DB #{:02X}", byte(address)),
+        };
+    }
+    symbolic(
+        (byte(address) as u16) << 8 | byte(address + 1) as u16,
+        symbols,
+    )
+}
+
+/// The mnemonic of an instruction with the label of its target instead of
+/// the address
+#[cfg(feature = "std")]
+pub fn symbolic(opcode: u16, symbols: &Symbols) -> String {
+    let mnemonic = disassemble(opcode);
+    let target = (opcode & 0x0FFF) as usize;
+    match (opcode >> 12, symbols.label(target)) {
+        (0x0 | 0x1 | 0x2 | 0xA | 0xB, Some(label)) => {
+            mnemonic.replace(&format!("#{:03X}", target), label)
+        }
+        _ => mnemonic,
+    }
+}
+
+/// Number of bytes starting at I read or written by an instruction,
+/// 0 if the instruction doesn't use I.
+#[bitmatch]
+pub fn index_access(opcode: u16) -> usize {
+    #[bitmatch]
+    match opcode {
+        "1101_????_????_nnnn" => n as usize,
+        "1111_????_0011_0011" => 3,
+        "1111_xxxx_0101_0101" => x as usize + 1,
+        "1111_xxxx_0110_0101" => x as usize + 1,
+        "????_????_????_????" => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(disassemble(0x00E0), "CLS");
+        assert_eq!(disassemble(0x00EE), "RET");
+        assert_eq!(disassemble(0x1228), "JP #228");
+        assert_eq!(disassemble(0x6A02), "LD VA, #02");
+        assert_eq!(disassemble(0x8AB4), "ADD VA, VB");
+        assert_eq!(disassemble(0xD125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xF265), "LD V2, [I]");
+        assert_eq!(disassemble(0x5121), "DW #5121");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn symbolic() {
+        let symbols = "label draw 204\nlabel sprite 208\ndata 208 20A";
+        let symbols = Symbols::parse(symbols, std::path::Path::new("")).unwrap();
+        let mut memory = vec![0; 0x200];
+        memory.extend_from_slice(&[
+            0x22, 0x04, 0xA2, 0x08, 0x12, 0x06, 0x00, 0xEE, 0xF0, 0x90, 0xF0,
+        ]);
+        assert_eq!(listing(&memory, 0x200, &symbols), "CALL draw");
+        assert_eq!(listing(&memory, 0x202, &symbols), "LD I, sprite");
+        assert_eq!(listing(&memory, 0x204, &symbols), "JP #206");
+        assert_eq!(listing(&memory, 0x206, &symbols), "RET");
+        assert_eq!(listing(&memory, 0x208, &symbols), "DB #F0, #90");
+        assert_eq!(listing(&memory, 0x20A, &symbols), "DB #F0");
+    }
+
+    #[test]
+    fn index() {
+        assert_eq!(index_access(0xD125), 5);
+        assert_eq!(index_access(0xF233), 3);
+        assert_eq!(index_access(0xF255), 3);
+        assert_eq!(index_access(0x6A02), 0);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::framebuffer::Framebuffer;
+use alloc::{format, string::String, vec, vec::Vec};
+use core::str::FromStr;
+
+/// How long a pixel stay visible after being switched off.
+/// A frame is every time the chip8 draw the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Persistence {
+    /// no filter, the screen is displayed as is
+    Off,
+    /// a pixel is fully lit if it was lit in any of the last N frames
+    Frames(u8),
+    /// each frame the pixel keep this ratio (0.0 to 1.0) of its intensity
+    Decay(f32),
+}
+
+/// Parse `off`, `frames:N` or `decay:RATIO`
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid persistence `{}`, expected off, frames:N or decay:RATIO",
+                s
+            )
+        };
+        let mut split = s.splitn(2, ':');
+        match (split.next(), split.next()) {
+            (Some("off"), None) => Ok(Persistence::Off),
+            (Some("frames"), Some(n)) => match n.parse() {
+                Ok(n) if n > 0 => Ok(Persistence::Frames(n)),
+                _ => Err(err()),
+            },
+            (Some("decay"), Some(ratio)) => match ratio.parse() {
+                Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(Persistence::Decay(ratio)),
+                _ => Err(err()),
+            },
+            _ => Err(err()),
+        }
+    }
+}
+
+/// Phosphor persistence filter.
+/// The chip8 games XOR their sprites to move them so a sprite is often
+/// erased in one frame and drawn back in the next one which cause a lot of
+/// flickering. This filter blend the last frames to hide it.
+pub struct Phosphor {
+    persistence: Persistence,
+    age: Vec<u8>,           // number of frames since a pixel was lit
+    intensity: Framebuffer, // 0 for a switched off pixel, 255 for a lit pixel
+}
+
+impl Phosphor {
+    /// a filter for a screen of `width` x `height` pixels
+    pub fn new(persistence: Persistence, width: usize, height: usize) -> Self {
+        Phosphor {
+            persistence,
+            age: vec![u8::MAX; width * height],
+            intensity: Framebuffer::new(width, height, 8),
+        }
+    }
+
+    pub fn persistence(&self) -> Persistence {
+        self.persistence
+    }
+
+    /// Push a new frame in the filter and return the intensity of every
+    /// pixel as the index of its color, see `Palette::shade`. Any color of
+    /// the screen is a lit pixel. The filter starts over when the screen
+    /// changes size.
+    pub fn apply(&mut self, screen: &Framebuffer) -> &Framebuffer {
+        let (width, height) = (screen.width(), screen.height());
+        if (width, height) != (self.intensity.width(), self.intensity.height()) {
+            *self = Phosphor::new(self.persistence, width, height);
+        }
+        let pixels = self.age.iter_mut().zip(screen.pixels().iter());
+        for (i, (age, pixel)) in pixels.enumerate() {
+            let lit = *pixel != 0;
+            *age = match lit {
+                true => 0,
+                false => age.saturating_add(1),
+            };
+            let (x, y) = (i % width, i / width);
+            // one region per line, to only redraw the lines which changed
+            if x == 0 {
+                self.intensity.split();
+            }
+            let intensity = match self.persistence {
+                Persistence::Off => lit as u8 * u8::MAX,
+                Persistence::Frames(n) if *age < n => u8::MAX,
+                Persistence::Frames(_) => 0,
+                Persistence::Decay(_) if lit => u8::MAX,
+                Persistence::Decay(ratio) => (self.intensity.get(x, y) as f32 * ratio) as u8,
+            };
+            self.intensity.set(x, y, intensity);
+        }
+        self.intensity.present();
+        &self.intensity
+    }
+
+    /// return the intensity computed by the last call to `apply`
+    pub fn intensity(&self) -> &Framebuffer {
+        &self.intensity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_persistence() {
+        assert_eq!("off".parse(), Ok(Persistence::Off));
+        assert_eq!("frames:2".parse(), Ok(Persistence::Frames(2)));
+        assert_eq!("decay:0.5".parse(), Ok(Persistence::Decay(0.5)));
+        assert!("frames:0".parse::<Persistence>().is_err());
+        assert!("decay:2".parse::<Persistence>().is_err());
+        assert!("decay".parse::<Persistence>().is_err());
+    }
+
+    /// apply a frame of two pixels and return their intensity
+    fn apply(filter: &mut Phosphor, lit: [bool; 2]) -> [u8; 2] {
+        let mut screen = Framebuffer::new(2, 1, 1);
+        screen.set(0, 0, lit[0] as u8);
+        screen.set(1, 0, lit[1] as u8);
+        let intensity = filter.apply(&screen);
+        [intensity.get(0, 0), intensity.get(1, 0)]
+    }
+
+    #[test]
+    fn off() {
+        let mut filter = Phosphor::new(Persistence::Off, 2, 1);
+        assert_eq!(apply(&mut filter, [true, false]), [255, 0]);
+        assert_eq!(apply(&mut filter, [false, true]), [0, 255]);
+    }
+
+    #[test]
+    fn last_two_frames() {
+        let mut filter = Phosphor::new(Persistence::Frames(2), 2, 1);
+        assert_eq!(apply(&mut filter, [true, false]), [255, 0]);
+        assert_eq!(apply(&mut filter, [false, false]), [255, 0]);
+        assert_eq!(apply(&mut filter, [false, false]), [0, 0]);
+    }
+
+    #[test]
+    fn decay() {
+        let mut filter = Phosphor::new(Persistence::Decay(0.5), 2, 1);
+        assert_eq!(apply(&mut filter, [true, false]), [255, 0]);
+        assert_eq!(apply(&mut filter, [false, true]), [127, 255]);
+        assert_eq!(apply(&mut filter, [false, false]), [63, 127]);
+        assert_eq!(filter.intensity().pixels(), &[63, 127]);
+    }
+
+    #[test]
+    fn resize() {
+        let mut filter = Phosphor::new(Persistence::Frames(2), 2, 1);
+        assert_eq!(apply(&mut filter, [true, true]), [255, 255]);
+        // the pixels lit before don't persist on the new screen
+        let mut screen = Framebuffer::new(1, 2, 1);
+        screen.set(0, 1, 1);
+        let intensity = filter.apply(&screen);
+        assert_eq!((intensity.width(), intensity.height()), (1, 2));
+        assert_eq!(intensity.pixels(), &[0, 255]);
+        assert_eq!(intensity.changed(), Some(intensity.bounds()));
+    }
+}
@@ -0,0 +1,464 @@
+use crate::palette::Color;
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+/// past this number of regions in a frame they are merged in one
+const MAX_REGIONS: usize = 32;
+
+/// A rectangle of pixels on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// the smallest rectangle containing both
+    pub fn union(self, other: Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    /// the lines covered by the rectangle
+    pub fn lines(&self) -> Range<usize> {
+        self.y..self.y + self.height
+    }
+}
+
+/// The screen of a machine, line by line.
+/// Each pixel is the index of its color in the palette, made from one bit
+/// per plane, so on a monochrome screen a pixel is 0 or 1.
+/// The pixels changed since the last frame are tracked to only redraw them,
+/// as one region per operation: a sprite, a clear or a scroll. The pixels
+/// changed one by one are added to the region of the current operation
+/// until `split` starts a new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    planes: u8,
+    pixels: Vec<u8>,
+    dirty: Vec<Rect>,   // changed since the last call to `present`
+    open: bool,         // the last dirty region is the current operation
+    changed: Vec<Rect>, // changed in the last frame
+}
+
+impl Framebuffer {
+    /// A switched off screen, there are 1 to 8 planes.
+    /// The whole screen is dirty so the first frame is fully drawn.
+    pub fn new(width: usize, height: usize, planes: u8) -> Self {
+        assert!((1..=8).contains(&planes), "{} planes", planes);
+        Framebuffer {
+            width,
+            height,
+            planes,
+            pixels: vec![0; width * height],
+            dirty: vec![Rect::new(0, 0, width, height)],
+            open: false,
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// number of pixels
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// the color index of the pixel at (x, y), which must be on the screen
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[x + y * self.width]
+    }
+
+    /// true if the pixel has a color in any of the planes
+    pub fn lit(&self, x: usize, y: usize) -> bool {
+        self.get(x, y) != 0
+    }
+
+    /// the bits of the index above the number of planes are dropped
+    pub fn set(&mut self, x: usize, y: usize, index: u8) {
+        let index = index & self.mask();
+        let pixel = &mut self.pixels[x + y * self.width];
+        if *pixel != index {
+            *pixel = index;
+            self.touch(x, y);
+        }
+    }
+
+    /// Switch the pixel in the `planes` given as a mask, like a sprite drawn
+    /// with a XOR. Returns true if one of them was lit and is now off.
+    pub fn flip(&mut self, x: usize, y: usize, planes: u8) -> bool {
+        let planes = planes & self.mask();
+        let pixel = &mut self.pixels[x + y * self.width];
+        let erased = *pixel & planes != 0;
+        *pixel ^= planes;
+        if planes != 0 {
+            self.touch(x, y);
+        }
+        erased
+    }
+
+    /// switch off every pixel
+    pub fn clear(&mut self) {
+        self.pixels.iter_mut().for_each(|p| *p = 0);
+        self.mark(self.bounds());
+    }
+
+    /// Move the pixels `n` lines up, the lines at the bottom are switched
+    /// off. A negative `n` scrolls down.
+    pub fn scroll(&mut self, n: isize) {
+        let shift = n.unsigned_abs().min(self.height) * self.width;
+        let len = self.pixels.len();
+        match n >= 0 {
+            true => {
+                self.pixels.copy_within(shift.., 0);
+                self.pixels[len - shift..].iter_mut().for_each(|p| *p = 0);
+            }
+            false => {
+                self.pixels.copy_within(..len - shift, shift);
+                self.pixels[..shift].iter_mut().for_each(|p| *p = 0);
+            }
+        }
+        if shift != 0 {
+            self.mark(self.bounds());
+        }
+    }
+
+    /// Move the pixels `n` columns left, the columns on the right are
+    /// switched off. A negative `n` scrolls right.
+    pub fn scroll_columns(&mut self, n: isize) {
+        let shift = n.unsigned_abs().min(self.width);
+        let width = self.width;
+        for line in self.pixels.chunks_mut(width) {
+            match n >= 0 {
+                true => {
+                    line.copy_within(shift.., 0);
+                    line[width - shift..].iter_mut().for_each(|p| *p = 0);
+                }
+                false => {
+                    line.copy_within(..width - shift, shift);
+                    line[..shift].iter_mut().for_each(|p| *p = 0);
+                }
+            }
+        }
+        if shift != 0 {
+            self.mark(self.bounds());
+        }
+    }
+
+    /// the whole screen
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    fn mask(&self) -> u8 {
+        (0xFFu16 >> (8 - self.planes)) as u8
+    }
+
+    /// remember that the pixels in `rect` changed, as an operation of its own
+    pub fn mark(&mut self, rect: Rect) {
+        self.split();
+        self.push(rect);
+    }
+
+    /// the next pixels changed are in a new region
+    pub fn split(&mut self) {
+        self.open = false;
+    }
+
+    /// add a pixel to the region of the current operation
+    fn touch(&mut self, x: usize, y: usize) {
+        let pixel = Rect::new(x, y, 1, 1);
+        match self.dirty.last_mut() {
+            Some(last) if self.open => *last = last.union(pixel),
+            _ => {
+                self.push(pixel);
+                self.open = true;
+            }
+        }
+    }
+
+    fn push(&mut self, rect: Rect) {
+        if self.dirty.len() >= MAX_REGIONS {
+            let union = union(&self.dirty).unwrap().union(rect);
+            self.dirty = vec![union];
+        } else {
+            self.dirty.push(rect);
+        }
+    }
+
+    /// the pixels changed since the last frame, `None` if nothing changed
+    pub fn dirty(&self) -> Option<Rect> {
+        union(&self.dirty)
+    }
+
+    /// end the current frame, what changed in it is given by `regions`
+    pub fn present(&mut self) {
+        self.changed = core::mem::take(&mut self.dirty);
+        self.open = false;
+    }
+
+    /// the regions changed in the last frame, they can overlap
+    pub fn regions(&self) -> &[Rect] {
+        &self.changed
+    }
+
+    /// all the pixels which changed in the last frame, `None` if nothing
+    /// changed
+    pub fn changed(&self) -> Option<Rect> {
+        union(&self.changed)
+    }
+
+    /// the lines changed in the last frame, the overlapping regions are
+    /// merged and the ranges are sorted
+    pub fn changed_lines(&self) -> Vec<Range<usize>> {
+        let mut lines: Vec<Range<usize>> = self.changed.iter().map(Rect::lines).collect();
+        lines.sort_by_key(|range| range.start);
+        let mut merged: Vec<Range<usize>> = Vec::new();
+        for range in lines {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// the pixels, a line at a time
+    pub fn lines(&self) -> impl Iterator<Item = &[u8]> {
+        self.pixels.chunks(self.width.max(1))
+    }
+
+    /// the coordinates and color index of every pixel
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let width = self.width.max(1);
+        let pixels = self.pixels.iter().enumerate();
+        pixels.map(move |(i, p)| (i % width, i / width, *p))
+    }
+
+    /// write the pixels as `0xAARRGGBB` in `output`, `color` gives the color
+    /// of an index, usually `Palette::color` or `Palette::shade`
+    pub fn to_argb(&self, color: impl Fn(u8) -> Color, output: &mut [u32]) {
+        self.lines_to_argb(0..self.height, color, output)
+    }
+
+    /// only convert the `lines`, the rest of `output` is left as it is
+    pub fn lines_to_argb(
+        &self,
+        lines: Range<usize>,
+        color: impl Fn(u8) -> Color,
+        output: &mut [u32],
+    ) {
+        let range = self.pixels_of(lines);
+        let pixels = self.pixels[range.clone()].iter();
+        for (argb, pixel) in output[range].iter_mut().zip(pixels) {
+            *argb = color(*pixel).to_argb();
+        }
+    }
+
+    /// write the pixels as 4 bytes `r, g, b, a` in `output`
+    pub fn to_rgba(&self, color: impl Fn(u8) -> Color, output: &mut [u8]) {
+        self.lines_to_rgba(0..self.height, color, output)
+    }
+
+    /// only convert the `lines`, the rest of `output` is left as it is
+    pub fn lines_to_rgba(
+        &self,
+        lines: Range<usize>,
+        color: impl Fn(u8) -> Color,
+        output: &mut [u8],
+    ) {
+        let range = self.pixels_of(lines);
+        let pixels = self.pixels[range.clone()].iter();
+        let output = output[range.start * 4..range.end * 4].chunks_mut(4);
+        for (rgba, pixel) in output.zip(pixels) {
+            rgba.copy_from_slice(&color(*pixel).to_rgba());
+        }
+    }
+
+    /// the pixels of `lines`, without the ones below the screen
+    fn pixels_of(&self, lines: Range<usize>) -> Range<usize> {
+        let end = lines.end.min(self.height);
+        lines.start.min(end) * self.width..end * self.width
+    }
+
+    /// the color of every pixel, for the screenshots
+    pub fn to_colors(&self, color: impl Fn(u8) -> Color) -> Vec<Color> {
+        self.pixels.iter().map(|pixel| color(*pixel)).collect()
+    }
+}
+
+/// the smallest rectangle containing all of them
+fn union(regions: &[Rect]) -> Option<Rect> {
+    let first = *regions.first()?;
+    Some(regions.iter().fold(first, |union, rect| union.union(*rect)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flip() {
+        let mut screen = Framebuffer::new(8, 4, 1);
+        assert!(!screen.flip(1, 2, 1));
+        assert!(screen.lit(1, 2));
+        assert!(screen.flip(1, 2, 1));
+        assert!(!screen.lit(1, 2));
+
+        // the planes which don't exist are ignored
+        let mut screen = Framebuffer::new(8, 4, 2);
+        screen.set(0, 0, 0xFF);
+        assert_eq!(screen.get(0, 0), 3);
+        assert!(screen.flip(0, 0, 1));
+        assert_eq!(screen.get(0, 0), 2);
+        assert!(!screen.flip(0, 0, 4));
+    }
+
+    #[test]
+    fn dirty() {
+        let mut screen = Framebuffer::new(64, 32, 1);
+        assert_eq!(screen.dirty(), Some(screen.bounds()));
+        screen.present();
+        screen.set(3, 4, 1);
+        screen.flip(10, 2, 1);
+        // setting a pixel to its color doesn't change anything
+        screen.set(60, 30, 0);
+        assert_eq!(screen.changed(), Some(screen.bounds()));
+        screen.present();
+        assert_eq!(screen.changed(), Some(Rect::new(3, 2, 8, 3)));
+        assert!(screen.changed().unwrap().contains(10, 4));
+        screen.present();
+        assert_eq!(screen.changed(), None);
+        screen.clear();
+        screen.present();
+        assert_eq!(screen.regions(), [screen.bounds()]);
+    }
+
+    #[test]
+    fn regions() {
+        let mut screen = Framebuffer::new(64, 32, 1);
+        screen.present();
+        // two sprites
+        screen.flip(0, 0, 1);
+        screen.flip(7, 4, 1);
+        screen.split();
+        screen.flip(40, 20, 1);
+        screen.flip(41, 21, 1);
+        screen.present();
+        assert_eq!(
+            screen.regions(),
+            [Rect::new(0, 0, 8, 5), Rect::new(40, 20, 2, 2)]
+        );
+        assert_eq!(screen.changed_lines(), [0..5, 20..22]);
+
+        // too many regions are merged
+        for i in 0..MAX_REGIONS + 1 {
+            screen.mark(Rect::new(i, 2, 1, 1));
+        }
+        screen.mark(Rect::new(0, 4, 1, 1));
+        screen.present();
+        assert_eq!(screen.regions().len(), 2);
+        assert_eq!(screen.changed(), Some(Rect::new(0, 2, MAX_REGIONS + 1, 3)));
+        assert_eq!(screen.changed_lines(), [2..3, 4..5]);
+    }
+
+    #[test]
+    fn scroll() {
+        let mut screen = Framebuffer::new(4, 4, 1);
+        screen.set(1, 1, 1);
+        screen.scroll(1);
+        assert!(screen.lit(1, 0) && !screen.lit(1, 1));
+        screen.scroll(-2);
+        assert!(screen.lit(1, 2) && !screen.lit(1, 0));
+        screen.scroll(4);
+        assert!(screen.pixels().iter().all(|p| *p == 0));
+
+        screen.set(1, 1, 1);
+        screen.scroll_columns(1);
+        assert!(screen.lit(0, 1) && !screen.lit(1, 1));
+        screen.scroll_columns(-3);
+        assert!(screen.lit(3, 1) && !screen.lit(0, 1));
+        screen.scroll_columns(-1);
+        assert!(screen.pixels().iter().all(|p| *p == 0));
+    }
+
+    #[test]
+    fn convert() {
+        let mut screen = Framebuffer::new(2, 2, 1);
+        screen.set(1, 1, 1);
+        let color = |index: u8| Color::rgb(index * 255, 0, 0);
+        let mut argb = [0; 4];
+        screen.to_argb(color, &mut argb);
+        assert_eq!(argb, [0xFF00_0000, 0xFF00_0000, 0xFF00_0000, 0xFFFF_0000]);
+        let mut rgba = [0; 16];
+        screen.to_rgba(color, &mut rgba);
+        #[rustfmt::skip]
+        assert_eq!(rgba, [
+            0, 0, 0, 0xFF, 0, 0, 0, 0xFF,
+            0, 0, 0, 0xFF, 0xFF, 0, 0, 0xFF,
+        ]);
+        let mut rgba = [0; 16];
+        screen.lines_to_rgba(1..2, color, &mut rgba);
+        assert_eq!(rgba[..8], [0; 8]);
+        let mut argb = [0; 4];
+        screen.lines_to_argb(1..2, color, &mut argb);
+        assert_eq!(argb, [0, 0, 0xFF00_0000, 0xFFFF_0000]);
+        assert_eq!(rgba[12..], [0xFF, 0, 0, 0xFF]);
+        let lines: Vec<&[u8]> = screen.lines().collect();
+        assert_eq!(lines, [&[0, 0], &[0, 1]]);
+        assert_eq!(screen.iter().last(), Some((1, 1, 1)));
+    }
+
+    #[test]
+    fn lines_below_the_screen() {
+        let screen = Framebuffer::new(64, 32, 1);
+        let color = |_| Color::rgb(0xFF, 0xFF, 0xFF);
+        let mut argb = vec![0; 64 * 32];
+        screen.lines_to_argb(40..48, color, &mut argb);
+        assert!(argb.iter().all(|&pixel| pixel == 0));
+        screen.lines_to_argb(30..40, color, &mut argb);
+        assert!(argb[..64 * 30].iter().all(|&pixel| pixel == 0));
+        assert!(argb[64 * 30..].iter().all(|&pixel| pixel == 0xFFFF_FFFF));
+
+        let mut rgba = vec![0; 64 * 32 * 4];
+        screen.lines_to_rgba(40..48, color, &mut rgba);
+        assert!(rgba.iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,577 @@
+use crate::cpu::{Cpu, Fault};
+use crate::disasm;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Range;
+
+/// Registers sent to gdb, in this order: V0 to VF, I, PC, SP, DT and ST.
+/// I and PC are on two bytes in little endian, the others on one byte.
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 1),
+    ("v1", 1),
+    ("v2", 1),
+    ("v3", 1),
+    ("v4", 1),
+    ("v5", 1),
+    ("v6", 1),
+    ("v7", 1),
+    ("v8", 1),
+    ("v9", 1),
+    ("va", 1),
+    ("vb", 1),
+    ("vc", 1),
+    ("vd", 1),
+    ("ve", 1),
+    ("vf", 1),
+    ("i", 2),
+    ("pc", 2),
+    ("sp", 1),
+    ("dt", 1),
+    ("st", 1),
+];
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DT: usize = 19;
+const ST: usize = 20;
+
+/// the signals reported to gdb when the cpu stop
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Watch {
+    Write,
+    Read,
+    Access,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Watchpoint {
+    kind: Watch,
+    addresses: Range<usize>,
+}
+
+/// A server of the gdb remote serial protocol.
+/// gdb can read and write the registers and the memory, set breakpoints
+/// and watchpoints, and step through the game. It's attached to the
+/// `Control` of a frontend which call `poll` and `cycle` instead of running
+/// the cpu directly.
+#[derive(Debug)]
+pub struct Gdb {
+    stream: TcpStream,
+    received: Vec<u8>, // bytes received but not handled yet
+    stopped: bool,
+    step: bool,
+    resume_pc: Option<usize>, // don't stop on the breakpoint we resume from
+    breakpoints: Vec<usize>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Gdb {
+    /// wait for gdb to connect on localhost, the cpu is stopped until gdb
+    /// ask to continue
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        Gdb::accept(&listener)
+    }
+
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Gdb {
+            stream,
+            received: Vec::new(),
+            stopped: true,
+            step: false,
+            resume_pc: None,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    /// the cpu is stopped and waiting for a command of gdb
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Handle the commands sent by gdb since the last call, without
+    /// blocking. Return false when gdb detached.
+    pub fn poll(&mut self, chip: &mut Cpu) -> io::Result<bool> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(&byte) = self.received.first() {
+            match byte {
+                b'$' => {
+                    let end = match self.received.iter().position(|b| *b == b'#') {
+                        Some(end) if end + 2 < self.received.len() => end,
+                        // the packet is incomplete
+                        _ => break,
+                    };
+                    let packet = self.received[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.received[end + 1..end + 3])
+                        .ok()
+                        .and_then(|c| u8::from_str_radix(c, 16).ok());
+                    self.received.drain(..end + 3);
+
+                    if checksum != Some(checksum_of(&packet)) {
+                        self.stream.write_all(b"-")?;
+                        continue;
+                    }
+                    self.stream.write_all(b"+")?;
+                    let packet = String::from_utf8_lossy(&packet).into_owned();
+                    // detach or kill, either way the game continue without gdb
+                    if packet.starts_with('D') {
+                        self.send("OK")?;
+                        return Ok(false);
+                    } else if packet.starts_with('k') {
+                        return Ok(false);
+                    }
+                    if let Some(reply) = self.handle(&packet, chip) {
+                        self.send(&reply)?;
+                    }
+                }
+                // ctrl-c in gdb
+                0x03 => {
+                    self.received.remove(0);
+                    if !self.stopped {
+                        self.stop(SIGINT, "")?;
+                    }
+                }
+                // the acknowledgments and the garbage
+                _ => {
+                    self.received.remove(0);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Execute one cycle of the cpu unless gdb stopped it, and stop on the
+    /// breakpoints, the watchpoints, the faults and after a single step.
+    /// Return the fault the cpu stopped on, gdb only gets a signal so the
+    /// caller should tell the user what happened.
+    pub fn cycle(&mut self, chip: &mut Cpu) -> io::Result<Option<Fault>> {
+        if self.stopped {
+            return Ok(None);
+        }
+        let pc = chip.pc();
+        if self.resume_pc.take() != Some(pc) && self.breakpoints.contains(&pc) {
+            return self.stop(SIGTRAP, "swbreak:;").map(|_| None);
+        }
+
+        let opcode = opcode(chip, pc);
+        let hit = self.watched(opcode, chip.index() as usize);
+        if let Err(fault) = chip.cycle() {
+            let signal = match fault {
+                Fault::UnknownInstruction(_) | Fault::MachineCode(_) => SIGILL,
+                Fault::StackOverflow | Fault::StackUnderflow | Fault::Memory(_) => SIGSEGV,
+            };
+            return self.stop(signal, "").map(|_| Some(fault));
+        }
+
+        match hit {
+            Some((kind, address)) => {
+                let kind = match kind {
+                    Watch::Write => "watch",
+                    Watch::Read => "rwatch",
+                    Watch::Access => "awatch",
+                };
+                self.stop(SIGTRAP, &format!("{}:{:x};", kind, address))?;
+            }
+            None if self.step => self.stop(SIGTRAP, "")?,
+            None => (),
+        }
+        Ok(None)
+    }
+
+    /// the first watchpoint triggered by an instruction, with the address
+    fn watched(&self, opcode: u16, index: usize) -> Option<(Watch, usize)> {
+        let len = disasm::index_access(opcode);
+        // FX33 and FX55 write at I, DXYN and FX65 read
+        let write = matches!(opcode & 0xF0FF, 0xF033 | 0xF055);
+        self.watchpoints.iter().find_map(|watchpoint| {
+            let triggered = match watchpoint.kind {
+                Watch::Write => write,
+                Watch::Read => !write,
+                Watch::Access => true,
+            };
+            let address = (index..index + len).find(|a| watchpoint.addresses.contains(a))?;
+            match triggered {
+                true => Some((watchpoint.kind, address)),
+                false => None,
+            }
+        })
+    }
+
+    fn stop(&mut self, signal: u8, reason: &str) -> io::Result<()> {
+        self.stopped = true;
+        self.step = false;
+        self.send(&format!("T{:02x}{}", signal, reason))
+    }
+
+    fn send(&mut self, reply: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", reply, checksum_of(reply.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    /// answer to a packet, `None` when the answer will come later
+    fn handle(&mut self, packet: &str, chip: &mut Cpu) -> Option<String> {
+        if packet.is_empty() {
+            return Some(String::new());
+        }
+        let (command, args) = packet.split_at(1);
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => (0..REGISTERS.len())
+                .map(|r| read_register(chip, r))
+                .collect(),
+            "G" => {
+                let mut args = args;
+                for (register, (_, size)) in REGISTERS.iter().enumerate() {
+                    let (value, rest) = args.split_at((size * 2).min(args.len()));
+                    args = rest;
+                    if write_register(chip, register, value).is_none() {
+                        return Some(String::from("E01"));
+                    }
+                }
+                String::from("OK")
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(r) if r < REGISTERS.len() => read_register(chip, r),
+                _ => String::from("E01"),
+            },
+            "P" => {
+                let mut split = args.splitn(2, '=');
+                let register = split.next().and_then(|r| usize::from_str_radix(r, 16).ok());
+                match register.and_then(|r| write_register(chip, r, split.next()?)) {
+                    Some(()) => String::from("OK"),
+                    None => String::from("E01"),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some(range) if range.end <= chip.memory().len() => chip.memory()[range]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect(),
+                _ => String::from("E01"),
+            },
+            "M" => {
+                let mut split = args.splitn(2, ':');
+                let range = split.next().and_then(parse_range);
+                let bytes = split.next().and_then(decode_hex);
+                match (range, bytes) {
+                    (Some(range), Some(bytes)) if range.len() == bytes.len() => {
+                        match chip.write_memory(range.start, &bytes) {
+                            Ok(()) => String::from("OK"),
+                            Err(_) => String::from("E01"),
+                        }
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            "c" | "s" => {
+                self.stopped = false;
+                self.step = command == "s";
+                self.resume_pc = Some(chip.pc());
+                return None;
+            }
+            "Z" | "z" => self.set_point(command == "Z", args),
+            "H" => String::from("OK"),
+            "q" => query(args),
+            // we don't know the other packets, an empty reply tell it to gdb
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    /// `TYPE,ADDR,KIND`, the types 0 and 1 are breakpoints, the types 2, 3
+    /// and 4 are write, read and access watchpoints of KIND bytes
+    fn set_point(&mut self, insert: bool, args: &str) -> String {
+        let mut split = args.splitn(2, ',');
+        let kind = split.next();
+        let range = match split.next().and_then(parse_range) {
+            Some(range) => range,
+            None => return String::from("E01"),
+        };
+        let watch = match kind {
+            Some("0") | Some("1") => {
+                match insert {
+                    true => self.breakpoints.push(range.start),
+                    false => self.breakpoints.retain(|b| *b != range.start),
+                }
+                return String::from("OK");
+            }
+            Some("2") => Watch::Write,
+            Some("3") => Watch::Read,
+            Some("4") => Watch::Access,
+            _ => return String::new(),
+        };
+        let watchpoint = Watchpoint {
+            kind: watch,
+            addresses: range,
+        };
+        match insert {
+            true => self.watchpoints.push(watchpoint),
+            false => self.watchpoints.retain(|w| *w != watchpoint),
+        }
+        String::from("OK")
+    }
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn opcode(chip: &Cpu, address: usize) -> u16 {
+    let memory = chip.memory();
+    let byte = |i: usize| memory.get(i).copied().unwrap_or(0) as u16;
+    (byte(address) << 8) | byte(address + 1)
+}
+
+/// `ADDR,LENGTH` in hexadecimal
+fn parse_range(args: &str) -> Option<Range<usize>> {
+    let mut split = args.splitn(2, ',');
+    let address = usize::from_str_radix(split.next()?, 16).ok()?;
+    let len = usize::from_str_radix(split.next()?, 16).ok()?;
+    Some(address..address.checked_add(len)?)
+}
+
+/// `None` if it's not an hexadecimal number of an even number of digits
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn read_register(chip: &Cpu, register: usize) -> String {
+    let (delay, sound) = chip.timers();
+    let value = match register {
+        I => chip.index() as usize,
+        PC => chip.pc(),
+        SP => chip.stack().len(),
+        DT => delay as usize,
+        ST => sound as usize,
+        v => chip.registers()[v] as usize,
+    };
+    let size = REGISTERS[register].1;
+    value.to_le_bytes()[..size]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// write a register from its value in hexadecimal little endian
+fn write_register(chip: &mut Cpu, register: usize, value: &str) -> Option<()> {
+    let bytes = decode_hex(value)?;
+    if register >= REGISTERS.len() || bytes.len() != REGISTERS[register].1 {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0usize, |v, b| (v << 8) | *b as usize);
+    let (delay, sound) = chip.timers();
+    match register {
+        I => chip.set_index(value as u16).ok()?,
+        PC => chip.set_pc(value).ok()?,
+        SP => {
+            // the addresses above the current top of the stack are zeroed
+            let mut stack = chip.stack().to_vec();
+            stack.resize(value, 0);
+            chip.set_stack(&stack).ok()?
+        }
+        DT => chip.set_timers(value as u8, sound),
+        ST => chip.set_timers(delay, value as u8),
+        v => chip.set_register(v, value as u8).ok()?,
+    }
+    Some(())
+}
+
+/// the general queries
+fn query(query: &str) -> String {
+    let mut split = query.splitn(2, ':');
+    match split.next() {
+        Some("Supported") => String::from("PacketSize=1000;qXfer:features:read+;swbreak+"),
+        Some("Attached") => String::from("1"),
+        Some("C") => String::from("QC1"),
+        Some("fThreadInfo") => String::from("m1"),
+        Some("sThreadInfo") => String::from("l"),
+        Some("Xfer") => {
+            let args = split.next().unwrap_or("");
+            match args.strip_prefix("features:read:target.xml:") {
+                Some(range) => match parse_range(range) {
+                    Some(range) => target_xml(range),
+                    None => String::from("E01"),
+                },
+                None => String::new(),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// the description of the registers, there is no chip8 architecture in gdb
+fn target_xml(range: Range<usize>) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for (name, size) in REGISTERS.iter() {
+        let kind = match *name {
+            "pc" => "code_ptr",
+            "i" => "data_ptr",
+            _ => "int",
+        };
+        xml += &format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>",
+            name,
+            size * 8,
+            kind
+        );
+    }
+    xml += "</feature></target>";
+
+    let start = range.start.min(xml.len());
+    let end = range.end.min(xml.len());
+    // `l` for the last part of the document, `m` if there is more to read
+    match end == xml.len() {
+        true => format!("l{}", &xml[start..end]),
+        false => format!("m{}", &xml[start..end]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// a gdb stub connected to a fake gdb
+    fn connect() -> (Gdb, TcpStream, Cpu) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let gdb = Gdb::accept(&listener).unwrap();
+        let mut chip = Cpu::new();
+        // LD V0, #42 ; LD I, #300 ; LD [I], V0 ; JP #206
+        chip.load_rom(&[0x60, 0x42, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06]);
+        (gdb, client, chip)
+    }
+
+    /// send a packet and return the reply, `c` and `s` are only acknowledged
+    fn request(gdb: &mut Gdb, client: &mut TcpStream, chip: &mut Cpu, packet: &str) -> String {
+        let acknowledge_only = packet == "c" || packet == "s";
+        let packet = format!("${}#{:02x}", packet, checksum_of(packet.as_bytes()));
+        client.write_all(packet.as_bytes()).unwrap();
+        let mut reply = String::new();
+        let mut buffer = [0; 1024];
+        loop {
+            assert!(gdb.poll(chip).unwrap());
+            let n = client.read(&mut buffer).unwrap();
+            reply += std::str::from_utf8(&buffer[..n]).unwrap();
+            match reply.find('#') {
+                _ if acknowledge_only && reply == "+" => return String::new(),
+                Some(end) if end + 2 < reply.len() => return reply[2..end].to_string(),
+                _ => (),
+            }
+        }
+    }
+
+    /// run the cpu until gdb stop it and return the stop reply
+    fn wait_stop(gdb: &mut Gdb, client: &mut TcpStream, chip: &mut Cpu) -> String {
+        for _ in 0..100 {
+            gdb.cycle(chip).unwrap();
+            if gdb.stopped() {
+                let mut buffer = [0; 64];
+                let n = client.read(&mut buffer).unwrap();
+                let reply = String::from_utf8_lossy(&buffer[..n]).into_owned();
+                return reply[1..reply.find('#').unwrap()].to_string();
+            }
+        }
+        panic!("the cpu never stopped");
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let (mut gdb, mut client, mut chip) = connect();
+        let (gdb, client, chip) = (&mut gdb, &mut client, &mut chip);
+        assert_eq!(request(gdb, client, chip, "?"), "S05");
+        let registers = request(gdb, client, chip, "g");
+        assert_eq!(registers.len(), 23 * 2);
+        // PC is after V0-VF and I
+        assert_eq!(&registers[36..40], "0002");
+
+        assert_eq!(request(gdb, client, chip, "P0=ab"), "OK");
+        assert_eq!(request(gdb, client, chip, "p0"), "ab");
+        assert_eq!(request(gdb, client, chip, "P11=0403"), "OK");
+        assert_eq!(chip.pc(), 0x304);
+        assert_eq!(request(gdb, client, chip, "P11=ff0f"), "E01");
+
+        assert_eq!(request(gdb, client, chip, "m200,4"), "6042a300");
+        assert_eq!(request(gdb, client, chip, "M300,2:beef"), "OK");
+        assert_eq!(&chip.memory()[0x300..0x302], &[0xBE, 0xEF]);
+        assert_eq!(request(gdb, client, chip, "mfff,2"), "E01");
+        assert!(
+            request(gdb, client, chip, "qXfer:features:read:target.xml:0,1000")
+                .starts_with("l<?xml")
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_steps() {
+        let (mut gdb, mut client, mut chip) = connect();
+        let (gdb, client, chip) = (&mut gdb, &mut client, &mut chip);
+        assert_eq!(request(gdb, client, chip, "s"), "");
+        assert_eq!(wait_stop(gdb, client, chip), "T05");
+        assert_eq!(chip.pc(), 0x202);
+
+        assert_eq!(request(gdb, client, chip, "Z0,206,2"), "OK");
+        assert_eq!(request(gdb, client, chip, "c"), "");
+        assert_eq!(wait_stop(gdb, client, chip), "T05swbreak:;");
+        assert_eq!(chip.pc(), 0x206);
+        // continuing from the breakpoint execute it, then stop on it again
+        assert_eq!(request(gdb, client, chip, "c"), "");
+        assert_eq!(wait_stop(gdb, client, chip), "T05swbreak:;");
+        assert_eq!(request(gdb, client, chip, "z0,206,2"), "OK");
+    }
+
+    #[test]
+    fn faults() {
+        let (mut gdb, mut client, mut chip) = connect();
+        let (gdb, client, chip) = (&mut gdb, &mut client, &mut chip);
+        chip.write_memory(0x206, &[0x00, 0xEE]).unwrap();
+        assert_eq!(request(gdb, client, chip, "c"), "");
+        assert_eq!(wait_stop(gdb, client, chip), "T0b");
+        assert_eq!(chip.pc(), 0x206);
+        chip.write_memory(0x206, &[0xFF, 0xFF]).unwrap();
+        assert_eq!(request(gdb, client, chip, "c"), "");
+        let fault = Some(Fault::UnknownInstruction(0xFFFF));
+        assert_eq!(gdb.cycle(chip).unwrap(), fault);
+        assert_eq!(wait_stop(gdb, client, chip), "T04");
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut gdb, mut client, mut chip) = connect();
+        let (gdb, client, chip) = (&mut gdb, &mut client, &mut chip);
+        assert_eq!(request(gdb, client, chip, "Z2,300,1"), "OK");
+        assert_eq!(request(gdb, client, chip, "c"), "");
+        assert_eq!(wait_stop(gdb, client, chip), "T05watch:300;");
+        assert_eq!(chip.memory()[0x300], 0x42);
+        assert_eq!(chip.pc(), 0x206);
+    }
+}
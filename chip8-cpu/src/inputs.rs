@@ -0,0 +1,97 @@
+use alloc::{format, string::String, vec::Vec};
+
+/// The keys pressed and released during a run, to replay exactly the same
+/// game, see `Cpu::set_inputs`.
+/// The script has one event per line: `CYCLE down|up KEY` with the key in
+/// hexadecimal, the lines starting with `#` are comments. The cycles count
+/// the instructions executed since the start of the game.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inputs {
+    events: Vec<(usize, usize, bool)>, // cycle, key, pressed
+    held: [bool; 16],
+    next: usize,  // the next event to apply
+    cycle: usize, // the instructions executed so far
+}
+
+impl Inputs {
+    #[cfg(feature = "std")]
+    pub fn load(file: &str) -> Result<Self, String> {
+        let script = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+        Inputs::parse(&script)
+    }
+
+    pub fn parse(script: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (number, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("Invalid input at line {} : {}", number + 1, line);
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let (cycle, pressed, key) = match words.as_slice() {
+                [cycle, "down", key] => (cycle, true, key),
+                [cycle, "up", key] => (cycle, false, key),
+                _ => return Err(error()),
+            };
+            let cycle = cycle.parse().map_err(|_| error())?;
+            let key = match usize::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(error()),
+            };
+            events.push((cycle, key, pressed));
+        }
+        // the order of the events of a same cycle is kept
+        events.sort_by_key(|(cycle, _, _)| *cycle);
+        Ok(Inputs {
+            events,
+            ..Inputs::default()
+        })
+    }
+
+    /// update the keys of the chip8 before running the next instruction
+    pub fn apply(&mut self, keys: &mut [bool; 16]) {
+        while let Some((_, key, pressed)) = self.events.get(self.next).filter(|e| e.0 <= self.cycle)
+        {
+            self.held[*key] = *pressed;
+            self.next += 1;
+        }
+        keys.copy_from_slice(&self.held);
+    }
+
+    /// the instruction was executed, the next one is another cycle
+    pub fn executed(&mut self) {
+        self.cycle += 1;
+    }
+
+    /// replay the events from the start
+    pub fn rewind(self) -> Self {
+        Inputs {
+            events: self.events,
+            ..Inputs::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay() {
+        let mut inputs = Inputs::parse("# comment\n2 up 5\n1 down 5\n1 down A").unwrap();
+        let mut keys = [false; 16];
+        for held in [(false, false), (true, true), (false, true)] {
+            inputs.apply(&mut keys);
+            assert_eq!((keys[0x5], keys[0xA]), held);
+            inputs.executed();
+        }
+
+        let mut inputs = inputs.rewind();
+        inputs.apply(&mut keys);
+        assert_eq!(keys, [false; 16]);
+
+        assert!(Inputs::parse("1 down 10").is_err());
+        assert!(Inputs::parse("1 press 5").is_err());
+    }
+}
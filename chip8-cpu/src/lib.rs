@@ -1,11 +1,39 @@
 // register name are going to be broken
 // function containing instruction will also trigger a warning
 #![allow(non_snake_case)]
-#![feature(stmt_expr_attributes)]
-#![feature(proc_macro_hygiene)]
+// without std the crate only needs an allocator, see the `std` feature,
+// the tests always have std
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod control;
 pub mod cpu;
+pub mod decode;
+pub mod disasm;
+pub mod filter;
+pub mod framebuffer;
+#[cfg(feature = "std")]
+pub mod gdb;
+pub mod inputs;
+pub mod machine;
+pub mod megachip;
 mod memory;
+#[cfg(feature = "std")]
+pub mod options;
+pub mod palette;
+pub mod quirks;
+pub mod rca1802;
+#[cfg(feature = "std")]
+pub mod screenshot;
+#[cfg(feature = "std")]
+pub mod symbols;
+pub mod threaded;
+pub mod timing;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod vip;
 
 /// return a new chip8 cpu
 pub fn new() -> cpu::Cpu {
@@ -0,0 +1,366 @@
+use crate::cpu::{Cpu, Fault, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::framebuffer::Framebuffer;
+use alloc::{format, string::String};
+use core::str::FromStr;
+
+/// size of the font in memory, 5 bytes for each of the 16 digits
+pub const FONT_SIZE: usize = 5 * 16;
+/// size of the big font of the SUPER-CHIP, 10 bytes for each of the 10
+/// decimal digits, it follows the small one
+pub const BIG_FONT_SIZE: usize = 10 * 10;
+/// the end of the memory used by the interpreter of the VIP for its stack,
+/// the registers and the screen
+pub const VIP_RESERVED: usize = 0x160;
+
+/// The interpreter the game was written for, each one has its own
+/// instructions on top of the usual chip 8 ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    /// the VIP with the color board: a background color (02A0), colored
+    /// zones (BXY0, BXYN), the nibble addition 5XY1, a second keypad
+    /// (EXF2, EXF5) and the tone (FXF8, FXFB)
+    Chip8X,
+    /// the extensions of Gilles Detillieux: relative branches, comparisons,
+    /// loading and storing a range of registers and waiting on the timer
+    Chip8E,
+    /// the usual instructions on a 128x64 screen
+    Chip10,
+    /// the MegaChip-8 of Revival Studios: the usual 64x32 screen which
+    /// becomes a 256x192 screen with colored sprites and digitized sounds
+    /// when its mode is turned on by 0011, see `MegaChip`
+    MegaChip,
+}
+
+impl Platform {
+    /// the bytes taken by the fonts from `MachineConfig::font_address`
+    pub fn font_size(&self) -> usize {
+        match self {
+            Platform::MegaChip => FONT_SIZE + BIG_FONT_SIZE,
+            _ => FONT_SIZE,
+        }
+    }
+
+    /// the width and the height of the screen when the machine starts
+    pub fn screen_size(&self) -> (usize, usize) {
+        match self {
+            Platform::Chip10 => (128, 64),
+            _ => (SCREEN_WIDTH, SCREEN_HEIGHT),
+        }
+    }
+}
+
+/// The layout of the machine running the games. The default is how this
+/// emulator always worked, the presets follow the machines the games were
+/// written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MachineConfig {
+    /// number of nested calls the stack can hold
+    pub stack_depth: usize,
+    /// size of the memory in bytes, I can't address more than 64ko
+    pub memory_size: usize,
+    /// where the game is loaded and where the PC starts
+    pub start_address: usize,
+    /// where the font used by FX29 is stored
+    pub font_address: usize,
+    /// Run the 0NNN as RCA 1802 subroutines like the interpreter of the
+    /// COSMAC VIP, otherwise they are skipped. The interpreter keeps the
+    /// registers and the screen in the last 0x160 bytes of the memory.
+    pub machine_code: bool,
+    pub platform: Platform,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        MachineConfig {
+            stack_depth: 16,
+            memory_size: 0x1000,
+            start_address: 0x200,
+            font_address: 0x000,
+            machine_code: false,
+            platform: Platform::Chip8,
+        }
+    }
+}
+
+impl MachineConfig {
+    /// the name of the presets accepted by `MachineConfig::preset`
+    pub const PRESETS: [&'static str; 9] = [
+        "default",
+        "vip",
+        "vip-hybrid",
+        "eti660",
+        "xochip",
+        "chip8x",
+        "chip8e",
+        "chip10",
+        "megachip",
+    ];
+
+    pub fn preset(name: &str) -> Option<MachineConfig> {
+        let default = MachineConfig::default();
+        let config = match name {
+            "default" => default,
+            // the interpreter of the COSMAC VIP kept 12 return addresses
+            "vip" => MachineConfig {
+                stack_depth: 12,
+                ..default
+            },
+            // the games mixing chip 8 and machine code
+            "vip-hybrid" => MachineConfig {
+                stack_depth: 12,
+                machine_code: true,
+                ..default
+            },
+            // the ETI-660 interpreter load the games at 0x600
+            "eti660" => MachineConfig {
+                start_address: 0x600,
+                ..default
+            },
+            "xochip" => MachineConfig {
+                memory_size: 0x10000,
+                ..default
+            },
+            // the interpreter of the CHIP-8X is bigger and load the games at 0x300
+            "chip8x" => MachineConfig {
+                stack_depth: 12,
+                start_address: 0x300,
+                platform: Platform::Chip8X,
+                ..default
+            },
+            "chip8e" => MachineConfig {
+                stack_depth: 12,
+                platform: Platform::Chip8E,
+                ..default
+            },
+            "chip10" => MachineConfig {
+                stack_depth: 12,
+                platform: Platform::Chip10,
+                ..default
+            },
+            // the games are bigger than 4ko, but not than the 64ko I can
+            // address even if 01NN NNNN loads 24 bits
+            "megachip" => MachineConfig {
+                memory_size: 0x10000,
+                platform: Platform::MegaChip,
+                ..default
+            },
+            _ => return None,
+        };
+        Some(config)
+    }
+
+    /// check that the machine can run a game
+    pub fn validate(&self) -> Result<(), String> {
+        if self.stack_depth == 0 {
+            return Err(String::from("The stack needs at least one level"));
+        }
+        if self.memory_size > 0x10000 {
+            return Err(format!(
+                "{:#X} bytes of memory can't be addressed with I, the maximum is 0x10000",
+                self.memory_size
+            ));
+        }
+        if self.start_address + 2 > self.memory_size {
+            return Err(format!(
+                "The start address {:#X} is outside of the {:#X} bytes of memory",
+                self.start_address, self.memory_size
+            ));
+        }
+        if self.font_address + self.platform.font_size() > self.start_address {
+            return Err(format!(
+                "The font at {:#X} must end before the start address {:#X}",
+                self.font_address, self.start_address
+            ));
+        }
+        if self.machine_code
+            && self.start_address + 2 > self.memory_size.saturating_sub(VIP_RESERVED)
+        {
+            return Err(format!(
+                "The interpreter of the VIP needs the memory from {:#X}, after the start address {:#X}",
+                self.memory_size.saturating_sub(VIP_RESERVED),
+                self.start_address
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What the frontends and `Control` need to run a machine, the chip 8 of
+/// `Cpu` or the whole COSMAC VIP of `Vip` running the original interpreter.
+pub trait Machine {
+    /// execute one instruction
+    fn cycle(&mut self) -> Result<(), Fault>;
+    /// start a new frame of 1/60s, see `Cpu::vblank`
+    fn vblank(&mut self);
+    /// the cycles left in the current frame, `None` when the instructions
+    /// aren't timed and `cycle` is called at the speed of the frontend
+    fn frame_budget(&self) -> Option<u32>;
+    /// turn the machine off and on, the game stays loaded
+    fn reset(&mut self);
+    /// the address of the next instruction
+    fn pc(&self) -> usize;
+    /// where the machine is, printed when it stops
+    fn stack_trace(&self) -> String;
+    /// the keypad, true when a key is held
+    fn keys(&mut self) -> &mut [bool; 16];
+    /// the screen if it changed since the last call
+    fn update(&mut self) -> Option<&Framebuffer>;
+    /// true if the screen changed since the last call to `update`
+    fn drawing(&self) -> bool;
+    /// the size of the screen in pixels
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// true when the buzzer is on
+    fn sound(&self) -> bool;
+    /// the screen as `0xAARRGGBB` when the machine has colors of its own
+    /// instead of the lit pixels of `update`
+    fn argb(&self) -> Option<&[u32]> {
+        None
+    }
+    /// fill `output` with the sound played at `rate` samples per second,
+    /// for the machines playing more than the buzzer
+    fn audio(&mut self, _rate: u32, output: &mut [f32]) {
+        output.iter_mut().for_each(|s| *s = 0.0);
+    }
+    /// the chip 8 when the machine is one, for gdb and the debugger
+    fn cpu(&mut self) -> Option<&mut Cpu> {
+        None
+    }
+}
+
+impl Machine for Cpu {
+    fn cycle(&mut self) -> Result<(), Fault> {
+        Cpu::cycle(self)
+    }
+
+    fn vblank(&mut self) {
+        Cpu::vblank(self)
+    }
+
+    fn frame_budget(&self) -> Option<u32> {
+        Cpu::frame_budget(self)
+    }
+
+    fn reset(&mut self) {
+        Cpu::reset(self)
+    }
+
+    fn pc(&self) -> usize {
+        Cpu::pc(self)
+    }
+
+    fn stack_trace(&self) -> String {
+        Cpu::stack_trace(self)
+    }
+
+    fn keys(&mut self) -> &mut [bool; 16] {
+        &mut self.key
+    }
+
+    fn update(&mut self) -> Option<&Framebuffer> {
+        Cpu::update(self)
+    }
+
+    fn drawing(&self) -> bool {
+        Cpu::drawing(self)
+    }
+
+    fn width(&self) -> usize {
+        Cpu::width(self)
+    }
+
+    fn height(&self) -> usize {
+        Cpu::height(self)
+    }
+
+    fn sound(&self) -> bool {
+        Cpu::sound(self)
+    }
+
+    fn argb(&self) -> Option<&[u32]> {
+        Cpu::argb(self)
+    }
+
+    fn audio(&mut self, rate: u32, output: &mut [f32]) {
+        Cpu::audio(self, rate, output)
+    }
+
+    fn cpu(&mut self) -> Option<&mut Cpu> {
+        Some(self)
+    }
+}
+
+impl FromStr for MachineConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MachineConfig::preset(s).ok_or_else(|| {
+            format!(
+                "Unknown machine `{}`, expected {}",
+                s,
+                MachineConfig::PRESETS.join(", ")
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets() {
+        for name in MachineConfig::PRESETS.iter() {
+            let config: MachineConfig = name.parse().unwrap();
+            assert_eq!(config.validate(), Ok(()));
+        }
+        assert_eq!("default".parse(), Ok(MachineConfig::default()));
+        assert_eq!(MachineConfig::preset("vip").unwrap().stack_depth, 12);
+        assert!(MachineConfig::preset("vip-hybrid").unwrap().machine_code);
+        let chip10 = MachineConfig::preset("chip10").unwrap();
+        assert_eq!(chip10.platform.screen_size(), (128, 64));
+        let megachip = MachineConfig::preset("megachip").unwrap();
+        assert_eq!(megachip.platform.screen_size(), (64, 32));
+        assert!("c64".parse::<MachineConfig>().is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let default = MachineConfig::default();
+        let invalid = [
+            MachineConfig {
+                stack_depth: 0,
+                ..default
+            },
+            MachineConfig {
+                memory_size: 0x10001,
+                ..default
+            },
+            MachineConfig {
+                start_address: 0xFFF,
+                ..default
+            },
+            MachineConfig {
+                font_address: 0x1C0,
+                ..default
+            },
+            MachineConfig {
+                memory_size: 0x300,
+                machine_code: true,
+                ..default
+            },
+        ];
+        for config in invalid.iter() {
+            assert!(config.validate().is_err(), "{:?}", config);
+        }
+        let config = MachineConfig {
+            font_address: 0x1B0,
+            stack_depth: 64,
+            memory_size: 0x800,
+            ..default
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,349 @@
+use crate::cpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::framebuffer::Framebuffer;
+use alloc::{vec, vec::Vec};
+
+/// size of the screen in the megachip mode
+pub const MEGA_WIDTH: usize = 256;
+pub const MEGA_HEIGHT: usize = 192;
+/// size of the screen of the SUPER-CHIP after 00FF
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// how a sprite is mixed with what is already on the screen, set by 080N
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    Normal,
+    /// the sprite is drawn at a quarter or half of its opacity
+    Quarter,
+    Half,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    fn from_mode(n: u8) -> Blend {
+        match n {
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::Add,
+            4 => Blend::Multiply,
+            _ => Blend::Normal,
+        }
+    }
+
+    /// mix the `0xAARRGGBB` color of the sprite on the one of the screen
+    fn mix(self, sprite: u32, screen: u32) -> u32 {
+        let alpha = match self {
+            Blend::Quarter => (sprite >> 24) / 4,
+            Blend::Half => (sprite >> 24) / 2,
+            _ => sprite >> 24,
+        };
+        let channel = |color: u32, shift: u32| (color >> shift) & 0xFF;
+        let mix = |shift| {
+            let (s, d) = (channel(sprite, shift), channel(screen, shift));
+            let value = match self {
+                Blend::Add => (d + s * alpha / 255).min(255),
+                Blend::Multiply => d * (255 - alpha + s * alpha / 255) / 255,
+                _ => (s * alpha + d * (255 - alpha)) / 255,
+            };
+            value << shift
+        };
+        0xFF00_0000 | mix(16) | mix(8) | mix(0)
+    }
+}
+
+/// A digitized sound started by 060N, the header at I gives the sample
+/// rate on 2 bytes and the number of samples on 3 bytes, followed by a
+/// byte we skip then the unsigned 8 bits samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub start: usize,
+    pub length: usize,
+    pub rate: u32,
+    pub looped: bool,
+    position: f64, // in samples of the sound, not of the output
+}
+
+const SAMPLE_HEADER: usize = 6;
+
+/// the size of the sprites, 0 is 256
+fn size(n: u8) -> usize {
+    match n {
+        0 => 256,
+        n => n as usize,
+    }
+}
+
+/// The state of the MegaChip-8 mode, turned on by 0011 and off by 0010.
+/// The sprites are drawn with the colors of a palette of 255 colors in a
+/// back buffer which is shown by 00E0, like the original emulator.
+/// The color 0 of the palette is transparent.
+/// With the mode off it's a SUPER-CHIP, with its 64x32 or 128x64 screen.
+#[derive(Debug, Clone)]
+pub struct MegaChip {
+    pub enabled: bool,
+    pub hires: bool,     // the 128x64 screen of the SUPER-CHIP, set by 00FF
+    flags: [u8; 8],      // the RPL flags of the SUPER-CHIP, see FX75
+    palette: [u32; 256], // 0xAARRGGBB
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8, // of the whole screen, set by 05NN
+    blend: Blend,
+    collision: u8,    // the color which sets VF when a sprite covers it
+    indexes: Vec<u8>, // the index in the palette of each pixel of the back buffer
+    back: Vec<u32>,   // where the sprites are drawn
+    front: Vec<u32>,  // what is shown, updated by 00E0
+    sample: Option<Sample>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip {
+            enabled: false,
+            hires: false,
+            flags: [0; 8],
+            palette: [0; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision: 0,
+            indexes: vec![0; MEGA_WIDTH * MEGA_HEIGHT],
+            back: vec![0xFF00_0000; MEGA_WIDTH * MEGA_HEIGHT],
+            front: vec![0xFF00_0000; MEGA_WIDTH * MEGA_HEIGHT],
+            sample: None,
+        }
+    }
+}
+
+impl MegaChip {
+    /// the screen as `0xAARRGGBB`, line by line
+    pub fn screen(&self) -> &[u32] {
+        &self.front
+    }
+
+    pub fn palette(&self) -> &[u32; 256] {
+        &self.palette
+    }
+
+    pub fn sample(&self) -> Option<&Sample> {
+        self.sample.as_ref()
+    }
+
+    /// the size of the screen in the current mode
+    pub fn screen_size(&self) -> (usize, usize) {
+        match (self.enabled, self.hires) {
+            (true, _) => (MEGA_WIDTH, MEGA_HEIGHT),
+            (false, true) => (HIRES_WIDTH, HIRES_HEIGHT),
+            (false, false) => (SCREEN_WIDTH, SCREEN_HEIGHT),
+        }
+    }
+
+    /// FX75: save V0 to VX in the flags, only the first 8 registers fit
+    pub fn save_flags(&mut self, registers: &[u8]) {
+        let n = registers.len().min(self.flags.len());
+        self.flags[..n].copy_from_slice(&registers[..n]);
+    }
+
+    /// FX85: restore V0 to VX from the flags
+    pub fn load_flags(&self, registers: &mut [u8]) {
+        let n = registers.len().min(self.flags.len());
+        registers[..n].copy_from_slice(&self.flags[..n]);
+    }
+
+    /// 02NN: load N colors from the memory, a color is 4 bytes A, R, G, B.
+    /// They replace the colors from 1 since 0 is transparent.
+    pub fn load_palette(&mut self, memory: &[u8], address: usize, n: usize) {
+        for i in 0..n {
+            let byte = |b: usize| memory.get(address + i * 4 + b).copied().unwrap_or(0) as u32;
+            self.palette[i + 1] = byte(0) << 24 | byte(1) << 16 | byte(2) << 8 | byte(3);
+        }
+    }
+
+    /// 03NN, 0 is 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = size(width);
+    }
+
+    /// 04NN, 0 is 256
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = size(height);
+    }
+
+    pub fn sprite_size(&self) -> (usize, usize) {
+        (self.sprite_width, self.sprite_height)
+    }
+
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.alpha = alpha;
+    }
+
+    pub fn set_blend(&mut self, mode: u8) {
+        self.blend = Blend::from_mode(mode);
+    }
+
+    pub fn set_collision(&mut self, color: u8) {
+        self.collision = color;
+    }
+
+    /// Draw a sprite of the size set by 03NN and 04NN, one byte per pixel
+    /// giving its color in the palette. The sprites are clipped at the
+    /// edges of the screen. Returns if a pixel of the collision color was
+    /// covered.
+    pub fn draw(&mut self, memory: &[u8], address: usize, x: usize, y: usize) -> bool {
+        let mut collision = false;
+        for row in 0..self.sprite_height {
+            let line = y + row;
+            if line >= MEGA_HEIGHT {
+                break;
+            }
+            for column in 0..self.sprite_width {
+                let offset = address + row * self.sprite_width + column;
+                let color = memory.get(offset).copied().unwrap_or(0);
+                let column = x + column;
+                if color == 0 || column >= MEGA_WIDTH {
+                    continue;
+                }
+                let pos = column + line * MEGA_WIDTH;
+                collision |= self.indexes[pos] == self.collision;
+                self.indexes[pos] = color;
+                self.back[pos] = self.blend.mix(self.palette[color as usize], self.back[pos]);
+            }
+        }
+        collision
+    }
+
+    /// 00E0: show the back buffer with the alpha of the screen and clear it.
+    /// The pixels with a color are lit on `screen` for the frontends which
+    /// can't show the colors.
+    pub fn present(&mut self, screen: &mut Framebuffer) {
+        screen.split();
+        for (i, index) in self.indexes.iter().enumerate() {
+            screen.set(i % MEGA_WIDTH, i / MEGA_WIDTH, (*index != 0) as u8);
+        }
+        let alpha = self.alpha as u32;
+        for (front, back) in self.front.iter_mut().zip(self.back.iter()) {
+            let fade = |shift: u32| ((back >> shift & 0xFF) * alpha / 255) << shift;
+            *front = 0xFF00_0000 | fade(16) | fade(8) | fade(0);
+        }
+        self.indexes.iter_mut().for_each(|i| *i = 0);
+        self.back.iter_mut().for_each(|p| *p = 0xFF00_0000);
+    }
+
+    /// 00BN: scroll the back buffer N lines up
+    pub fn scroll_up(&mut self, n: usize) {
+        let n = n.min(MEGA_HEIGHT) * MEGA_WIDTH;
+        self.indexes.copy_within(n.., 0);
+        self.back.copy_within(n.., 0);
+        let end = self.back.len() - n;
+        self.indexes[end..].iter_mut().for_each(|i| *i = 0);
+        self.back[end..].iter_mut().for_each(|p| *p = 0xFF00_0000);
+    }
+
+    /// 060N: play the sound at I, in a loop when N is 0 and once otherwise
+    pub fn play(&mut self, memory: &[u8], address: usize, looped: bool) {
+        let byte = |b: usize| memory.get(address + b).copied().unwrap_or(0);
+        let rate = (byte(0) as u32) << 8 | byte(1) as u32;
+        let length = (byte(2) as usize) << 16 | (byte(3) as usize) << 8 | byte(4) as usize;
+        let start = address + SAMPLE_HEADER;
+        // what doesn't fit in the memory is not played
+        let length = length.min(memory.len().saturating_sub(start));
+        self.sample = Some(Sample {
+            start,
+            length,
+            rate,
+            looped,
+            position: 0.0,
+        })
+        .filter(|sample| sample.length > 0 && sample.rate > 0);
+    }
+
+    /// 0700
+    pub fn stop(&mut self) {
+        self.sample = None;
+    }
+
+    /// Fill `output` with the sound played at `rate` samples per second,
+    /// between -1 and 1. It's silent when nothing is played.
+    pub fn audio(&mut self, memory: &[u8], rate: u32, output: &mut [f32]) {
+        for out in output.iter_mut() {
+            *out = match &mut self.sample {
+                Some(sample) => {
+                    let value = memory[sample.start + sample.position as usize];
+                    sample.position += sample.rate as f64 / rate as f64;
+                    if sample.position as usize >= sample.length {
+                        match sample.looped {
+                            true => sample.position = 0.0,
+                            false => self.sample = None,
+                        }
+                    }
+                    (value as f32 - 128.0) / 128.0
+                }
+                None => 0.0,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend() {
+        let red = 0xFFFF_0000;
+        let gray = 0xFF80_8080;
+        assert_eq!(Blend::Normal.mix(red, gray), red);
+        assert_eq!(Blend::Normal.mix(0x00FF_0000, gray), gray);
+        assert_eq!(Blend::Half.mix(red, 0xFF00_0000), 0xFF7F_0000);
+        assert_eq!(Blend::Add.mix(red, gray), 0xFFFF_8080);
+        assert_eq!(Blend::Multiply.mix(red, gray), 0xFF80_0000);
+    }
+
+    #[test]
+    fn draw() {
+        let mut mega = MegaChip::default();
+        let mut memory = vec![0; 0x100];
+        memory[..8].copy_from_slice(&[0xFF, 0x11, 0x22, 0x33, 0xFF, 0x44, 0x55, 0x66]);
+        mega.load_palette(&memory, 0, 2);
+        assert_eq!(mega.palette()[2], 0xFF44_5566);
+
+        // a 2x2 sprite with a transparent pixel, clipped on the right
+        memory[0x10..0x14].copy_from_slice(&[1, 0, 2, 1]);
+        mega.set_sprite_width(2);
+        mega.set_sprite_height(2);
+        mega.set_collision(1);
+        assert!(!mega.draw(&memory, 0x10, MEGA_WIDTH - 1, 0));
+        assert!(!mega.draw(&memory, 0x10, MEGA_WIDTH - 2, 0));
+        assert!(mega.draw(&memory, 0x10, MEGA_WIDTH - 2, 0));
+        assert_eq!(mega.screen()[MEGA_WIDTH - 2], 0xFF00_0000);
+        let mut screen = Framebuffer::new(MEGA_WIDTH, MEGA_HEIGHT, 1);
+        mega.present(&mut screen);
+        assert!(screen.lit(MEGA_WIDTH - 2, 0) && !screen.lit(0, 0));
+        assert_eq!(mega.screen()[MEGA_WIDTH - 2], 0xFF11_2233);
+        assert_eq!(mega.screen()[MEGA_WIDTH - 1], 0xFF11_2233);
+        assert_eq!(mega.screen()[2 * MEGA_WIDTH - 2], 0xFF44_5566);
+
+        // the back buffer is cleared by 00E0
+        mega.present(&mut screen);
+        assert!(mega.screen().iter().all(|&p| p == 0xFF00_0000));
+    }
+
+    #[test]
+    fn sample() {
+        let mut mega = MegaChip::default();
+        let memory = [0x00, 0x02, 0x00, 0x00, 0x02, 0x00, 0x00, 0xFF];
+        mega.play(&memory, 0, false);
+        let mut output = [1.0; 6];
+        // the sample is played at half of the output rate
+        mega.audio(&memory, 4, &mut output);
+        assert_eq!(output, [-1.0, -1.0, 127.0 / 128.0, 127.0 / 128.0, 0.0, 0.0]);
+        assert_eq!(mega.sample(), None);
+
+        mega.play(&memory, 0, true);
+        mega.audio(&memory, 2, &mut output);
+        assert_eq!(output[4], -1.0);
+        mega.stop();
+        assert_eq!(mega.sample(), None);
+    }
+}
@@ -1,9 +1,12 @@
+use crate::machine::{MachineConfig, Platform, BIG_FONT_SIZE, FONT_SIZE};
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+#[cfg(feature = "std")]
 use std::fs::File;
+#[cfg(feature = "std")]
 use std::io::Read;
 
-const MEMORY_SIZE: usize = 0x1000;
-
-const FONTSET: [u8; 5 * 16] = [
+const FONTSET: [u8; FONT_SIZE] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -22,36 +25,121 @@ const FONTSET: [u8; 5 * 16] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// the digits of the SUPER-CHIP, 8x10 pixels
+const BIG_FONTSET: [u8; BIG_FONT_SIZE] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
 pub struct Memory {
-    raw: [u8; MEMORY_SIZE], // 4ko
+    raw: Vec<u8>,                  // 4ko by default
+    rom: Vec<u8>,                  // the game, kept to be able to reset the memory
+    start: usize,                  // where the game is loaded
+    font: usize,                   // where the font is loaded
+    big_font: bool,                // the big font of the SUPER-CHIP follows it
+    written: Option<Range<usize>>, // what was written since the last `take_written`
 }
 
 impl Memory {
     /// initialize the memory of the chip-8
-    /// create an empty array of 4ko (MEMORY_SIZE)
-    /// Then load the fontset from the start
-    pub fn new() -> Self {
+    /// create an empty array of the size of the memory of the machine
+    /// Then load the fontsets at their address.
+    /// The config must be valid, see `MachineConfig::validate`
+    pub fn new(config: &MachineConfig) -> Self {
         let mut memory = Memory {
-            raw: [0; MEMORY_SIZE],
+            raw: vec![0; config.memory_size],
+            rom: Vec::new(),
+            start: config.start_address,
+            font: config.font_address,
+            big_font: config.platform == Platform::MegaChip,
+            written: Some(0..config.memory_size),
         };
-        for (i, v) in FONTSET.iter().enumerate() {
-            memory[i] = *v
-        }
+        memory.load_fonts();
         memory
     }
 
-    /// Load the game into the chip-8 memory from 0x200 to the end of memory
+    fn load_fonts(&mut self) {
+        self.raw[self.font..self.font + FONT_SIZE].copy_from_slice(&FONTSET);
+        if self.big_font {
+            let big = self.big_font();
+            self.raw[big..big + BIG_FONT_SIZE].copy_from_slice(&BIG_FONTSET);
+        }
+    }
+
+    /// the address of the sprite of the digit 0, the others follow it
+    pub fn font(&self) -> usize {
+        self.font
+    }
+
+    /// the address of the big 0 on the MegaChip-8
+    pub fn big_font(&self) -> usize {
+        self.font + FONT_SIZE
+    }
+
+    /// Load the game into the chip-8 memory from the start address to the end of memory
+    #[cfg(feature = "std")]
     pub fn load_game(&mut self, file: &str) -> std::io::Result<()> {
         let mut file = File::open(file)?;
-        file.read(&mut self.raw[0x200..])?;
+        let mut rom = Vec::new();
+        file.read_to_end(&mut rom)?;
+        self.load_rom(&rom);
         Ok(())
     }
+
+    /// Load a game already in memory, what doesn't fit in the memory is dropped
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let rom = &rom[..rom.len().min(self.raw.len() - self.start)];
+        self.raw[self.start..self.start + rom.len()].copy_from_slice(rom);
+        self.rom = rom.to_vec();
+        self.touch(0..self.raw.len());
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// everything is considered written since we don't know what the
+    /// caller will change
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.touch(0..self.raw.len());
+        &mut self.raw
+    }
+
+    /// The addresses written since the last call, in a single range
+    /// covering all of them. Used to drop what was decoded from the memory
+    /// when the game modifies its code.
+    pub fn take_written(&mut self) -> Option<Range<usize>> {
+        self.written.take()
+    }
+
+    fn touch(&mut self, range: Range<usize>) {
+        self.written = Some(match self.written.take() {
+            Some(written) => written.start.min(range.start)..written.end.max(range.end),
+            None => range,
+        });
+    }
+
+    /// Put the memory back in the state it was just after loading the game
+    pub fn reset(&mut self) {
+        self.raw.iter_mut().for_each(|b| *b = 0);
+        self.load_fonts();
+        self.raw[self.start..self.start + self.rom.len()].copy_from_slice(&self.rom);
+        self.touch(0..self.raw.len());
+    }
 }
 
 /// Just implementing some easy access to the memory
 /// Nothing interesting to see after this point
-use std::ops::Index;
-use std::ops::IndexMut;
+use core::ops::Index;
+use core::ops::IndexMut;
 
 macro_rules! impl_index_for {
     ($t:ty) => {
@@ -63,8 +151,10 @@ macro_rules! impl_index_for {
         }
 
         impl IndexMut<$t> for Memory {
-            fn index_mut<'b>(&'b mut self, i: $t) -> &'b mut Self::Output {
-                &mut self.raw[i as usize]
+            fn index_mut(&mut self, i: $t) -> &mut Self::Output {
+                let i = i as usize;
+                self.touch(i..i + 1);
+                &mut self.raw[i]
             }
         }
     };
@@ -0,0 +1,257 @@
+use crate::cpu::Cpu;
+use crate::inputs::Inputs;
+use crate::machine::{Machine, MachineConfig};
+use crate::palette::{Palette, Themes};
+use crate::symbols::Symbols;
+use crate::timing::Timing;
+use crate::trace::{self, Tracer};
+use crate::vip::Vip;
+
+/// the options which mean nothing when the game runs on the whole VIP
+const VIP_IGNORES: [&str; 6] = [
+    "--machine",
+    "--timing",
+    "--trace",
+    "--palette-file",
+    "--seed",
+    "--inputs",
+];
+
+/// The command line options shared by all the frontends, the ones only
+/// a frontend understands are kept in `Options::extra`.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// the builtin palettes, the ones of the palette file and the custom
+    /// one, with the palette asked selected
+    pub themes: Themes,
+    pub trace: Option<String>,
+    pub trace_format: trace::Format,
+    pub trace_filter: trace::Filter,
+    // a trace can only be compared to another run with the same random
+    // numbers and the same keys, see `Cpu::set_seed` and `Cpu::set_inputs`
+    pub seed: Option<u64>,
+    pub inputs: Option<Inputs>,
+    pub machine: MachineConfig,
+    pub timing: Timing,
+    pub gdb: Option<u16>, // the port gdb connects to
+    pub symbols: Symbols,
+    pub breakpoints: Vec<String>, // resolved with the symbols, see `Options::machine`
+    pub vip: Option<(String, String)>, // the monitor and the interpreter
+    pub game: String,
+    extra: Vec<(&'static str, String)>,
+}
+
+impl Options {
+    /// Parse the arguments of the command line, without the name of the
+    /// program. `extra` are the options taking a value the frontend handles
+    /// itself.
+    pub fn parse<I>(args: I, extra: &[&'static str]) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut palette = None;
+        let mut options = Options {
+            themes: Themes::default(),
+            trace: None,
+            trace_format: trace::Format::Text,
+            trace_filter: trace::Filter::default(),
+            seed: None,
+            inputs: None,
+            machine: MachineConfig::default(),
+            timing: Timing::default(),
+            gdb: None,
+            symbols: Symbols::default(),
+            breakpoints: Vec::new(),
+            vip: None,
+            game: String::new(),
+            extra: Vec::new(),
+        };
+        let mut game = None;
+        // the VIP has its own machine, timing and display
+        let mut not_on_vip = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            if VIP_IGNORES.contains(&arg.as_str()) && !not_on_vip.contains(&arg) {
+                not_on_vip.push(arg.clone());
+            }
+            match arg.as_str() {
+                "--palette" => palette = Some(value()?),
+                "--palette-file" => {
+                    let palettes = Palette::load_config(&value()?)
+                        .map_err(|e| format!("Can't load palettes : {}", e))?;
+                    palettes.into_iter().for_each(|p| options.themes.add(p));
+                }
+                "--trace" => options.trace = Some(value()?),
+                "--trace-format" => options.trace_format = value()?.parse()?,
+                "--trace-filter" => options.trace_filter = value()?.parse()?,
+                "--seed" => match value()?.parse() {
+                    Ok(seed) => options.seed = Some(seed),
+                    Err(e) => return Err(format!("Invalid seed : {}", e)),
+                },
+                "--inputs" => {
+                    let inputs = Inputs::load(&value()?)
+                        .map_err(|e| format!("Can't load inputs : {}", e))?;
+                    options.inputs = Some(inputs);
+                }
+                "--machine" => options.machine = value()?.parse()?,
+                "--timing" => options.timing = value()?.parse()?,
+                "--gdb" => match value()?.parse::<u16>() {
+                    Ok(port) => options.gdb = Some(port),
+                    Err(e) => return Err(format!("Invalid port : {}", e)),
+                },
+                "--symbols" => options.symbols = Symbols::load(&value()?)?,
+                "--break" => options.breakpoints.push(value()?),
+                "--vip" => {
+                    let monitor = value()?;
+                    options.vip = Some((monitor, value()?));
+                }
+                name => match extra.iter().find(|&&e| e == name) {
+                    Some(&name) => options.extra.push((name, value()?)),
+                    None if name.starts_with("--") => {
+                        return Err(format!("Unknown option {}", name))
+                    }
+                    None if game.is_some() => return Err(format!("Unexpected argument {}", name)),
+                    None => game = Some(arg),
+                },
+            }
+        }
+
+        if options.vip.is_some() && !not_on_vip.is_empty() {
+            return Err(format!(
+                "--vip can't be used with {}",
+                not_on_vip.join(", ")
+            ));
+        }
+
+        // the palette can be a name from the palette file or a list of colors
+        if let Some(palette) = palette {
+            if !options.themes.select(&palette) {
+                let palette = palette
+                    .parse::<Palette>()
+                    .map_err(|e| format!("Can't use palette : {}", e))?;
+                options.themes.add(palette);
+                options.themes.select("custom");
+            }
+        }
+        options.game = game.ok_or("Need a game as argument")?;
+        Ok(options)
+    }
+
+    /// the last value given to an option of the frontend
+    pub fn extra(&self, name: &str) -> Option<&str> {
+        self.extra
+            .iter()
+            .rev()
+            .find(|(option, _)| *option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Load the game in the machine asked, with the tracer and the symbols,
+    /// and resolve the addresses of the breakpoints
+    pub fn machine(&self) -> Result<(Box<dyn Machine>, Vec<usize>), String> {
+        let mut chip = Cpu::with_config(self.machine)?;
+        chip.set_timing(self.timing);
+        if let Some(seed) = self.seed {
+            chip.set_seed(seed);
+        }
+        chip.set_inputs(self.inputs.clone());
+        chip.load_game(&self.game)
+            .map_err(|e| format!("Can't load game : {}", e))?;
+
+        if let Some(trace) = &self.trace {
+            let tracer = Tracer::create(trace, self.trace_format, self.trace_filter.clone())
+                .map_err(|e| format!("Can't create trace : {}", e))?;
+            chip.set_tracer(Some(tracer));
+        }
+
+        let breakpoints = self
+            .breakpoints
+            .iter()
+            .map(|b| self.symbols.resolve(b))
+            .collect::<Result<Vec<_>, _>>()?;
+        chip.set_symbols(self.symbols.clone());
+
+        // the whole VIP runs the game with its own interpreter instead
+        let chip: Box<dyn Machine> = match &self.vip {
+            Some(_) if self.gdb.is_some() || !breakpoints.is_empty() => {
+                return Err(String::from(
+                    "gdb and the breakpoints can't debug the interpreter of the VIP",
+                ))
+            }
+            Some((monitor, interpreter)) => {
+                let mut vip = Vip::load(monitor, interpreter)?;
+                vip.load_game(&self.game)
+                    .map_err(|e| format!("Can't load game : {}", e))?;
+                Box::new(vip)
+            }
+            None => Box::new(chip),
+        };
+        Ok((chip, breakpoints))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str, extra: &[&'static str]) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from), extra)
+    }
+
+    #[test]
+    fn options() {
+        let options = parse(
+            "--machine vip --timing vip --break 0x204 --mode braille --palette green --seed 42 GAME",
+            &["--mode"],
+        )
+        .unwrap();
+        assert_eq!(options.game, "GAME");
+        assert_eq!(options.machine, "vip".parse().unwrap());
+        assert_eq!(options.timing, Timing::Vip);
+        assert_eq!(options.breakpoints, ["0x204"]);
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.extra("--mode"), Some("braille"));
+        assert_eq!(options.extra("--hold"), None);
+        assert_eq!(options.themes.current().name(), "green");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("--trace", &[]).unwrap_err(), "--trace needs a value");
+        assert_eq!(
+            parse("--mode", &["--mode"]).unwrap_err(),
+            "--mode needs a value"
+        );
+        assert_eq!(parse("", &[]).unwrap_err(), "Need a game as argument");
+        assert!(parse("--gdb port GAME", &[]).is_err());
+        assert!(parse("--machine 1802 GAME", &[]).is_err());
+        assert!(parse("--seed -1 GAME", &[]).is_err());
+        assert_eq!(
+            parse("--mode half GAME", &[]).unwrap_err(),
+            "Unknown option --mode"
+        );
+        assert_eq!(
+            parse("GAME OTHER", &[]).unwrap_err(),
+            "Unexpected argument OTHER"
+        );
+        assert!(parse("--inputs missing GAME", &[]).is_err());
+        assert_eq!(
+            parse("--timing vip --vip a b --trace t --timing vip GAME", &[]).unwrap_err(),
+            "--vip can't be used with --timing, --trace"
+        );
+    }
+
+    #[test]
+    fn machine() {
+        let options = parse("--break 0x206 ../games/PONG", &[]).unwrap();
+        let (mut chip, breakpoints) = options.machine().unwrap();
+        assert_eq!(breakpoints, [0x206]);
+        assert_eq!(chip.pc(), 0x200);
+        assert!(chip.cycle().is_ok());
+
+        let options = parse("--vip monitor interpreter --gdb 1234 ../games/PONG", &[]);
+        assert!(options.unwrap().machine().is_err());
+    }
+}
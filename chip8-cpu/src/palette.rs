@@ -0,0 +1,322 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::str::FromStr;
+
+/// A RGB color, the frontends convert it to whatever their backend need
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+
+    /// return the color as `0xAARRGGBB` with a fully opaque alpha
+    pub fn to_argb(self) -> u32 {
+        0xFF00_0000 | (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+
+    /// the color of a `0xAARRGGBB`, the alpha is ignored
+    pub fn from_argb(argb: u32) -> Self {
+        Color::rgb((argb >> 16) as u8, (argb >> 8) as u8, argb as u8)
+    }
+
+    /// return the color as `[r, g, b, a]` with a fully opaque alpha
+    pub fn to_rgba(self) -> [u8; 4] {
+        [self.r, self.g, self.b, 0xFF]
+    }
+
+    /// mix two colors, with an amount of 0 you get `self` and with 255 you
+    /// get `other`
+    pub fn blend(self, other: Color, amount: u8) -> Color {
+        let mix = |a: u8, b: u8| {
+            let (a, b, amount) = (a as u32, b as u32, amount as u32);
+            ((a * (255 - amount) + b * amount) / 255) as u8
+        };
+        Color::rgb(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
+}
+
+/// Parse a color written as `#RRGGBB` or `RRGGBB`
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid color `{}`, expected `#RRGGBB`", s));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        Ok(Color::rgb(channel(0), channel(2), channel(4)))
+    }
+}
+
+/// A set of colors used to display the screen.
+/// The color at index 0 is the background, index 1 is the color of a lit
+/// pixel. Four and sixteen colors palettes are used by the multi-plane modes
+/// where the index of a pixel is made from the bits of every plane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    name: String,
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    /// create a new palette, it must contains 2, 4 or 16 colors
+    pub fn new(name: &str, colors: Vec<Color>) -> Result<Self, String> {
+        match colors.len() {
+            2 | 4 | 16 => Ok(Palette {
+                name: name.to_string(),
+                colors,
+            }),
+            n => Err(format!(
+                "Palette `{}` has {} colors, expected 2, 4 or 16",
+                name, n
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    pub fn background(&self) -> Color {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.colors[1]
+    }
+
+    /// return the color of a pixel from its plane index.
+    /// If the palette doesn't have enough colors we fallback on the foreground.
+    pub fn color(&self, index: usize) -> Color {
+        match self.colors.get(index) {
+            Some(color) => *color,
+            None => self.foreground(),
+        }
+    }
+
+    /// return the color of a pixel on a monochrome screen
+    pub fn pixel(&self, lit: bool) -> Color {
+        self.color(lit as usize)
+    }
+
+    /// return the color of a pixel from its intensity, see `filter::Phosphor`
+    pub fn shade(&self, intensity: u8) -> Color {
+        self.background().blend(self.foreground(), intensity)
+    }
+
+    /// return all the themes shipped with the emulator, the first one is the
+    /// default
+    pub fn builtin() -> Vec<Palette> {
+        let palette = |name: &str, colors: &[u32]| {
+            let colors = colors
+                .iter()
+                .map(|c| Color::rgb((c >> 16) as u8, (c >> 8) as u8, *c as u8))
+                .collect();
+            Palette::new(name, colors).unwrap()
+        };
+
+        vec![
+            palette("classic", &[0x32321e, 0xe6ffff, 0x8c9696, 0x5a6460]),
+            palette("green", &[0x0a1a0a, 0x33ff66, 0x1f9940, 0x145c28]),
+            palette("amber", &[0x1a1000, 0xffb000, 0xb37b00, 0x664600]),
+            palette("lcd", &[0x9bbc0f, 0x0f380f, 0x8bac0f, 0x306230]),
+            palette("high-contrast", &[0x000000, 0xffffff, 0xffff00, 0x00ffff]),
+            palette("octo", &[0x996600, 0xffcc00, 0xff6600, 0x662200]),
+            palette(
+                "octo16",
+                &[
+                    0x000000, 0xffffff, 0xaaaaaa, 0x555555, 0xff0000, 0x00ff00, 0x0000ff, 0xffff00,
+                    0x880000, 0x008800, 0x000088, 0x888800, 0xff00ff, 0x00ffff, 0x880088, 0x008888,
+                ],
+            ),
+        ]
+    }
+
+    /// return the builtin palette with the specified name
+    pub fn by_name(name: &str) -> Option<Palette> {
+        Palette::builtin().into_iter().find(|p| p.name == name)
+    }
+
+    /// Parse a palette config, every non empty line define one palette:
+    /// ```text
+    /// # this is a comment
+    /// gameboy = #9bbc0f #0f380f #8bac0f #306230
+    /// ```
+    pub fn parse_config(config: &str) -> Result<Vec<Palette>, String> {
+        config
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(nb, line)| {
+                let mut split = line.splitn(2, '=');
+                let name = split.next().unwrap().trim();
+                let colors = split
+                    .next()
+                    .ok_or(format!("line {}: expected `name = colors`", nb + 1))?;
+                let colors = parse_colors(colors).map_err(|e| format!("line {}: {}", nb + 1, e))?;
+                Palette::new(name, colors).map_err(|e| format!("line {}: {}", nb + 1, e))
+            })
+            .collect()
+    }
+
+    /// load all the palettes defined in a config file
+    #[cfg(feature = "std")]
+    pub fn load_config(file: &str) -> Result<Vec<Palette>, String> {
+        let config = std::fs::read_to_string(file).map_err(|e| e.to_string())?;
+        Palette::parse_config(&config)
+    }
+}
+
+/// Parse a builtin palette name or a list of colors separated by spaces or
+/// commas, for example `amber` or `000000,ffffff`
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Palette::by_name(s) {
+            Some(palette) => Ok(palette),
+            None => Palette::new("custom", parse_colors(s)?),
+        }
+    }
+}
+
+/// parse a list of colors separated by spaces or commas
+fn parse_colors(s: &str) -> Result<Vec<Color>, String> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|c| !c.is_empty())
+        .map(Color::from_str)
+        .collect()
+}
+
+/// The list of palettes the user can cycle through at runtime
+#[derive(Debug, Clone)]
+pub struct Themes {
+    palettes: Vec<Palette>,
+    current: usize,
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        Themes {
+            palettes: Palette::builtin(),
+            current: 0,
+        }
+    }
+}
+
+impl Themes {
+    /// add a palette to the cycle
+    pub fn add(&mut self, palette: Palette) {
+        self.palettes.push(palette);
+    }
+
+    /// select the palette with the specified name, return false if there is
+    /// no palette with this name
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.palettes.iter().position(|p| p.name == name) {
+            Some(idx) => self.current = idx,
+            None => return false,
+        }
+        true
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.palettes[self.current]
+    }
+
+    /// switch to the next palette and return it
+    pub fn cycle(&mut self) -> &Palette {
+        self.current = (self.current + 1) % self.palettes.len();
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_color() {
+        assert_eq!("#32321e".parse(), Ok(Color::rgb(0x32, 0x32, 0x1e)));
+        assert_eq!("E6FFFF".parse(), Ok(Color::rgb(0xe6, 0xff, 0xff)));
+        assert!("#12345".parse::<Color>().is_err());
+        assert!("#12345g".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn argb() {
+        assert_eq!(Color::rgb(0x32, 0x32, 0x1e).to_argb(), 0xFF32_321E);
+        assert_eq!(Color::from_argb(0x0032_321E), Color::rgb(0x32, 0x32, 0x1e));
+    }
+
+    #[test]
+    fn blend() {
+        let black = Color::rgb(0, 0, 0);
+        let white = Color::rgb(0xff, 0xff, 0xff);
+        assert_eq!(black.blend(white, 0), black);
+        assert_eq!(black.blend(white, 255), white);
+        assert_eq!(black.blend(white, 128), Color::rgb(128, 128, 128));
+    }
+
+    #[test]
+    fn parse_palette() {
+        let palette: Palette = "amber".parse().unwrap();
+        assert_eq!(palette.name(), "amber");
+
+        let palette: Palette = "#000000,#ffffff".parse().unwrap();
+        assert_eq!(palette.background(), Color::rgb(0, 0, 0));
+        assert_eq!(palette.foreground(), Color::rgb(0xff, 0xff, 0xff));
+        // a missing plane color fallback on the foreground
+        assert_eq!(palette.color(3), palette.foreground());
+
+        assert!("000000 ffffff 000000".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = "
+            # my palettes
+            mono = #000000 #ffffff
+            gameboy = 9bbc0f, 0f380f, 8bac0f, 306230
+        ";
+        let palettes = Palette::parse_config(config).unwrap();
+        assert_eq!(palettes.len(), 2);
+        assert_eq!(palettes[0].name(), "mono");
+        assert_eq!(palettes[1].colors().len(), 4);
+
+        assert!(Palette::parse_config("broken").is_err());
+    }
+
+    #[test]
+    fn cycle_themes() {
+        let mut themes = Themes::default();
+        let builtin = Palette::builtin();
+        assert_eq!(themes.current(), &builtin[0]);
+        assert_eq!(themes.cycle(), &builtin[1]);
+        assert!(themes.select("octo16"));
+        assert!(!themes.select("unknown"));
+        themes.add(Palette::new("mine", vec![Color::rgb(1, 2, 3); 2]).unwrap());
+        assert_eq!(themes.cycle().name(), "mine");
+        assert_eq!(themes.cycle(), &builtin[0]);
+    }
+}
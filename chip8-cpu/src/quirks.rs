@@ -0,0 +1,68 @@
+use alloc::{format, string::String};
+use core::str::FromStr;
+
+/// The instructions which don't behave the same on every chip8.
+/// The default is how this emulator always worked, the presets reproduce
+/// the original COSMAC VIP interpreter and the SUPER-CHIP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// 8XY6 and 8XYE shift VY and store the result in VX instead of
+    /// shifting VX in place
+    pub shift_vy: bool,
+    /// FX55 and FX65 leave I pointing after the last register
+    pub increment_i: bool,
+    /// BNNN jumps to NNN plus VX, where X is the first digit of NNN,
+    /// instead of V0
+    pub jump_vx: bool,
+    /// the sprites are clipped at the edges of the screen instead of
+    /// wrapping around
+    pub clip: bool,
+    /// FX1E sets VF to 1 when I goes past 0xFFF and to 0 otherwise, like
+    /// the Amiga interpreter, the COSMAC VIP leaves VF alone
+    pub index_overflow: bool,
+}
+
+impl Quirks {
+    /// the name of the presets accepted by `Quirks::preset`
+    pub const PRESETS: [&'static str; 3] = ["default", "cosmac", "schip"];
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        let quirks = match name {
+            "default" => Quirks::default(),
+            "cosmac" => Quirks {
+                vf_reset: true,
+                shift_vy: true,
+                increment_i: true,
+                jump_vx: false,
+                clip: true,
+                index_overflow: false,
+            },
+            "schip" => Quirks {
+                vf_reset: false,
+                shift_vy: false,
+                increment_i: false,
+                jump_vx: true,
+                clip: true,
+                index_overflow: false,
+            },
+            _ => return None,
+        };
+        Some(quirks)
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Quirks::preset(s).ok_or_else(|| {
+            format!(
+                "Unknown quirks preset `{}`, expected {}",
+                s,
+                Quirks::PRESETS.join(", ")
+            )
+        })
+    }
+}
@@ -0,0 +1,455 @@
+/// What the RCA 1802 is connected to: the memory and the I/O lines.
+/// Only the memory is required, the rest is left unconnected by default.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+
+    /// `INP n`, the byte put on the bus by the device `n` (1 to 7)
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    /// `OUT n`, the byte sent to the device `n` (1 to 7)
+    fn output(&mut self, _port: u8, _value: u8) {}
+
+    /// the state of the external flag EF`n` (1 to 4)
+    fn flag(&mut self, _n: u8) -> bool {
+        false
+    }
+
+    /// called when the Q output changes, it often drives a speaker
+    fn set_q(&mut self, _q: bool) {}
+}
+
+/// a bare memory, the addresses wrap around its size
+impl Bus for [u8] {
+    fn read(&mut self, address: u16) -> u8 {
+        self[address as usize % self.len()]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let len = self.len();
+        self[address as usize % len] = value;
+    }
+}
+
+/// The registers of the RCA 1802 (or CDP1802), the cpu of the COSMAC VIP.
+/// Any of the 16 registers can be the program counter (`R[P]`) or the
+/// index for the memory operations (`R[X]`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rca1802 {
+    /// the 16 scratchpad registers R0 to RF
+    pub r: [u16; 16],
+    /// the accumulator
+    pub d: u8,
+    /// the carry flag
+    pub df: bool,
+    /// which register is the program counter
+    pub p: u8,
+    /// which register is the index
+    pub x: u8,
+    /// where X and P are saved by an interrupt
+    pub t: u8,
+    /// interrupt enable
+    pub ie: bool,
+    /// the output flip flop
+    pub q: bool,
+    /// waiting for an interrupt or a DMA after IDL
+    pub idle: bool,
+}
+
+impl Rca1802 {
+    /// the state after a reset: P, X and R0 are cleared and the
+    /// interrupts are enabled, the other registers are kept
+    pub fn reset(&mut self) {
+        self.p = 0;
+        self.x = 0;
+        self.r[0] = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+    }
+
+    /// the program counter, R[P]
+    pub fn pc(&self) -> u16 {
+        self.r[self.p as usize]
+    }
+
+    /// Take the interrupt if it's enabled: X and P are saved in T, then
+    /// the interrupt routine runs with R1 as PC and R2 as index.
+    /// Return false when the interrupts are disabled.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = self.x << 4 | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    /// A DMA out cycle: the byte at R0 is read for the device and R0 is
+    /// incremented, it wakes the cpu after IDL.
+    pub fn dma_out<B: Bus + ?Sized>(&mut self, bus: &mut B) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        byte
+    }
+
+    /// Execute one instruction and return the machine cycles it took,
+    /// 2 for most instructions and 3 for the long branches and skips.
+    /// After IDL nothing is executed until an interrupt or a DMA.
+    pub fn step<B: Bus + ?Sized>(&mut self, bus: &mut B) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let (i, n) = (opcode >> 4, opcode & 0xF);
+        let rn = n as usize;
+        match i {
+            0x0 => match n {
+                0 => self.idle = true,
+                _ => self.d = bus.read(self.r[rn]),
+            },
+            0x1 => self.r[rn] = self.r[rn].wrapping_add(1),
+            0x2 => self.r[rn] = self.r[rn].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(bus, n);
+                let pc = self.pc();
+                match taken {
+                    true => self.r[self.p as usize] = pc & 0xFF00 | bus.read(pc) as u16,
+                    false => self.r[self.p as usize] = pc.wrapping_add(1),
+                }
+            }
+            0x4 => {
+                self.d = bus.read(self.r[rn]);
+                self.r[rn] = self.r[rn].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[rn], self.d),
+            0x6 => match n {
+                0x0 => self.inc_x(),
+                // 68 is only used by the 1804
+                0x8 => (),
+                0x1..=0x7 => {
+                    let value = bus.read(self.rx());
+                    bus.output(n, value);
+                    self.inc_x();
+                }
+                _ => {
+                    self.d = bus.input(n - 8);
+                    bus.write(self.rx(), self.d);
+                }
+            },
+            0x7 => self.opcode_7N(bus, n),
+            0x8 => self.d = self.r[rn] as u8,
+            0x9 => self.d = (self.r[rn] >> 8) as u8,
+            0xA => self.r[rn] = self.r[rn] & 0xFF00 | self.d as u16,
+            0xB => self.r[rn] = self.r[rn] & 0x00FF | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(bus, n);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.opcode_FN(bus, n),
+        }
+        2
+    }
+
+    /// the byte at the PC, the PC moves to the next one
+    fn fetch<B: Bus + ?Sized>(&mut self, bus: &mut B) -> u8 {
+        let pc = self.pc();
+        self.r[self.p as usize] = pc.wrapping_add(1);
+        bus.read(pc)
+    }
+
+    /// R[X]
+    fn rx(&self) -> u16 {
+        self.r[self.x as usize]
+    }
+
+    fn inc_x(&mut self) {
+        let x = self.x as usize;
+        self.r[x] = self.r[x].wrapping_add(1);
+    }
+
+    /// The condition of the short branch 3N, the ones from 8 to F are the
+    /// opposite of the ones from 0 to 7. 38 is the unconditional skip.
+    fn condition<B: Bus + ?Sized>(&mut self, bus: &mut B, n: u8) -> bool {
+        let condition = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            ef => bus.flag(ef - 3),
+        };
+        condition == (n < 8)
+    }
+
+    /// CN, the long branches take the next two bytes as address and the long
+    /// skips jump over them
+    fn long_branch<B: Bus + ?Sized>(&mut self, bus: &mut B, n: u8) {
+        let condition = match n & 0x3 {
+            0 => n != 0x4 && n != 0xC || n == 0xC && self.ie,
+            1 => self.q,
+            2 => self.d == 0,
+            _ => self.df,
+        };
+        let pc = self.pc();
+        let p = self.p as usize;
+        match n {
+            // NOP
+            0x4 => self.r[p] = pc,
+            // the skips
+            0x5..=0x8 | 0xC..=0xF => {
+                // LSNQ, LSNZ and LSNF skip when the condition is false
+                let skip = match n {
+                    0x5..=0x7 => !condition,
+                    _ => condition,
+                };
+                if skip {
+                    self.r[p] = pc.wrapping_add(2);
+                }
+            }
+            // the branches, from 8 to B they are inverted
+            _ => match condition == (n < 8) {
+                true => {
+                    let high = bus.read(pc) as u16;
+                    let low = bus.read(pc.wrapping_add(1)) as u16;
+                    self.r[p] = high << 8 | low;
+                }
+                false => self.r[p] = pc.wrapping_add(2),
+            },
+        }
+    }
+
+    /// 7N, the control instructions and the arithmetic with carry
+    fn opcode_7N<B: Bus + ?Sized>(&mut self, bus: &mut B, n: u8) {
+        let carry = self.df as u16;
+        match n {
+            // RET and DIS
+            0x0 | 0x1 => {
+                let xp = bus.read(self.rx());
+                self.inc_x();
+                self.x = xp >> 4;
+                self.p = xp & 0xF;
+                self.ie = n == 0x0;
+            }
+            // LDXA
+            0x2 => {
+                self.d = bus.read(self.rx());
+                self.inc_x();
+            }
+            // STXD
+            0x3 => {
+                bus.write(self.rx(), self.d);
+                let x = self.x as usize;
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 => self.add(bus.read(self.rx()), self.d, carry),
+            0x5 => self.subtract(bus.read(self.rx()), self.d, carry),
+            // SHRC
+            0x6 => {
+                let df = self.d & 1 == 1;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+                self.df = df;
+            }
+            0x7 => self.subtract(self.d, bus.read(self.rx()), carry),
+            // SAV
+            0x8 => bus.write(self.rx(), self.t),
+            // MARK
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.set_q(bus, false),
+            0xB => self.set_q(bus, true),
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.d, carry);
+            }
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, carry);
+            }
+            // SHLC
+            0xE => {
+                let df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+                self.df = df;
+            }
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, carry);
+            }
+        }
+    }
+
+    /// FN, the logic and the arithmetic with M(R[X]) or the next byte
+    fn opcode_FN<B: Bus + ?Sized>(&mut self, bus: &mut B, n: u8) {
+        // F8 to FF take the byte after the instruction instead of M(R[X])
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => bus.read(self.rx()),
+            _ => self.fetch(bus),
+        };
+        match n & 0x7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, self.d, 0),
+            0x5 => self.subtract(value, self.d, 1),
+            0x6 => match n {
+                // SHR
+                0x6 => {
+                    self.df = self.d & 1 == 1;
+                    self.d >>= 1;
+                }
+                // SHL
+                _ => {
+                    self.df = self.d & 0x80 != 0;
+                    self.d <<= 1;
+                }
+            },
+            _ => self.subtract(self.d, value, 1),
+        }
+    }
+
+    /// D = a + b + carry, DF is the carry out
+    fn add(&mut self, a: u8, b: u8, carry: u16) {
+        let sum = a as u16 + b as u16 + carry;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// D = a - b, with a borrow when `no_borrow` is 0. DF is 1 when there is
+    /// no borrow, like on the 6502
+    fn subtract(&mut self, a: u8, b: u8, no_borrow: u16) {
+        let difference = a as u16 + (!b) as u16 + no_borrow;
+        self.d = difference as u8;
+        self.df = difference > 0xFF;
+    }
+
+    fn set_q<B: Bus + ?Sized>(&mut self, bus: &mut B, q: bool) {
+        self.q = q;
+        bus.set_q(q);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// run the program at 0 until it executes IDL
+    fn run(program: &[u8]) -> (Rca1802, Vec<u8>) {
+        let mut memory = vec![0; 0x100];
+        memory[..program.len()].copy_from_slice(program);
+        let mut cpu = Rca1802::default();
+        cpu.reset();
+        for _ in 0..1000 {
+            if cpu.idle {
+                break;
+            }
+            cpu.step(memory.as_mut_slice());
+        }
+        assert!(cpu.idle, "the program didn't stop");
+        (cpu, memory)
+    }
+
+    #[test]
+    fn load_and_store() {
+        // LDI 80, PLO R3, LDI 42, STR R3, LDI 0, LDN R3, IDL
+        let (cpu, memory) = run(&[0xF8, 0x80, 0xA3, 0xF8, 0x42, 0x53, 0xF8, 0x00, 0x03, 0x00]);
+        assert_eq!(memory[0x80], 0x42);
+        assert_eq!(cpu.d, 0x42);
+        assert_eq!(cpu.r[3], 0x80);
+        assert_eq!(cpu.pc(), 0x0A);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // LDI F0, ADI 20
+        let (cpu, _) = run(&[0xF8, 0xF0, 0xFC, 0x20, 0x00]);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+        // LDI 10, SMI 20, the borrow clears DF
+        let (cpu, _) = run(&[0xF8, 0x10, 0xFF, 0x20, 0x00]);
+        assert_eq!((cpu.d, cpu.df), (0xF0, false));
+        // LDI 10, SDI 20
+        let (cpu, _) = run(&[0xF8, 0x10, 0xFD, 0x20, 0x00]);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+        // LDI 81, SHL, SHLC
+        let (cpu, _) = run(&[0xF8, 0x81, 0xFE, 0x7E, 0x00]);
+        assert_eq!((cpu.d, cpu.df), (0x05, false));
+        // LDI 0F, ANI 3C, XRI FF
+        let (cpu, _) = run(&[0xF8, 0x0F, 0xFA, 0x3C, 0xFB, 0xFF, 0x00]);
+        assert_eq!(cpu.d, 0xF3);
+    }
+
+    #[test]
+    fn branches() {
+        // LDI 0, BZ 06, LDI 1, IDL, LDI 2, IDL
+        let (cpu, _) = run(&[0xF8, 0x00, 0x32, 0x06, 0xF8, 0x01, 0xF8, 0x02, 0x00]);
+        assert_eq!(cpu.d, 0x02);
+        // LDI 1, LBNZ 0008, IDL, IDL, SEQ, LSQ, IDL, IDL, IDL
+        let program = [
+            0xF8, 0x01, 0xCA, 0x00, 0x08, 0x00, 0x00, 0x00, 0x7B, 0xCD, 0x00, 0x00, 0x00,
+        ];
+        let (cpu, _) = run(&program);
+        assert!(cpu.q);
+        assert_eq!(cpu.pc(), 0x0D);
+        // SKP, IDL, LDI 3, IDL
+        let (cpu, _) = run(&[0x38, 0x00, 0xF8, 0x03, 0x00]);
+        assert_eq!(cpu.d, 0x03);
+    }
+
+    #[test]
+    fn subroutine() {
+        // R3 runs the subroutine at 0x10 which returns with SEP R0
+        // LDI 10, PLO R3, SEP R3, IDL
+        let mut program = vec![0xF8, 0x10, 0xA3, 0xD3, 0x00];
+        program.resize(0x10, 0);
+        // LDI 55, SEP R0
+        program.extend([0xF8, 0x55, 0xD0]);
+        let (cpu, _) = run(&program);
+        assert_eq!((cpu.d, cpu.p), (0x55, 0));
+        assert_eq!(cpu.pc(), 0x05);
+    }
+
+    #[test]
+    fn interrupt() {
+        // LDI 30, PLO R2, MARK, IDL
+        let (mut cpu, mut memory) = run(&[0xF8, 0x30, 0xA2, 0x79, 0x00]);
+        assert_eq!(memory[0x30], 0x00);
+        assert_eq!((cpu.x, cpu.r[2]), (0, 0x2F));
+        cpu.r[1] = 0x20;
+        cpu.x = 5;
+        assert!(cpu.interrupt());
+        assert!(!cpu.interrupt());
+        assert_eq!((cpu.t, cpu.p, cpu.x), (0x50, 1, 2));
+        // the routine at 0x20: SAV then RET with X=5 and P=0
+        memory[0x20..0x22].copy_from_slice(&[0x78, 0x70]);
+        assert_eq!(cpu.step(memory.as_mut_slice()), 2);
+        cpu.step(memory.as_mut_slice());
+        assert_eq!((cpu.x, cpu.p, cpu.ie), (5, 0, true));
+        assert_eq!(cpu.pc(), 0x05);
+    }
+
+    #[test]
+    fn dma() {
+        let mut memory = vec![0xAA; 4];
+        let mut cpu = Rca1802 {
+            idle: true,
+            ..Rca1802::default()
+        };
+        assert_eq!(cpu.step(memory.as_mut_slice()), 1);
+        assert_eq!(cpu.dma_out(memory.as_mut_slice()), 0xAA);
+        assert_eq!(cpu.r[0], 1);
+        assert!(!cpu.idle);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::palette::Color;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Save the pixels in a PNG file, each pixel become a square of `scale` pixels
+pub fn save_png(
+    file: &str,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+    scale: usize,
+) -> io::Result<()> {
+    let file = BufWriter::new(File::create(file)?);
+    write_png(file, width, height, pixels, scale)
+}
+
+/// Encode the pixels as a PNG image.
+/// The image is not compressed, it's a few kilobytes for a chip8 screen
+/// so we don't need to pull a whole deflate implementation for that.
+pub fn write_png<W: Write>(
+    mut writer: W,
+    width: usize,
+    height: usize,
+    pixels: &[Color],
+    scale: usize,
+) -> io::Result<()> {
+    let scale = scale.max(1);
+    let (image_width, image_height) = (width * scale, height * scale);
+
+    // every scanline start with its filter type, 0 means no filter
+    let mut raw = Vec::with_capacity((image_width * 3 + 1) * image_height);
+    for y in 0..image_height {
+        raw.push(0);
+        for x in 0..image_width {
+            let color = pixels[(y / scale) * width + x / scale];
+            raw.extend_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    // zlib stream made of stored (uncompressed) deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image_width as u32).to_be_bytes());
+    header.extend_from_slice(&(image_height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, default compression, filter and no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_chunk(&mut writer, b"IHDR", &header)?;
+    write_chunk(&mut writer, b"IDAT", &zlib)?;
+    write_chunk(&mut writer, b"IEND", &[])?;
+    writer.flush()
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => 0xEDB8_8320 ^ (crc >> 1),
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"IEND".iter()), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn png() {
+        let pixels = [Color::rgb(0, 0, 0), Color::rgb(255, 255, 255)];
+        let mut png = Vec::new();
+        write_png(&mut png, 2, 1, &pixels, 2).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        // the image was scaled to 4x2
+        assert_eq!(&png[16..24], &[0, 0, 0, 4, 0, 0, 0, 2]);
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// The names and the sources of a game, written by an assembler or by hand.
+/// The file has one entry per line, the addresses are in hexadecimal:
+/// - `label NAME ADDRESS` names an address
+/// - `line ADDRESS FILE:LINE` gives the line of the source of an instruction,
+///   the file is relative to the symbol file
+/// - `data START END` marks the bytes from START to END as data instead of
+///   instructions
+///
+/// The empty lines and the ones starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    labels: Vec<(String, usize)>, // sorted by address
+    lines: Vec<Line>,
+    data: Vec<RangeInclusive<usize>>,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    address: usize,
+    file: PathBuf, // as written in the symbol file
+    path: PathBuf, // relative to the working directory
+    line: usize,
+}
+
+impl Symbols {
+    pub fn load(file: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(file).map_err(|e| format!("Can't load {} : {}", file, e))?;
+        let directory = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+        Symbols::parse(&text, directory)
+    }
+
+    /// parse a symbol file, the sources are relative to `directory`
+    pub fn parse(text: &str, directory: &Path) -> Result<Self, String> {
+        let mut symbols = Symbols::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || format!("Invalid symbol at line {} : {}", number + 1, line);
+            let address = |a: &str| hex(a).ok_or_else(error);
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["label", name, a] => symbols.labels.push((name.to_string(), address(a)?)),
+                ["line", a, location] => {
+                    let (file, line) = location.rsplit_once(':').ok_or_else(error)?;
+                    symbols.lines.push(Line {
+                        address: address(a)?,
+                        file: PathBuf::from(file),
+                        path: directory.join(file),
+                        line: line.parse().map_err(|_| error())?,
+                    });
+                }
+                ["data", start, end] => symbols.data.push(address(start)?..=address(end)?),
+                _ => return Err(error()),
+            }
+        }
+        symbols.labels.sort_by_key(|(_, address)| *address);
+        Ok(symbols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.lines.is_empty() && self.data.is_empty()
+    }
+
+    pub fn address_of(&self, label: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .find(|(name, _)| name == label)
+            .map(|(_, address)| *address)
+    }
+
+    /// the label put exactly on `address`
+    pub fn label(&self, address: usize) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(_, a)| *a == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// `address` relative to the closest label before it, like `loop+0x4`
+    pub fn symbolize(&self, address: usize) -> Option<String> {
+        let (name, label) = self.labels.iter().rev().find(|(_, a)| *a <= address)?;
+        match address - label {
+            0 => Some(name.clone()),
+            offset => Some(format!("{}+{:#X}", name, offset)),
+        }
+    }
+
+    /// the source file and line of the instruction at `address`
+    pub fn location(&self, address: usize) -> Option<(&Path, usize)> {
+        self.lines
+            .iter()
+            .find(|l| l.address == address)
+            .map(|l| (l.path.as_path(), l.line))
+    }
+
+    /// The address of the first instruction on `line` of the source `path`,
+    /// or on the next line with an instruction.
+    /// Return the address and the line it's really on.
+    pub fn line_address(&self, path: &str, line: usize) -> Option<(usize, usize)> {
+        self.lines
+            .iter()
+            .filter(|l| Path::new(path).ends_with(&l.file) && l.line >= line)
+            .min_by_key(|l| (l.line, l.address))
+            .map(|l| (l.address, l.line))
+    }
+
+    pub fn is_data(&self, address: usize) -> bool {
+        self.data.iter().any(|range| range.contains(&address))
+    }
+
+    /// Find the address of a breakpoint given as a label, an address in
+    /// hexadecimal or a `FILE:LINE`
+    pub fn resolve(&self, spec: &str) -> Result<usize, String> {
+        if let Some(address) = self.address_of(spec) {
+            return Ok(address);
+        }
+        if let Some((file, line)) = spec.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return self
+                    .line_address(file, line)
+                    .map(|(address, _)| address)
+                    .ok_or_else(|| format!("No instruction at {}", spec));
+            }
+        }
+        hex(spec).ok_or_else(|| format!("Unknown label or address `{}`", spec))
+    }
+
+    /// `0x2D6 draw+0x2 (pong.8o:12)` with what we know of the address
+    pub fn describe(&self, address: usize) -> String {
+        let mut text = format!("{:#05X}", address);
+        if let Some(symbol) = self.symbolize(address) {
+            text += &format!(" {}", symbol);
+        }
+        if let Some((path, line)) = self.location(address) {
+            text += &format!(" ({}:{})", path.display(), line);
+        }
+        text
+    }
+}
+
+/// an address in hexadecimal, with or without `0x`
+fn hex(text: &str) -> Option<usize> {
+    let text = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(text, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PONG: &str = "
+# made by hand
+label main 200
+label draw 2D4
+line 200 pong.8o:3
+line 202 pong.8o:4
+line 2D4 src/draw.8o:10
+line 2D6 src/draw.8o:12
+data 2EA 2F1
+";
+
+    fn symbols() -> Symbols {
+        Symbols::parse(PONG, Path::new("games")).unwrap()
+    }
+
+    #[test]
+    fn labels() {
+        let symbols = symbols();
+        assert_eq!(symbols.address_of("draw"), Some(0x2D4));
+        assert_eq!(symbols.address_of("loop"), None);
+        assert_eq!(symbols.label(0x200), Some("main"));
+        assert_eq!(symbols.label(0x202), None);
+        assert_eq!(symbols.symbolize(0x1FF), None);
+        assert_eq!(symbols.symbolize(0x200).as_deref(), Some("main"));
+        assert_eq!(symbols.symbolize(0x2D8).as_deref(), Some("draw+0x4"));
+    }
+
+    #[test]
+    fn lines() {
+        let symbols = symbols();
+        let location = symbols.location(0x2D6).unwrap();
+        assert_eq!(location, (Path::new("games/src/draw.8o"), 12));
+        assert_eq!(symbols.location(0x2D8), None);
+        assert_eq!(
+            symbols.line_address("/home/me/src/draw.8o", 11),
+            Some((0x2D6, 12))
+        );
+        assert_eq!(symbols.line_address("draw.8o", 11), None);
+        assert_eq!(symbols.line_address("src/draw.8o", 13), None);
+        assert_eq!(
+            symbols.describe(0x2D6),
+            "0x2D6 draw+0x2 (games/src/draw.8o:12)"
+        );
+        assert_eq!(symbols.describe(0x100), "0x100");
+    }
+
+    #[test]
+    fn data() {
+        let symbols = symbols();
+        assert!(!symbols.is_data(0x2E9));
+        assert!(symbols.is_data(0x2EA));
+        assert!(symbols.is_data(0x2F1));
+        assert!(!symbols.is_data(0x2F2));
+    }
+
+    #[test]
+    fn resolve() {
+        let symbols = symbols();
+        assert_eq!(symbols.resolve("draw"), Ok(0x2D4));
+        assert_eq!(symbols.resolve("0x2F6"), Ok(0x2F6));
+        assert_eq!(symbols.resolve("2f6"), Ok(0x2F6));
+        assert_eq!(symbols.resolve("pong.8o:4"), Ok(0x202));
+        assert!(symbols.resolve("pong.8o:40").is_err());
+        assert!(symbols.resolve("loop").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Symbols::parse("label main", Path::new("")).is_err());
+        assert!(Symbols::parse("line 200 pong.8o", Path::new("")).is_err());
+        assert!(Symbols::parse("data 2EA 2GG", Path::new("")).is_err());
+        assert!(Symbols::parse("function main 200", Path::new("")).is_err());
+    }
+}
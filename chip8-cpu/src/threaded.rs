@@ -0,0 +1,242 @@
+use crate::cpu::{Cpu, Fault};
+use crate::decode::{self, Instruction};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// the most instructions in a block, it also bounds how far before a
+/// write we look for the blocks to drop
+const MAX_BLOCK: usize = 32;
+
+/// A run of instructions decoded once, ending with the first one which
+/// may not continue at the next instruction: a jump, a call, a skip, a
+/// wait for a key or machine code.
+#[derive(Debug)]
+struct Block {
+    end: usize, // the address after the last instruction
+    instructions: Vec<(u16, Instruction)>,
+}
+
+/// An optional backend faster than calling `Cpu::cycle` for each
+/// instruction, for the turbo and to run a lot of games. The code is
+/// translated by blocks of decoded instructions which are executed
+/// without fetching or decoding anything. When the game writes in the
+/// memory the blocks decoded from there are dropped and translated again.
+///
+/// The variants, the VIP timing and the tracing are left to the
+/// interpreter, see `Threaded::run`.
+/// A `Threaded` must only be used with a single cpu.
+#[derive(Debug, Default)]
+pub struct Threaded {
+    blocks: Vec<Option<Block>>, // by the address of their first instruction
+}
+
+impl Threaded {
+    pub fn new() -> Self {
+        Threaded::default()
+    }
+
+    /// Execute `cycles` instructions, the cpu ends in the same state as
+    /// after as many `Cpu::cycle`. On a fault the cpu is left as it was
+    /// before the instruction which caused it.
+    pub fn run(&mut self, cpu: &mut Cpu, cycles: usize) -> Result<(), Fault> {
+        if !cpu.threadable() {
+            for _ in 0..cycles {
+                cpu.cycle()?;
+            }
+            return Ok(());
+        }
+        self.blocks.resize_with(cpu.memory().len(), || None);
+
+        let mut left = cycles;
+        while left > 0 {
+            // the frontend may have written in the memory since the last run
+            if let Some(written) = cpu.take_written() {
+                self.drop_blocks(written);
+            }
+            let start = cpu.pc();
+            let block = match self.blocks.get_mut(start).and_then(Option::take) {
+                Some(block) => block,
+                None => match translate(cpu.memory(), start) {
+                    Some(block) => block,
+                    // the interpreter deals with a pc at the end of the memory
+                    None => {
+                        cpu.cycle()?;
+                        left -= 1;
+                        continue;
+                    }
+                },
+            };
+
+            let mut result = Ok(());
+            let mut valid = true;
+            for &(opcode, instruction) in block.instructions.iter().take(left) {
+                if let Err(fault) = cpu.step(opcode, instruction) {
+                    result = Err(fault);
+                    break;
+                }
+                left -= 1;
+                if let Some(written) = cpu.take_written() {
+                    // the rest of the block may have been modified
+                    valid = written.start >= block.end || written.end <= start;
+                    self.drop_blocks(written);
+                    if !valid {
+                        break;
+                    }
+                }
+            }
+            if valid {
+                self.blocks[start] = Some(block);
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    /// drop the blocks with an instruction in `written`
+    fn drop_blocks(&mut self, written: Range<usize>) {
+        if written.len() >= self.blocks.len() {
+            self.blocks.iter_mut().for_each(|block| *block = None);
+            return;
+        }
+        let first = written.start.saturating_sub(MAX_BLOCK * 2);
+        let last = written.end.min(self.blocks.len());
+        for start in first..last {
+            if let Some(block) = &self.blocks[start] {
+                if block.end > written.start {
+                    self.blocks[start] = None;
+                }
+            }
+        }
+    }
+}
+
+/// decode the block starting at `start`, nothing when there isn't a whole
+/// instruction there
+fn translate(memory: &[u8], start: usize) -> Option<Block> {
+    let mut instructions = Vec::new();
+    let mut pc = start;
+    while instructions.len() < MAX_BLOCK && pc + 1 < memory.len() {
+        let opcode = (memory[pc] as u16) << 8 | memory[pc + 1] as u16;
+        let instruction = decode::lookup(opcode);
+        instructions.push((opcode, instruction));
+        pc += 2;
+        if ends_block(instruction) {
+            break;
+        }
+    }
+    match instructions.is_empty() {
+        true => None,
+        false => Some(Block {
+            end: pc,
+            instructions,
+        }),
+    }
+}
+
+/// the instructions after which the pc may not be on the next instruction
+fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        Op00EE
+            | Op0NNN(_)
+            | Op1NNN(_)
+            | Op2NNN(_)
+            | Op3XNN(..)
+            | Op4XNN(..)
+            | Op5XY0(..)
+            | Op9XY0(..)
+            | OpBNNN(_)
+            | OpEX9E(_)
+            | OpEXA1(_)
+            | OpFX0A(_)
+            | Unknown(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// everything the two cpus must agree on
+    fn assert_same(interpreter: &Cpu, threaded: &Cpu, context: &str) {
+        assert_eq!(interpreter.pc(), threaded.pc(), "{}", context);
+        assert_eq!(interpreter.registers(), threaded.registers(), "{}", context);
+        assert_eq!(interpreter.index(), threaded.index(), "{}", context);
+        assert_eq!(interpreter.stack(), threaded.stack(), "{}", context);
+        assert_eq!(interpreter.timers(), threaded.timers(), "{}", context);
+        assert!(interpreter.memory() == threaded.memory(), "{}", context);
+        assert!(
+            interpreter.framebuffer().pixels() == threaded.framebuffer().pixels(),
+            "{}",
+            context
+        );
+    }
+
+    #[test]
+    fn lockstep() {
+        let mut games: Vec<_> = std::fs::read_dir("../games")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_file())
+            .collect();
+        games.sort();
+        assert!(!games.is_empty());
+
+        for game in games {
+            let game = game.to_str().unwrap();
+            let mut interpreter = Cpu::new();
+            let mut threaded = Cpu::new();
+            let mut backend = Threaded::new();
+            for cpu in [&mut interpreter, &mut threaded] {
+                cpu.load_rom(&std::fs::read(game).unwrap());
+                cpu.set_seed(8);
+            }
+
+            let mut executed = 0;
+            for step in 0..2000 {
+                // press the keys in turn, to go through the menus
+                let key = [step / 16 % 16, step * 7 % 16];
+                for cpu in [&mut interpreter, &mut threaded] {
+                    cpu.key = [false; 16];
+                    key.iter().for_each(|&k| cpu.key[k] = step % 3 != 0);
+                }
+                // runs of every length, to stop in the middle of the blocks
+                let cycles = step % 37 + 1;
+                let expected = (0..cycles).try_for_each(|_| interpreter.cycle());
+                let result = backend.run(&mut threaded, cycles);
+                let context = format!("{} after {} instructions", game, executed);
+                assert_eq!(expected, result, "{}", context);
+                assert_same(&interpreter, &threaded, &context);
+                if result.is_err() {
+                    break;
+                }
+                executed += cycles;
+            }
+        }
+    }
+
+    #[test]
+    fn self_modifying() {
+        let rom = [
+            0x60, 0x62, // V0 = 0x62
+            0x61, 0x42, // V1 = 0x42
+            0xA2, 0x08, // I = 0x208
+            0xF1, 0x55, // write V0 and V1 over the next instruction
+            0x62, 0x00, // V2 = 0, becomes V2 = 0x42
+            0x12, 0x0A, // loop
+        ];
+        let mut cpu = Cpu::new();
+        cpu.load_rom(&rom);
+        let mut backend = Threaded::new();
+        backend.run(&mut cpu, 5).unwrap();
+        assert_eq!(cpu.registers()[2], 0x42);
+        assert_eq!(cpu.pc(), 0x20A);
+
+        // a write from outside of the cpu is seen too
+        cpu.write_memory(0x208, &[0x62, 0x24]).unwrap();
+        cpu.set_pc(0x208).unwrap();
+        backend.run(&mut cpu, 1).unwrap();
+        assert_eq!(cpu.registers()[2], 0x24);
+    }
+}
@@ -0,0 +1,160 @@
+use alloc::{format, string::String};
+use core::str::FromStr;
+
+/// Machine cycles of the 1802 in a 60Hz frame of the COSMAC VIP, a machine
+/// cycle is 8 clock cycles at 1.76MHz, about 4.54µs
+pub const VIP_FRAME_CYCLES: i32 = 3668;
+/// cycles of the frame taken by the display: the DMA of the 128 lines of 8
+/// bytes and the interrupt routine which updates the timers
+pub const VIP_DISPLAY_CYCLES: i32 = 1024 + 60;
+/// what is left to the interpreter in every frame
+pub const VIP_BUDGET: i32 = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+
+/// How long the instructions take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// every instruction take one call to `Cpu::cycle` and the timers tick
+    /// at each instruction, the speed depends on the frontend
+    #[default]
+    Instruction,
+    /// Every instruction take as long as in the interpreter of the COSMAC
+    /// VIP, the frontend call `Cpu::vblank` 60 times per second to give the
+    /// cpu a new frame of cycles and tick the timers.
+    Vip,
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "instruction" => Ok(Timing::Instruction),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!(
+                "Unknown timing `{}`, expected instruction or vip",
+                s
+            )),
+        }
+    }
+}
+
+/// Machine cycles of the fetch and decode loop of the VIP interpreter,
+/// taken by every instruction before its own routine
+pub const VIP_FETCH_CYCLES: i32 = 68;
+
+/// The machine cycles the VIP interpreter takes to execute `opcode`,
+/// including the fetch and the decoding. `skipped` tells if a conditional
+/// instruction skipped the next one and `vx` is the value of VX before the
+/// instruction, which gives the position of a sprite for DXYN and the
+/// digits of FX33.
+///
+/// The counts of the routines come from the instruction index of Laurence
+/// Scotford's disassembly of the interpreter, "Chip-8 on the COSMAC VIP".
+/// The DMA of the display and the interrupt are not in them, they are
+/// taken out of the frame by `VIP_BUDGET`.
+pub fn vip_cycles(opcode: u16, skipped: bool, vx: u8) -> i32 {
+    let x = (opcode >> 8 & 0xF) as i32;
+    let n = (opcode & 0xF) as i32;
+    let vx = vx as i32;
+    let skip = |taken: i32, not_taken: i32| match skipped {
+        true => taken,
+        false => not_taken,
+    };
+    let execute = match opcode >> 12 {
+        0x0 => match opcode {
+            // the routine at 00E0 clears the 256 bytes of the screen one
+            // by one, 12 cycles each
+            0x00E0 => 24 + 3078,
+            0x00EE => 10,
+            // the machine code subroutines are counted by the cpu running them
+            _ => 0,
+        },
+        0x1 => 12,
+        0x2 => 26,
+        0x3 | 0x4 => skip(14, 10),
+        0x5 | 0x9 => skip(18, 14),
+        0x6 => 6,
+        0x7 => 10,
+        // the ALU instructions are built in memory and run as machine code
+        0x8 => 44,
+        0xA => 12,
+        // the high byte of the target may need a carry
+        0xB => 22,
+        0xC => 36,
+        // The rows of the sprite are shifted bit by bit to their position
+        // in the bytes of the screen, a row which isn't aligned is written
+        // in two bytes.
+        0xD => {
+            26 + n * match vx % 8 {
+                0 => 46,
+                shift => 68 + 4 * shift,
+            }
+        }
+        0xE => skip(18, 14),
+        0xF => match opcode & 0xFF {
+            0x07 | 0x15 | 0x18 => 10,
+            // not in the index as it waits for a key, each call is a scan
+            // of the keypad by the monitor
+            0x0A => 38,
+            0x1E | 0x29 => 16,
+            // the digits are counted by repeated subtractions of 100 and 10
+            0x33 => 80 + 16 * (vx / 100 + vx / 10 % 10 + vx % 10),
+            0x55 | 0x65 => 14 + 14 * (x + 1),
+            _ => 10,
+        },
+        _ => unreachable!(),
+    };
+    VIP_FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("vip".parse(), Ok(Timing::Vip));
+        assert_eq!("instruction".parse(), Ok(Timing::Instruction));
+        assert!("fast".parse::<Timing>().is_err());
+    }
+
+    #[test]
+    fn cycles() {
+        // the counts of the index, plus the fetch
+        let documented = [
+            (0x00E0, 3102),
+            (0x00EE, 10),
+            (0x1234, 12),
+            (0x2234, 26),
+            (0x6A02, 6),
+            (0x7A02, 10),
+            (0x8AB4, 44),
+            (0xA234, 12),
+            (0xB234, 22),
+            (0xCAFF, 36),
+            (0xFA07, 10),
+            (0xFA1E, 16),
+            (0xFA29, 16),
+        ];
+        for (opcode, cycles) in documented {
+            assert_eq!(vip_cycles(opcode, false, 0), VIP_FETCH_CYCLES + cycles);
+        }
+        assert_eq!(vip_cycles(0x3A02, true, 0), VIP_FETCH_CYCLES + 14);
+        assert_eq!(vip_cycles(0x3A02, false, 0), VIP_FETCH_CYCLES + 10);
+        assert_eq!(vip_cycles(0x5AB0, true, 0), VIP_FETCH_CYCLES + 18);
+        assert_eq!(vip_cycles(0xFA33, false, 0), VIP_FETCH_CYCLES + 80);
+        assert_eq!(
+            vip_cycles(0xFA33, false, 255),
+            VIP_FETCH_CYCLES + 80 + 16 * 12
+        );
+        assert_eq!(vip_cycles(0xF255, false, 0), VIP_FETCH_CYCLES + 14 + 14 * 3);
+        assert_eq!(vip_cycles(0xD015, false, 8), VIP_FETCH_CYCLES + 26 + 5 * 46);
+        assert_eq!(vip_cycles(0xD015, false, 9), VIP_FETCH_CYCLES + 26 + 5 * 72);
+        // clearing the screen takes more than a frame
+        assert!(vip_cycles(0x00E0, false, 0) > VIP_BUDGET);
+        // the aligned sprites are the fastest to draw
+        assert!(vip_cycles(0xD125, false, 8) < vip_cycles(0xD125, false, 9));
+        assert!(vip_cycles(0xD125, false, 8) < vip_cycles(0xD12F, false, 8));
+        assert!(vip_cycles(0xF055, false, 0) < vip_cycles(0xFF55, false, 0));
+    }
+}
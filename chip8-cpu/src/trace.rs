@@ -0,0 +1,503 @@
+use crate::disasm;
+use crate::symbols::Symbols;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// first bytes of a binary trace, followed by the version of the format
+const MAGIC: &[u8; 4] = b"CH8T";
+const VERSION: u8 = 1;
+/// the register number used for I in a `Change`
+pub const INDEX: u8 = 16;
+
+/// How the trace is written in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// one line per instruction: `PC OPCODE MNEMONIC CHANGES`
+    Text,
+    /// the `MAGIC`, the version then for every instruction the PC and the
+    /// opcode on two bytes, the number of changes on one byte and every
+    /// change as a register number on one byte and its value on two bytes.
+    /// Everything is big endian like the chip8.
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!(
+                "Unknown trace format `{}`, expected text or binary",
+                s
+            )),
+        }
+    }
+}
+
+/// The instructions to log.
+/// An instruction is logged if its address is in the range and its class,
+/// the first digit of the opcode, was selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    addresses: RangeInclusive<usize>,
+    classes: [bool; 16],
+}
+
+impl Default for Filter {
+    /// log everything, whatever the size of the memory
+    fn default() -> Self {
+        Filter {
+            addresses: 0..=usize::MAX,
+            classes: [true; 16],
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, pc: usize, opcode: u16) -> bool {
+        self.addresses.contains(&pc) && self.classes[(opcode >> 12) as usize]
+    }
+}
+
+/// Parse a list of `addr=START-END` and `class=DIGITS` separated by commas,
+/// all the numbers are in hexadecimal.
+/// `addr=200-2FF,class=8D` log the arithmetic and draw instructions in the
+/// first 256 bytes of the game.
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = |n: &str| {
+            usize::from_str_radix(n.trim(), 16)
+                .map_err(|e| format!("Invalid address `{}` : {}", n, e))
+        };
+        let mut filter = Filter::default();
+        for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let mut split = part.splitn(2, '=');
+            match (split.next().map(str::trim), split.next()) {
+                (Some("addr"), Some(range)) => {
+                    let mut bounds = range.splitn(2, '-');
+                    let start = hex(bounds.next().unwrap_or(""))?;
+                    let end = match bounds.next() {
+                        Some(end) => hex(end)?,
+                        None => start,
+                    };
+                    filter.addresses = start..=end;
+                }
+                (Some("class"), Some(classes)) => {
+                    filter.classes = [false; 16];
+                    for c in classes.trim().chars() {
+                        match c.to_digit(16) {
+                            Some(class) => filter.classes[class as usize] = true,
+                            None => return Err(format!("Invalid opcode class `{}`", c)),
+                        }
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid trace filter `{}`, expected addr=START-END or class=DIGITS",
+                        part
+                    ))
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// A register modified by an instruction with its new value,
+/// the registers 0 to 15 are V0 to VF and `INDEX` is I
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub register: u8,
+    pub value: u16,
+}
+
+/// An executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub changes: Vec<Change>,
+}
+
+impl Entry {
+    /// compare the registers before and after the instruction
+    pub fn new(pc: usize, opcode: u16, before: (&[u8; 16], u16), after: (&[u8; 16], u16)) -> Self {
+        let mut changes = Vec::new();
+        for (register, (old, new)) in before.0.iter().zip(after.0.iter()).enumerate() {
+            if old != new {
+                changes.push(Change {
+                    register: register as u8,
+                    value: *new as u16,
+                });
+            }
+        }
+        if before.1 != after.1 {
+            changes.push(Change {
+                register: INDEX,
+                value: after.1,
+            });
+        }
+        Entry {
+            pc: pc as u16,
+            opcode,
+            changes,
+        }
+    }
+
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", self)
+    }
+
+    /// Like `write_text` with the labels of the symbols in the instruction
+    /// and the closest label before it in a comment:
+    /// `0200 2204 CALL draw ; main`
+    pub fn write_symbolic<W: Write>(&self, writer: &mut W, symbols: &Symbols) -> io::Result<()> {
+        let line = self.format(disasm::symbolic(self.opcode, symbols));
+        match symbols.symbolize(self.pc as usize) {
+            Some(symbol) => writeln!(writer, "{} ; {}", line, symbol),
+            None => writeln!(writer, "{}", line),
+        }
+    }
+
+    pub fn write_binary<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.pc.to_be_bytes())?;
+        writer.write_all(&self.opcode.to_be_bytes())?;
+        writer.write_all(&[self.changes.len() as u8])?;
+        for change in &self.changes {
+            writer.write_all(&[change.register])?;
+            writer.write_all(&change.value.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// `0200 6A02 LD VA, #02       VA=02`
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(disasm::disassemble(self.opcode)))
+    }
+}
+
+impl Entry {
+    fn format(&self, mnemonic: String) -> String {
+        let mut line = format!("{:04X} {:04X} {:<16}", self.pc, self.opcode, mnemonic);
+        for change in &self.changes {
+            match change.register {
+                INDEX => line += &format!(" I={:04X}", change.value),
+                v => line += &format!(" V{:X}={:02X}", v, change.value),
+            }
+        }
+        line.trim_end().to_string()
+    }
+}
+
+/// Log the instructions executed by the cpu, see `Cpu::set_tracer`
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: Format,
+    filter: Filter,
+}
+
+impl Tracer {
+    pub fn new(mut writer: Box<dyn Write>, format: Format, filter: Filter) -> io::Result<Self> {
+        if format == Format::Binary {
+            writer.write_all(MAGIC)?;
+            writer.write_all(&[VERSION])?;
+        }
+        Ok(Tracer {
+            writer,
+            format,
+            filter,
+        })
+    }
+
+    /// write the trace in a file, it's overwritten if it already exist
+    pub fn create(file: &str, format: Format, filter: Filter) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(file)?);
+        Tracer::new(Box::new(file), format, filter)
+    }
+
+    /// log the instruction if it pass the filter, the text traces use the
+    /// labels of the symbols
+    pub fn record(&mut self, entry: &Entry, symbols: &Symbols) -> io::Result<()> {
+        if !self.filter.matches(entry.pc as usize, entry.opcode) {
+            return Ok(());
+        }
+        match self.format {
+            Format::Text => entry.write_symbolic(&mut self.writer, symbols),
+            Format::Binary => entry.write_binary(&mut self.writer),
+        }
+    }
+}
+
+/// Read a trace written in any of the two formats, to compare it with
+/// another run of the emulator
+pub fn read<R: Read>(reader: R) -> io::Result<Vec<Entry>> {
+    let mut reader = BufReader::new(reader);
+    match reader.fill_buf()?.starts_with(MAGIC) {
+        true => read_binary(reader),
+        false => read_text(reader),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_binary<R: Read>(mut reader: R) -> io::Result<Vec<Entry>> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if header[4] != VERSION {
+        return Err(invalid(format!("Unknown trace version {}", header[4])));
+    }
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut entries = Vec::new();
+    let mut bytes = bytes.as_slice();
+    let truncated = || invalid(String::from("The trace is truncated"));
+    let word = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+    while !bytes.is_empty() {
+        let header = bytes.get(..5).ok_or_else(truncated)?;
+        let (pc, opcode, len) = (word(header), word(&header[2..]), header[4] as usize);
+        let changes = bytes.get(5..5 + len * 3).ok_or_else(truncated)?;
+        let changes = changes
+            .chunks(3)
+            .map(|c| Change {
+                register: c[0],
+                value: word(&c[1..]),
+            })
+            .collect();
+        entries.push(Entry {
+            pc,
+            opcode,
+            changes,
+        });
+        bytes = &bytes[5 + len * 3..];
+    }
+    Ok(entries)
+}
+
+fn read_text<R: BufRead>(reader: R) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let error = || invalid(format!("Invalid trace at line {} : {}", number + 1, line));
+        let hex = |n: &str| u16::from_str_radix(n, 16).map_err(|_| error());
+        // the labels are in a comment at the end of the line
+        let mut words = line
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace();
+        let pc = hex(words.next().ok_or_else(error)?)?;
+        let opcode = hex(words.next().ok_or_else(error)?)?;
+        // the words of the mnemonic never contain a `=`
+        let mut changes = Vec::new();
+        for change in words.filter_map(|w| w.split_once('=')) {
+            let register = match change.0 {
+                "I" => INDEX,
+                v if v.starts_with('V') => hex(&v[1..])? as u8,
+                _ => return Err(error()),
+            };
+            changes.push(Change {
+                register,
+                value: hex(change.1)?,
+            });
+        }
+        entries.push(Entry {
+            pc,
+            opcode,
+            changes,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!("".parse(), Ok(Filter::default()));
+        assert!(Filter::default().matches(0xFFFE, 0x6000));
+        let filter: Filter = "addr=200-2FF,class=8d".parse().unwrap();
+        assert!(filter.matches(0x200, 0x8AB4));
+        assert!(filter.matches(0x2FF, 0xD125));
+        assert!(!filter.matches(0x300, 0x8AB4));
+        assert!(!filter.matches(0x200, 0x6A02));
+        assert!("addr=2G0".parse::<Filter>().is_err());
+        assert!("class=X".parse::<Filter>().is_err());
+        assert!("pc=200".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn changes() {
+        let mut after = [0; 16];
+        after[0xA] = 2;
+        let entry = Entry::new(0x200, 0x6A02, (&[0; 16], 0), (&after, 0));
+        assert_eq!(
+            entry.changes,
+            vec![Change {
+                register: 0xA,
+                value: 2
+            }]
+        );
+
+        let entry = Entry::new(0x202, 0xA220, (&after, 0), (&after, 0x220));
+        assert_eq!(
+            entry.changes,
+            vec![Change {
+                register: INDEX,
+                value: 0x220
+            }]
+        );
+    }
+
+    #[test]
+    fn text() {
+        let entry = Entry {
+            pc: 0x200,
+            opcode: 0xF265,
+            changes: vec![
+                Change {
+                    register: 2,
+                    value: 0xFF,
+                },
+                Change {
+                    register: INDEX,
+                    value: 0x30,
+                },
+            ],
+        };
+        let mut text = Vec::new();
+        entry.write_text(&mut text).unwrap();
+        assert_eq!(text, b"0200 F265 LD V2, [I]       V2=FF I=0030\n");
+
+        let entry = Entry {
+            pc: 0x202,
+            opcode: 0x00E0,
+            changes: vec![],
+        };
+        let mut text = Vec::new();
+        entry.write_text(&mut text).unwrap();
+        assert_eq!(text, b"0202 00E0 CLS\n");
+    }
+
+    #[test]
+    fn symbolic() {
+        let symbols = "label main 200\nlabel draw 2D4";
+        let symbols = Symbols::parse(symbols, std::path::Path::new("")).unwrap();
+        let entries = vec![
+            Entry {
+                pc: 0x204,
+                opcode: 0x22D4,
+                changes: vec![],
+            },
+            Entry {
+                pc: 0x100,
+                opcode: 0x6A02,
+                changes: vec![Change {
+                    register: 0xA,
+                    value: 2,
+                }],
+            },
+        ];
+        let mut text = Vec::new();
+        for entry in &entries {
+            entry.write_symbolic(&mut text, &symbols).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8_lossy(&text),
+            "0204 22D4 CALL draw ; main+0x4\n0100 6A02 LD VA, #02       VA=02\n"
+        );
+        assert_eq!(read(text.as_slice()).unwrap(), entries);
+    }
+
+    #[test]
+    fn binary() {
+        let entry = Entry {
+            pc: 0x200,
+            opcode: 0xA220,
+            changes: vec![Change {
+                register: INDEX,
+                value: 0x220,
+            }],
+        };
+        let mut binary = Vec::new();
+        entry.write_binary(&mut binary).unwrap();
+        assert_eq!(binary, [0x02, 0x00, 0xA2, 0x20, 1, 16, 0x02, 0x20]);
+    }
+
+    #[test]
+    fn trace_cpu() {
+        let file = std::env::temp_dir().join("chip8-trace-test.txt");
+        let file = file.to_str().unwrap();
+        let filter = "class=6".parse().unwrap();
+        let mut chip = crate::new();
+        chip.load_rom(&[0x6A, 0x02, 0xA2, 0x20, 0x6B, 0x03]);
+        chip.set_tracer(Some(Tracer::create(file, Format::Text, filter).unwrap()));
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+        }
+        // flush the file
+        chip.set_tracer(None);
+
+        let trace = std::fs::read_to_string(file).unwrap();
+        std::fs::remove_file(file).unwrap();
+        assert_eq!(
+            trace,
+            "0200 6A02 LD VA, #02       VA=02\n0204 6B03 LD VB, #03       VB=03\n"
+        );
+    }
+
+    #[test]
+    fn read_back() {
+        let entries = vec![
+            Entry {
+                pc: 0x200,
+                opcode: 0xF265,
+                changes: vec![
+                    Change {
+                        register: 2,
+                        value: 0xFF,
+                    },
+                    Change {
+                        register: INDEX,
+                        value: 0x30,
+                    },
+                ],
+            },
+            Entry {
+                pc: 0x202,
+                opcode: 0x00E0,
+                changes: vec![],
+            },
+        ];
+        for format in [Format::Text, Format::Binary].iter() {
+            let mut trace = Vec::new();
+            if *format == Format::Binary {
+                trace.extend_from_slice(b"CH8T\x01");
+            }
+            for entry in &entries {
+                match format {
+                    Format::Text => entry.write_text(&mut trace).unwrap(),
+                    Format::Binary => entry.write_binary(&mut trace).unwrap(),
+                }
+            }
+            assert_eq!(read(trace.as_slice()).unwrap(), entries);
+            if *format == Format::Binary {
+                assert!(read(&trace[..trace.len() - 1]).is_err());
+            }
+        }
+        assert!(read(&b"0200 zzzz CLS\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use crate::cpu::{Fault, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::machine::Machine;
+use crate::rca1802::{Bus, Rca1802};
+use crate::timing::VIP_FRAME_CYCLES;
+use alloc::{format, string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::fs;
+
+/// the RAM of the VIP, mirrored in the first half of the addresses
+pub const RAM_SIZE: usize = 0x1000;
+/// the monitor ROM, mirrored in the second half of the addresses
+pub const MONITOR_SIZE: usize = 0x200;
+/// where the interpreter loads the games
+const START_ADDRESS: usize = 0x200;
+
+// The CDP1861 draws 262 lines of 14 machine cycles each frame, the 128
+// lines in the middle are displayed. It asks for an interrupt 2 lines
+// before them and raises EF1 in the 4 lines before the first and the last
+// displayed line. Each displayed line takes 8 bytes by DMA.
+const LINE_CYCLES: u32 = 14;
+const LINES: u32 = VIP_FRAME_CYCLES as u32 / LINE_CYCLES;
+const INTERRUPT_LINE: u32 = 78;
+const DISPLAY_START: u32 = 80;
+const DISPLAY_END: u32 = DISPLAY_START + 128;
+const DMA_BYTES: u32 = 8;
+
+/// The memory and the devices of the VIP, as seen by the 1802
+struct Hardware {
+    ram: Vec<u8>,
+    monitor: Vec<u8>,
+    overlay: bool, // the monitor is also at 0 until an address with A15 is read
+    display: bool, // the CDP1861 is on
+    ef1: bool,     // the display is about to start or end
+    latch: u8,     // the key tested by EF3
+    keys: [bool; 16],
+    q: bool, // the tone
+}
+
+impl Bus for Hardware {
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.overlay = false;
+        }
+        match self.overlay || address & 0x8000 != 0 {
+            true => self.monitor[address as usize % MONITOR_SIZE],
+            false => self.ram[address as usize % RAM_SIZE],
+        }
+    }
+
+    /// the ROM can't be written
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0x8000 == 0 {
+            self.ram[address as usize % RAM_SIZE] = value;
+        }
+    }
+
+    /// INP 1 turns the display on
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display = true;
+        }
+        0
+    }
+
+    /// OUT 1 turns the display off and OUT 2 selects the key read by EF3
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display = false,
+            2 => self.latch = value & 0xF,
+            _ => (),
+        }
+    }
+
+    fn flag(&mut self, n: u8) -> bool {
+        match n {
+            1 => self.ef1,
+            3 => self.keys[self.latch as usize],
+            _ => false,
+        }
+    }
+
+    fn set_q(&mut self, q: bool) {
+        self.q = q;
+    }
+}
+
+/// A COSMAC VIP: a RCA 1802, a CDP1861 for the video, the hex keypad and
+/// 4ko of RAM. It runs the monitor ROM and the chip 8 interpreter given by
+/// the user, so the games run on the real interpreter with all its quirks.
+///
+/// The 1861 displays 128 lines of 64 pixels, the interpreter repeats each
+/// line of the chip 8 screen 4 times so the framebuffer keeps the first of
+/// every 4 lines to have the same 64x32 screen as `Cpu`.
+pub struct Vip {
+    cpu: Rca1802,
+    bus: Hardware,
+    image: Vec<u8>, // the RAM after loading the interpreter and the game
+
+    cycle: u32,      // position in the frame of the 1861
+    interrupt: bool, // the 1861 is asking for an interrupt
+    budget: i32,     // cycles left in the frame given by `vblank`
+
+    frame: Vec<bool>, // the frame being displayed by the 1861
+    screen: Framebuffer,
+    draw: bool,
+}
+
+impl Vip {
+    /// a VIP with the interpreter loaded at 0, the monitor must be the
+    /// 512 bytes of the ROM
+    pub fn new(monitor: &[u8], interpreter: &[u8]) -> Result<Self, String> {
+        if monitor.len() != MONITOR_SIZE {
+            return Err(format!(
+                "The monitor ROM must be {} bytes, not {}",
+                MONITOR_SIZE,
+                monitor.len()
+            ));
+        }
+        if interpreter.len() > START_ADDRESS {
+            return Err(format!(
+                "The interpreter must fit in {} bytes, it takes {}",
+                START_ADDRESS,
+                interpreter.len()
+            ));
+        }
+        let mut image = vec![0; RAM_SIZE];
+        image[..interpreter.len()].copy_from_slice(interpreter);
+        let mut vip = Vip {
+            cpu: Rca1802::default(),
+            bus: Hardware {
+                ram: image.clone(),
+                monitor: monitor.to_vec(),
+                overlay: true,
+                display: false,
+                ef1: false,
+                latch: 0,
+                keys: [false; 16],
+                q: false,
+            },
+            image,
+
+            cycle: 0,
+            interrupt: false,
+            budget: 0,
+
+            frame: vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: Framebuffer::new(SCREEN_WIDTH, SCREEN_HEIGHT, 1),
+            draw: true,
+        };
+        vip.reset();
+        Ok(vip)
+    }
+
+    /// load the monitor and the interpreter from files
+    #[cfg(feature = "std")]
+    pub fn load(monitor: &str, interpreter: &str) -> Result<Self, String> {
+        let read = |file: &str| fs::read(file).map_err(|e| format!("Can't load {} : {}", file, e));
+        Vip::new(&read(monitor)?, &read(interpreter)?)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load_game(&mut self, file: &str) -> std::io::Result<()> {
+        let rom = fs::read(file)?;
+        self.load_rom(&rom);
+        Ok(())
+    }
+
+    /// load a game after the interpreter, what doesn't fit in the RAM is
+    /// dropped
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        let rom = &rom[..rom.len().min(RAM_SIZE - START_ADDRESS)];
+        self.image[START_ADDRESS..START_ADDRESS + rom.len()].copy_from_slice(rom);
+        self.reset();
+    }
+
+    /// Press the reset switch: the RAM is reloaded and the 1802 starts the
+    /// monitor, which runs the interpreter unless the key C is held
+    pub fn reset(&mut self) {
+        self.bus.ram.copy_from_slice(&self.image);
+        self.bus.overlay = true;
+        self.bus.display = false;
+        self.bus.q = false;
+        self.cpu.reset();
+        self.cycle = 0;
+        self.interrupt = false;
+    }
+
+    /// the registers of the 1802
+    pub fn rca(&self) -> &Rca1802 {
+        &self.cpu
+    }
+
+    /// the 4ko of RAM
+    pub fn memory(&self) -> &[u8] {
+        &self.bus.ram
+    }
+
+    /// Run one instruction of the 1802, or take the interrupt, and return
+    /// the machine cycles it took with the DMA of the display
+    pub fn step(&mut self) -> u32 {
+        let cycles = match self.interrupt && self.cpu.interrupt() {
+            true => {
+                self.interrupt = false;
+                1
+            }
+            false => self.cpu.step(&mut self.bus),
+        };
+        self.advance(cycles)
+    }
+
+    /// move the 1861 by `cycles`, with the DMA of the lines it started
+    fn advance(&mut self, mut cycles: u32) -> u32 {
+        let mut total = 0;
+        while cycles > 0 {
+            cycles -= 1;
+            total += 1;
+            self.cycle = (self.cycle + 1) % (LINES * LINE_CYCLES);
+            if self.cycle.is_multiple_of(LINE_CYCLES) {
+                cycles += self.line(self.cycle / LINE_CYCLES);
+            }
+        }
+        total
+    }
+
+    /// the start of a line, return the cycles taken by the DMA
+    fn line(&mut self, line: u32) -> u32 {
+        self.bus.ef1 = (DISPLAY_START - 4..DISPLAY_START).contains(&line)
+            || (DISPLAY_END - 4..DISPLAY_END).contains(&line);
+        match line {
+            INTERRUPT_LINE => self.interrupt = self.bus.display,
+            DISPLAY_START => self.interrupt = false,
+            DISPLAY_END => {
+                self.screen.split();
+                for (i, lit) in self.frame.iter().enumerate() {
+                    self.screen
+                        .set(i % SCREEN_WIDTH, i / SCREEN_WIDTH, *lit as u8);
+                }
+                self.draw |= self.screen.dirty().is_some();
+            }
+            _ => (),
+        }
+        if !self.bus.display || !(DISPLAY_START..DISPLAY_END).contains(&line) {
+            return 0;
+        }
+
+        let line = (line - DISPLAY_START) as usize;
+        for i in 0..DMA_BYTES as usize {
+            let byte = self.cpu.dma_out(&mut self.bus);
+            if line.is_multiple_of(4) {
+                let pixels = &mut self.frame[line / 4 * SCREEN_WIDTH + i * 8..][..8];
+                for (x, lit) in pixels.iter_mut().enumerate() {
+                    *lit = byte & (0x80 >> x) != 0;
+                }
+            }
+        }
+        DMA_BYTES
+    }
+}
+
+impl Machine for Vip {
+    /// the instructions of the 1802 don't fail
+    fn cycle(&mut self) -> Result<(), Fault> {
+        if self.budget > 0 {
+            self.budget -= self.step() as i32;
+        }
+        Ok(())
+    }
+
+    /// the VIP runs at the speed of its clock, a frame of cycles every 1/60s
+    fn vblank(&mut self) {
+        self.budget = self.budget.min(0) + VIP_FRAME_CYCLES;
+    }
+
+    fn frame_budget(&self) -> Option<u32> {
+        Some(self.budget.max(0) as u32)
+    }
+
+    fn reset(&mut self) {
+        Vip::reset(self)
+    }
+
+    /// the PC of the 1802
+    fn pc(&self) -> usize {
+        self.cpu.pc() as usize
+    }
+
+    /// the interpreter keeps the PC of the chip 8 in R5
+    fn stack_trace(&self) -> String {
+        format!(
+            "  1802 at {:#06X} (R{:X}), chip 8 at {:#05X}",
+            self.cpu.pc(),
+            self.cpu.p,
+            self.cpu.r[5]
+        )
+    }
+
+    fn keys(&mut self) -> &mut [bool; 16] {
+        &mut self.bus.keys
+    }
+
+    fn update(&mut self) -> Option<&Framebuffer> {
+        let draw = self.draw;
+        self.draw = false;
+        match draw {
+            true => {
+                self.screen.present();
+                Some(&self.screen)
+            }
+            false => None,
+        }
+    }
+
+    fn drawing(&self) -> bool {
+        self.draw
+    }
+
+    fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    /// Q drives the speaker
+    fn sound(&self) -> bool {
+        self.bus.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// jump to the monitor to remove the overlay then run the RAM from 0
+    /// with R3 as PC, like the real monitor when C isn't held
+    fn monitor() -> Vec<u8> {
+        // LBR 8003, LDI 0, PHI R3, PLO R3, SEP R3
+        let mut monitor = vec![0xC0, 0x80, 0x03, 0xF8, 0x00, 0xB3, 0xA3, 0xD3];
+        monitor.resize(MONITOR_SIZE, 0);
+        monitor
+    }
+
+    /// turn the display on, point the DMA at 0xF00 in the interrupt and
+    /// wait for the key A to put 42 in RF.1
+    #[rustfmt::skip]
+    fn program() -> Vec<u8> {
+        let mut program = vec![
+            0xF8, 0x00, 0xB1, 0xF8, 0x40, 0xA1, // R1 = 0x040
+            0xF8, 0x0E, 0xB2, 0xF8, 0x00, 0xA2, // R2 = 0xE00
+            0xE2, 0x69,                         // SEX R2, INP 1
+            0xF8, 0x0A, 0x52, 0x62,             // OUT 2 with A
+            0x36, 0x18, 0x30, 0x12,             // B3 018, BR 012
+            0x00, 0x00,
+            0xF8, 0x42, 0xBF, 0x30, 0x1B,       // RF.1 = 42, BR 01B
+        ];
+        program.resize(0x3F, 0);
+        program.extend([
+            0x70,                               // RET
+            0x22, 0x78,                         // DEC R2, SAV
+            0xF8, 0x0F, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0xF00
+            0x30, 0x3F,                         // BR 03F
+        ]);
+        program
+    }
+
+    fn frame(vip: &mut Vip) {
+        vip.vblank();
+        while vip.frame_budget() > Some(0) {
+            vip.cycle().unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Vip::new(&[0; 16], &program()).is_err());
+        assert!(Vip::new(&monitor(), &[0; 0x201]).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let mut vip = Vip::new(&monitor(), &program()).unwrap();
+        // the lines 0 and 4 of the 1861 are the rows 0 and 1 of the screen
+        vip.image[0xF00] = 0xFF;
+        vip.image[0xF08] = 0xFF;
+        vip.image[0xF20] = 0x81;
+        vip.reset();
+        frame(&mut vip);
+        frame(&mut vip);
+        assert!(!vip.bus.overlay);
+        assert!(vip.bus.display);
+
+        let screen = vip.update().unwrap();
+        assert!((0..8).all(|x| screen.lit(x, 0)));
+        assert!(!screen.lit(8, 0));
+        assert_eq!(screen.pixels()[64..72], [1, 0, 0, 0, 0, 0, 0, 1]);
+        // the DMA wraps to the program after the row 7
+        assert!(screen.pixels()[128..64 * 8].iter().all(|p| *p == 0));
+        assert_eq!(vip.update(), None);
+    }
+
+    #[test]
+    fn keypad() {
+        let mut vip = Vip::new(&monitor(), &program()).unwrap();
+        frame(&mut vip);
+        assert_eq!(vip.rca().r[0xF], 0);
+        assert!((0x12..0x16).contains(&vip.pc()));
+        vip.keys()[0xA] = true;
+        frame(&mut vip);
+        assert_eq!(vip.rca().r[0xF], 0x4200);
+    }
+
+    #[test]
+    fn timing() {
+        let mut vip = Vip::new(&monitor(), &program()).unwrap();
+        // the 1802 runs a frame of the 1861 in every frame, with the DMA
+        for _ in 0..10 {
+            frame(&mut vip);
+        }
+        assert!(vip.cycle < 16, "{}", vip.cycle);
+        assert_eq!(vip.frame_budget(), Some(0));
+    }
+    /// Run a game on the real monitor and interpreter, which aren't in the
+    /// repository. Give the path of their dumps in `CHIP8_VIP_MONITOR` and
+    /// `CHIP8_VIP_INTERPRETER` then run `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn interpreter() {
+        let read = |var: &str| {
+            let file = std::env::var(var).unwrap_or_else(|_| panic!("{} isn't set", var));
+            std::fs::read(&file).unwrap_or_else(|e| panic!("Can't load {} : {}", file, e))
+        };
+        let mut vip = Vip::new(&read("CHIP8_VIP_MONITOR"), &read("CHIP8_VIP_INTERPRETER")).unwrap();
+        #[rustfmt::skip]
+        vip.load_rom(&[
+            0x60, 0x00, 0x61, 0x00, // V0 = 0, V1 = 0
+            0xF0, 0x29,             // I = the 0 of the font
+            0xD0, 0x15,             // draw it in the corner
+            0x12, 0x08,             // loop
+        ]);
+        // the monitor and the interpreter take a few frames to start
+        for _ in 0..60 {
+            frame(&mut vip);
+        }
+
+        let screen = vip.update().unwrap();
+        assert_eq!((screen.width(), screen.height()), (64, 32));
+        let glyph = [0xF0, 0x90, 0x90, 0x90, 0xF0];
+        for y in 0..32 {
+            for x in 0..64 {
+                let row = glyph.get(y).copied().unwrap_or(0);
+                let lit = x < 8 && row & (0x80 >> x) != 0;
+                assert_eq!(screen.lit(x, y), lit, "pixel at ({}, {})", x, y);
+            }
+        }
+    }
+}
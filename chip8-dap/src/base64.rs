@@ -0,0 +1,70 @@
+//! The memory requests exchange the bytes in base64
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let block = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |block, (i, b)| block | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(ALPHABET[(block >> (18 - 6 * i)) as usize & 0x3F] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
+
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut block, mut bits) = (0u32, 0);
+    for c in text
+        .bytes()
+        .filter(|c| !c.is_ascii_whitespace() && *c != b'=')
+    {
+        let value = ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or_else(|| format!("Invalid base64 character `{}`", c as char))?;
+        block = (block << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((block >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"M"), "TQ==");
+        assert_eq!(encode(b"Ma"), "TWE=");
+        assert_eq!(encode(b"Man"), "TWFu");
+        assert_eq!(decode("TQ==").unwrap(), b"M");
+        assert_eq!(decode("TW\nFu").unwrap(), b"Man");
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        for len in 0..8 {
+            assert_eq!(decode(&encode(&bytes[..len])).unwrap(), &bytes[..len]);
+        }
+        assert_eq!(decode(&encode(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(decode("TW-u").unwrap_err(), "Invalid base64 character `-`");
+    }
+}
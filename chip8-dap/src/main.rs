@@ -0,0 +1,80 @@
+use serde_json::Value;
+use std::env;
+use std::io;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+mod base64;
+mod protocol;
+mod session;
+use crate::protocol::Output;
+use crate::session::Session;
+
+const USAGE: &str = "Usage: chip8-dap
+
+A debug adapter for the editors speaking the Debug Adapter Protocol. It talks
+to the editor over stdin and stdout and runs the game without any window.
+The launch request takes the `program` to debug, an optional `symbols` file
+to name the addresses and put breakpoints on the sources, `stopOnEntry`, the
+`machine` and `quirks` presets and the `seed`.";
+
+/// the number of instructions executed between two checks of the requests
+const CYCLES_PER_POLL: usize = 8;
+
+fn main() -> io::Result<()> {
+    if env::args().len() > 1 {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    // the requests are read on their own thread so we can receive a pause
+    // while the game is running
+    let (sender, receiver) = mpsc::channel::<Value>();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        while let Ok(Some(request)) = protocol::read(&mut reader) {
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut output = Output::new(io::stdout());
+    let mut session = Session::default();
+    loop {
+        let request = match session.running() {
+            true => match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            },
+            false => match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            },
+        };
+
+        if let Some(request) = request {
+            let result = session.handle(&request);
+            output.respond(&request, result)?;
+            for (event, body) in session.events() {
+                output.event(event, body)?;
+            }
+            if request["command"] == "disconnect" || request["command"] == "terminate" {
+                output.event("terminated", serde_json::json!({}))?;
+                break;
+            }
+        }
+
+        if session.running() {
+            session.run(CYCLES_PER_POLL);
+            for (event, body) in session.events() {
+                output.event(event, body)?;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use serde_json::{json, Value};
+use std::io::{self, BufRead, ErrorKind, Write};
+
+/// Read one message: the headers, an empty line and then `Content-Length`
+/// bytes of json.
+/// Return `None` when the client closed the connection.
+pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length =
+        length.ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Send the responses and events to the client, numbering them as we go
+pub struct Output<W> {
+    writer: W,
+    seq: u64,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(writer: W) -> Self {
+        Output { writer, seq: 0 }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()
+    }
+
+    pub fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    pub fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn framing() {
+        let body = r#"{"seq":1,"type":"request","command":"threads"}"#;
+        let message = format!(
+            "Content-Length: {}\r\nContent-Type: application/json\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let mut reader = Cursor::new(message.repeat(2));
+        for _ in 0..2 {
+            let request = read(&mut reader).unwrap().unwrap();
+            assert_eq!(request["command"], "threads");
+        }
+        assert!(read(&mut reader).unwrap().is_none());
+
+        let mut reader = Cursor::new("Content-Type: json\r\n\r\n{}");
+        assert_eq!(
+            read(&mut reader).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let mut output = Output::new(Vec::new());
+        let request = json!({ "seq": 7, "command": "pause" });
+        output.respond(&request, Ok(json!({}))).unwrap();
+        output.respond(&request, Err(String::from("no"))).unwrap();
+        output
+            .event("stopped", json!({ "reason": "pause" }))
+            .unwrap();
+
+        // what we send can be read back, numbered in order
+        let mut reader = Cursor::new(output.writer);
+        let ok = read(&mut reader).unwrap().unwrap();
+        assert_eq!(ok["seq"], 1);
+        assert_eq!(ok["request_seq"], 7);
+        assert_eq!(ok["success"], true);
+        let error = read(&mut reader).unwrap().unwrap();
+        assert_eq!(error["seq"], 2);
+        assert_eq!(error["success"], false);
+        assert_eq!(error["message"], "no");
+        let event = read(&mut reader).unwrap().unwrap();
+        assert_eq!(event["seq"], 3);
+        assert_eq!(event["type"], "event");
+        assert_eq!(event["body"]["reason"], "pause");
+        assert!(read(&mut reader).unwrap().is_none());
+    }
+}
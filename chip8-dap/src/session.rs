@@ -0,0 +1,726 @@
+use crate::base64;
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::disasm;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::quirks::Quirks;
+use chip8_cpu::symbols::Symbols;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+/// the chip8 has a single thread of execution
+const THREAD: u64 = 1;
+
+/// the `variablesReference` of the scopes
+const REGISTERS: u64 = 1;
+const TIMERS: u64 = 2;
+const STACK: u64 = 3;
+const KEYS: u64 = 4;
+
+/// How far the game runs before we stop it again
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Run {
+    /// until a breakpoint or a pause request
+    Continue,
+    /// a single instruction
+    Step,
+    /// until the stack goes back to this depth, to step over the calls
+    StepOver(usize),
+    /// until the stack is shallower than this depth
+    StepOut(usize),
+}
+
+/// The state of a debugging session, from the `launch` request to the
+/// `disconnect`
+#[derive(Default)]
+pub struct Session {
+    chip: Option<Box<Cpu>>,
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    function_breakpoints: Vec<usize>,
+    instruction_breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    running: Option<Run>,
+    /// the breakpoint we're resuming from, to not stop on it again
+    resume_pc: Option<usize>,
+    /// the events to send after the response to the current request
+    events: Vec<(&'static str, Value)>,
+}
+
+impl Session {
+    pub fn running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// the events generated since the last call
+    pub fn events(&mut self) -> Vec<(&'static str, Value)> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn handle(&mut self, request: &Value) -> Result<Value, String> {
+        let arguments = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => self.initialize(),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "chip8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(scopes()),
+            "variables" => self.variables(arguments),
+            "setVariable" => self.set_variable(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "writeMemory" => self.write_memory(arguments),
+            "disassemble" => self.disassemble(arguments),
+            "continue" => self.resume(Run::Continue),
+            "next" => {
+                let depth = self.chip()?.stack().len();
+                self.resume(Run::StepOver(depth))
+            }
+            "stepIn" => self.resume(Run::Step),
+            "stepOut" => {
+                let depth = self.chip()?.stack().len();
+                self.resume(Run::StepOut(depth))
+            }
+            "pause" => {
+                if self.running.take().is_some() {
+                    self.stopped("pause", None);
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.running = None;
+                Ok(json!({}))
+            }
+            command => Err(format!("Unsupported request `{}`", command)),
+        }
+    }
+
+    fn chip(&self) -> Result<&Cpu, String> {
+        self.chip
+            .as_deref()
+            .ok_or_else(|| String::from("No game launched"))
+    }
+
+    fn chip_mut(&mut self) -> Result<&mut Cpu, String> {
+        self.chip
+            .as_deref_mut()
+            .ok_or_else(|| String::from("No game launched"))
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.events.push(("stopped", body));
+    }
+
+    fn initialize(&mut self) -> Result<Value, String> {
+        self.events.push(("initialized", json!({})));
+        Ok(json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsSetVariable": true,
+            "supportsEvaluateForHovers": true,
+            "supportsReadMemoryRequest": true,
+            "supportsWriteMemoryRequest": true,
+            "supportsDisassembleRequest": true,
+            "supportsInstructionBreakpoints": true,
+            "supportsFunctionBreakpoints": true,
+            "supportsTerminateRequest": true,
+        }))
+    }
+
+    /// The arguments are the `program` to debug, an optional `symbols` file
+    /// to name the addresses and put breakpoints on the lines of the
+    /// sources, `stopOnEntry` (true by default), the `machine` and `quirks`
+    /// presets and the `seed` of the random numbers.
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or_else(|| String::from("Missing the `program` to debug"))?;
+        let rom = fs::read(program).map_err(|e| format!("Can't load {} : {}", program, e))?;
+        let machine = match arguments["machine"].as_str() {
+            Some(machine) => machine.parse::<MachineConfig>()?,
+            None => MachineConfig::default(),
+        };
+        let mut chip = Box::new(Cpu::with_config(machine)?);
+        chip.load_rom(&rom);
+        if let Some(quirks) = arguments["quirks"].as_str() {
+            chip.set_quirks(quirks.parse::<Quirks>()?);
+        }
+        if let Some(seed) = arguments["seed"].as_u64() {
+            chip.set_seed(seed);
+        }
+        if let Some(symbols) = arguments["symbols"].as_str() {
+            chip.set_symbols(Symbols::load(symbols)?);
+        }
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(true);
+        self.chip = Some(chip);
+        Ok(json!({}))
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        self.chip()?;
+        match self.stop_on_entry {
+            true => self.stopped("entry", None),
+            false => self.running = Some(Run::Continue),
+        }
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["source"]["path"]
+            .as_str()
+            .ok_or_else(|| String::from("Missing the path of the source"))?;
+        let symbols = self.chip()?.symbols();
+        let requested = arguments["breakpoints"].as_array().cloned();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested.unwrap_or_default() {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            match symbols.line_address(path, line) {
+                Some((address, line)) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": reference(address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instruction on this line",
+                })),
+            }
+        }
+        self.source_breakpoints.insert(path.to_string(), addresses);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// the functions are given by their label, or anything else
+    /// `Symbols::resolve` understand
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let symbols = self.chip()?.symbols();
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+        {
+            match symbols.resolve(breakpoint["name"].as_str().unwrap_or_default()) {
+                Ok(address) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": reference(address),
+                    }));
+                }
+                Err(message) => breakpoints.push(json!({
+                    "verified": false,
+                    "message": message,
+                })),
+            }
+        }
+        self.function_breakpoints = addresses;
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        self.instruction_breakpoints.clear();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default()
+        {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(number)
+                .map(|a| a as i64 + breakpoint["offset"].as_i64().unwrap_or_default());
+            match address {
+                Some(address) if address >= 0 => {
+                    self.instruction_breakpoints.push(address as usize);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": reference(address as usize),
+                    }));
+                }
+                _ => breakpoints.push(json!({
+                    "verified": false,
+                    "message": "Invalid address",
+                })),
+            }
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn breakpoint(&self, pc: usize) -> bool {
+        self.instruction_breakpoints.contains(&pc)
+            || self.function_breakpoints.contains(&pc)
+            || self.source_breakpoints.values().any(|a| a.contains(&pc))
+    }
+
+    fn resume(&mut self, run: Run) -> Result<Value, String> {
+        let pc = self.chip()?.pc();
+        self.resume_pc = Some(pc);
+        self.running = Some(run);
+        Ok(json!({ "allThreadsContinued": true }))
+    }
+
+    /// Execute up to `cycles` instructions, or less if we have to stop
+    pub fn run(&mut self, cycles: usize) {
+        let run = match (self.running, self.chip.is_some()) {
+            (Some(run), true) => run,
+            _ => return,
+        };
+        for _ in 0..cycles {
+            let pc = self.chip.as_ref().unwrap().pc();
+            if self.resume_pc.take() != Some(pc) && self.breakpoint(pc) {
+                self.running = None;
+                return self.stopped("breakpoint", None);
+            }
+
+            let chip = self.chip.as_deref_mut().unwrap();
+            // the faults leave the cpu as it was before the instruction, the
+            // user can fix it and continue
+            if let Err(fault) = chip.cycle() {
+                let message = format!("{}\n{}", fault, chip.stack_trace());
+                self.running = None;
+                self.events.push((
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                ));
+                let summary = message.lines().next().unwrap_or_default().to_string();
+                return self.stopped("exception", Some(summary));
+            }
+
+            let depth = chip.stack().len();
+            let done = match run {
+                Run::Continue => false,
+                Run::Step => true,
+                Run::StepOver(d) => depth <= d,
+                Run::StepOut(d) => depth < d,
+            };
+            if done {
+                self.running = None;
+                return self.stopped("step", None);
+            }
+        }
+    }
+
+    /// a frame for the current instruction and one for each call in the stack
+    fn stack_trace(&self) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let symbols = chip.symbols();
+        let addresses = std::iter::once(chip.pc()).chain(chip.stack().iter().rev().copied());
+        let frames = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": symbols.symbolize(address).unwrap_or_else(|| reference(address)),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": reference(address),
+                });
+                if let Some((path, line)) = symbols.location(address) {
+                    frame["source"] = json!({ "path": path.to_string_lossy() });
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let variables = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS) => {
+                let mut variables = chip
+                    .registers()
+                    .iter()
+                    .enumerate()
+                    .map(|(x, v)| variable(&format!("V{:X}", x), format!("{:#04X}", v)))
+                    .collect::<Vec<_>>();
+                let mut index = variable("I", format!("{:#05X}", chip.index()));
+                index["memoryReference"] = json!(reference(chip.index() as usize));
+                let mut pc = variable("PC", reference(chip.pc()));
+                pc["memoryReference"] = json!(reference(chip.pc()));
+                variables.push(index);
+                variables.push(pc);
+                variables
+            }
+            Some(TIMERS) => {
+                let (delay, sound) = chip.timers();
+                vec![
+                    variable("DT", format!("{:#04X}", delay)),
+                    variable("ST", format!("{:#04X}", sound)),
+                ]
+            }
+            Some(STACK) => chip
+                .stack()
+                .iter()
+                .enumerate()
+                .map(|(i, address)| variable(&format!("{}", i), reference(*address)))
+                .collect(),
+            Some(KEYS) => chip
+                .key
+                .iter()
+                .enumerate()
+                .map(|(k, pressed)| variable(&format!("{:X}", k), pressed.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn set_variable(&mut self, arguments: &Value) -> Result<Value, String> {
+        let name = arguments["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_uppercase();
+        let value = arguments["value"].as_str().unwrap_or_default();
+        let invalid = || format!("Invalid value `{}`", value);
+        let chip = self.chip_mut()?;
+        match (arguments["variablesReference"].as_u64(), name.as_str()) {
+            (Some(KEYS), key) => {
+                let key = usize::from_str_radix(key, 16).unwrap_or(usize::MAX);
+                *chip.key.get_mut(key).ok_or_else(invalid)? =
+                    value.parse().map_err(|_| invalid())?;
+            }
+            (Some(STACK), _) => return Err(String::from("The stack is read only")),
+            (_, name) => {
+                let number = number(value).ok_or_else(invalid)?;
+                set(chip, name, number)?;
+            }
+        }
+        Ok(json!({ "value": value }))
+    }
+
+    /// `V0` to `VF`, `I`, `PC`, `DT`, `ST`, the address of a label or a byte
+    /// of memory as `[ADDRESS]` or `[LABEL]`
+    fn evaluate(&self, arguments: &Value) -> Result<Value, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default().trim();
+        let chip = self.chip()?;
+        let symbols = chip.symbols();
+        let unknown = || format!("Can't evaluate `{}`", expression);
+        let result = match expression.to_uppercase().as_str() {
+            "I" => format!("{:#05X}", chip.index()),
+            "PC" => reference(chip.pc()),
+            "DT" => format!("{:#04X}", chip.timers().0),
+            "ST" => format!("{:#04X}", chip.timers().1),
+            e if e.starts_with('V') && e.len() == 2 => {
+                let x = usize::from_str_radix(&e[1..], 16).map_err(|_| unknown())?;
+                format!("{:#04X}", chip.registers()[x])
+            }
+            e if e.starts_with('[') && e.ends_with(']') => {
+                let inner = expression[1..e.len() - 1].trim();
+                let address = symbols.address_of(inner).or_else(|| number(inner));
+                let address = address.ok_or_else(unknown)?;
+                let byte = chip.memory().get(address).ok_or_else(unknown)?;
+                format!("{:#04X}", byte)
+            }
+            _ => {
+                let address = symbols.address_of(expression).ok_or_else(unknown)?;
+                return Ok(json!({
+                    "result": reference(address),
+                    "memoryReference": reference(address),
+                    "variablesReference": 0,
+                }));
+            }
+        };
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let memory = self.chip()?.memory();
+        let start = address(arguments)?.min(memory.len());
+        let count = arguments["count"].as_u64().unwrap_or_default() as usize;
+        let end = start.saturating_add(count).min(memory.len());
+        Ok(json!({
+            "address": reference(start),
+            "data": base64::encode(&memory[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    fn write_memory(&mut self, arguments: &Value) -> Result<Value, String> {
+        let start = address(arguments)?;
+        let bytes = base64::decode(arguments["data"].as_str().unwrap_or_default())?;
+        self.chip_mut()?.write_memory(start, &bytes)?;
+        Ok(json!({ "bytesWritten": bytes.len() }))
+    }
+
+    fn disassemble(&self, arguments: &Value) -> Result<Value, String> {
+        let chip = self.chip()?;
+        let start = address(arguments)? as i64
+            + arguments["instructionOffset"].as_i64().unwrap_or_default() * 2;
+        let count = arguments["instructionCount"].as_u64().unwrap_or_default() as i64;
+        let instructions = (0..count)
+            .map(|i| start + i * 2)
+            .map(|address| match address {
+                a if a < 0 || a as usize + 1 >= chip.memory().len() => json!({
+                    "address": format!("{:#X}", a),
+                    "instruction": "??",
+                    "presentationHint": "invalid",
+                }),
+                a => {
+                    let a = a as usize;
+                    let memory = chip.memory();
+                    let mut instruction = json!({
+                        "address": reference(a),
+                        "instructionBytes": format!("{:02X} {:02X}", memory[a], memory[a + 1]),
+                        "instruction": disasm::listing(memory, a, chip.symbols()),
+                    });
+                    if let Some(label) = chip.symbols().label(a) {
+                        instruction["symbol"] = json!(label);
+                    }
+                    if let Some((path, line)) = chip.symbols().location(a) {
+                        instruction["location"] = json!({ "path": path.to_string_lossy() });
+                        instruction["line"] = json!(line);
+                    }
+                    instruction
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "instructions": instructions }))
+    }
+}
+
+fn scopes() -> Value {
+    let scope = |name: &str, reference: u64| json!({ "name": name, "variablesReference": reference, "expensive": false });
+    json!({
+        "scopes": [
+            scope("Registers", REGISTERS),
+            scope("Timers", TIMERS),
+            scope("Stack", STACK),
+            scope("Keys", KEYS),
+        ]
+    })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+/// the addresses are exchanged as `0x200`
+fn reference(address: usize) -> String {
+    format!("0x{:03X}", address)
+}
+
+/// a number in hexadecimal with `0x` or in decimal
+fn number(text: &str) -> Option<usize> {
+    let text = text.trim();
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// the `memoryReference` plus the `offset` of a memory request
+fn address(arguments: &Value) -> Result<usize, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    let base = number(reference).ok_or_else(|| format!("Invalid address `{}`", reference))?;
+    let address = base as i64 + arguments["offset"].as_i64().unwrap_or_default();
+    match address >= 0 {
+        true => Ok(address as usize),
+        false => Err(format!("Invalid address {}", address)),
+    }
+}
+
+fn set(chip: &mut Cpu, name: &str, value: usize) -> Result<(), String> {
+    let (delay, sound) = chip.timers();
+    let byte = || match value <= 0xFF {
+        true => Ok(value as u8),
+        false => Err(format!("{} doesn't fit in a byte", value)),
+    };
+    match name {
+        "I" if value <= 0xFFFF => chip.set_index(value as u16)?,
+        "PC" => chip.set_pc(value)?,
+        "DT" => chip.set_timers(byte()?, sound),
+        "ST" => chip.set_timers(delay, byte()?),
+        n if n.starts_with('V') && n.len() == 2 => {
+            let x = usize::from_str_radix(&n[1..], 16).map_err(|_| format!("Unknown {}", n))?;
+            chip.set_register(x, byte()?)?
+        }
+        n => return Err(format!("Can't set {} to {}", n, value)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// call a subroutine then loop forever
+    #[rustfmt::skip]
+    const ROM: [u8; 12] = [
+        0x60, 0x01, // 200: V0 = 1
+        0x22, 0x08, // 202: call 208
+        0x60, 0x03, // 204: V0 = 3
+        0x12, 0x06, // 206: loop
+        0x61, 0x02, // 208: V1 = 2
+        0x00, 0xEE, // 20A: return
+    ];
+
+    fn request(session: &mut Session, command: &str, arguments: Value) -> Result<Value, String> {
+        session.handle(&json!({ "seq": 1, "command": command, "arguments": arguments }))
+    }
+
+    /// a session stopped on the entry of the rom
+    fn launch(name: &str) -> Session {
+        let program = std::env::temp_dir().join(format!("chip8-dap-{}.ch8", name));
+        fs::write(&program, ROM).unwrap();
+        let mut session = Session::default();
+        request(&mut session, "initialize", json!({})).unwrap();
+        let arguments = json!({ "program": program.to_str().unwrap() });
+        request(&mut session, "launch", arguments).unwrap();
+        fs::remove_file(&program).unwrap();
+        request(&mut session, "configurationDone", json!({})).unwrap();
+        let events = session.events();
+        assert_eq!(events[0].0, "initialized");
+        assert_eq!(events[1], ("stopped", stopped("entry")));
+        session
+    }
+
+    fn stopped(reason: &str) -> Value {
+        json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true })
+    }
+
+    /// run until the session stops and return the reason
+    fn wait(session: &mut Session) -> Value {
+        for _ in 0..100 {
+            session.run(8);
+            if !session.running() {
+                let (event, body) = session.events().pop().unwrap();
+                assert_eq!(event, "stopped");
+                return body;
+            }
+        }
+        panic!("the session never stopped");
+    }
+
+    fn pc(session: &Session) -> usize {
+        session.chip().unwrap().pc()
+    }
+
+    #[test]
+    fn not_launched() {
+        let mut session = Session::default();
+        let error = request(&mut session, "continue", json!({})).unwrap_err();
+        assert_eq!(error, "No game launched");
+        let error = request(&mut session, "restart", json!({})).unwrap_err();
+        assert_eq!(error, "Unsupported request `restart`");
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut session = launch("breakpoints");
+        let arguments = json!({ "breakpoints": [
+            { "instructionReference": "0x200", "offset": 8 },
+            { "instructionReference": "nowhere" },
+        ]});
+        let response = request(&mut session, "setInstructionBreakpoints", arguments).unwrap();
+        let breakpoints = &response["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[0]["instructionReference"], "0x208");
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        request(&mut session, "continue", json!({})).unwrap();
+        assert_eq!(wait(&mut session), stopped("breakpoint"));
+        assert_eq!(pc(&session), 0x208);
+
+        // resuming doesn't stop on the same breakpoint, the loop never
+        // comes back to it
+        request(&mut session, "continue", json!({})).unwrap();
+        session.run(64);
+        assert!(session.running());
+        request(&mut session, "pause", json!({})).unwrap();
+        assert_eq!(session.events(), [("stopped", stopped("pause"))]);
+        assert_eq!(pc(&session), 0x206);
+    }
+
+    #[test]
+    fn steps() {
+        let mut session = launch("steps");
+        request(&mut session, "stepIn", json!({})).unwrap();
+        assert_eq!(wait(&mut session), stopped("step"));
+        assert_eq!(pc(&session), 0x202);
+
+        // over the call
+        request(&mut session, "next", json!({})).unwrap();
+        assert_eq!(wait(&mut session), stopped("step"));
+        assert_eq!(pc(&session), 0x204);
+        assert_eq!(session.chip().unwrap().registers()[1], 2);
+
+        // into the call and back out of it
+        let mut session = launch("step-out");
+        for _ in 0..2 {
+            request(&mut session, "stepIn", json!({})).unwrap();
+            wait(&mut session);
+        }
+        assert_eq!(pc(&session), 0x208);
+        request(&mut session, "stepOut", json!({})).unwrap();
+        assert_eq!(wait(&mut session), stopped("step"));
+        assert_eq!(pc(&session), 0x204);
+    }
+
+    #[test]
+    fn variables() {
+        let mut session = launch("variables");
+        for _ in 0..2 {
+            request(&mut session, "stepIn", json!({})).unwrap();
+            wait(&mut session);
+        }
+
+        let variables = |session: &mut Session, reference| {
+            let arguments = json!({ "variablesReference": reference });
+            let response = request(session, "variables", arguments).unwrap();
+            response["variables"].as_array().unwrap().clone()
+        };
+        let registers = variables(&mut session, REGISTERS);
+        assert_eq!(registers.len(), 18);
+        assert_eq!(registers[0], variable("V0", String::from("0x01")));
+        assert_eq!(registers[17]["name"], "PC");
+        assert_eq!(registers[17]["value"], "0x208");
+        let stack = variables(&mut session, STACK);
+        assert_eq!(stack, [variable("0", reference(0x202))]);
+        assert_eq!(variables(&mut session, KEYS).len(), 16);
+
+        let arguments = json!({ "variablesReference": REGISTERS, "name": "vf", "value": "0x42" });
+        request(&mut session, "setVariable", arguments).unwrap();
+        assert_eq!(variables(&mut session, REGISTERS)[15]["value"], "0x42");
+        let arguments = json!({ "variablesReference": REGISTERS, "name": "V0", "value": "256" });
+        let error = request(&mut session, "setVariable", arguments).unwrap_err();
+        assert_eq!(error, "256 doesn't fit in a byte");
+        let arguments = json!({ "variablesReference": STACK, "name": "0", "value": "0" });
+        assert!(request(&mut session, "setVariable", arguments).is_err());
+    }
+
+    #[test]
+    fn fault() {
+        let mut session = launch("fault");
+        let arguments = json!({ "variablesReference": REGISTERS, "name": "PC", "value": "0xFFE" });
+        request(&mut session, "setVariable", arguments).unwrap();
+        // the cpu stops before the instruction out of the memory, every time
+        for _ in 0..2 {
+            request(&mut session, "continue", json!({})).unwrap();
+            let stopped = wait(&mut session);
+            assert_eq!(stopped["reason"], "exception");
+            assert_eq!(stopped["text"], "Access outside of the memory at 1000");
+            assert_eq!(pc(&session), 0x1000);
+        }
+    }
+}
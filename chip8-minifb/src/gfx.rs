@@ -1,5 +1,13 @@
+use chip8_cpu::control::Control;
+use chip8_cpu::filter::{Persistence, Phosphor};
+use chip8_cpu::framebuffer::Framebuffer;
+use chip8_cpu::palette::{Color, Themes};
 use minifb::{Key, KeyRepeat};
-use std::time::{Duration, Instant};
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// how often the phosphor fades when the game doesn't draw
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
 
 pub struct Gfx {
     window: minifb::Window,
@@ -7,17 +15,31 @@ pub struct Gfx {
     width: usize,
     height: usize,
     buffer: Vec<u32>,
+    screen: Framebuffer,      // the last screen drawn by the game
+    colors: Option<Vec<u32>>, // the last screen when the machine has its own colors
+    themes: Themes,
+    phosphor: Phosphor,
+    status: Option<&'static str>, // state of the emulation displayed in the title
 }
 
 impl Gfx {
-    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        themes: Themes,
+        persistence: Persistence,
+    ) -> Result<Self, String> {
         let window = minifb::Window::new(
             "chip-8 Emulator",
             width,
             height,
             minifb::WindowOptions {
                 resize: true, // TODO allow resize
-                scale: minifb::Scale::X8,
+                // the big screens would not fit
+                scale: match width {
+                    w if w > 128 => minifb::Scale::X4,
+                    _ => minifb::Scale::X8,
+                },
                 ..minifb::WindowOptions::default()
             },
         );
@@ -33,17 +55,58 @@ impl Gfx {
             width,
             height,
             buffer: vec![0; width * height],
+            screen: Framebuffer::new(width, height, 1),
+            colors: None,
+            themes,
+            phosphor: Phosphor::new(persistence, width, height),
+            status: None,
         };
 
-        return Ok(gfx);
+        Ok(gfx)
+    }
+
+    /// Draw the new screen of the game, if there's one. The phosphor gets
+    /// the last screen every frame even when the game didn't draw, so the
+    /// pixels keep fading. The window stays the same, the buffer follows the
+    /// size of the screen.
+    pub fn update(&mut self, screen: Option<&Framebuffer>) {
+        let drawn = screen.is_some();
+        if let Some(screen) = screen {
+            self.screen.clone_from(screen);
+        }
+        if !drawn && self.current_time.elapsed() < FRAME {
+            return;
+        }
+        let lines = self.phosphor.apply(&self.screen).changed_lines();
+        if (self.screen.width(), self.screen.height()) != (self.width, self.height) {
+            self.width = self.screen.width();
+            self.height = self.screen.height();
+            self.buffer = vec![0; self.width * self.height];
+        }
+        match drawn || !lines.is_empty() {
+            true => self.render(&lines),
+            false => self.current_time = Instant::now(),
+        }
+    }
+
+    /// the colors of the next screens, `None` to use the palette
+    pub fn set_colors(&mut self, colors: Option<&[u32]>) {
+        self.colors = colors.map(|colors| colors.to_vec());
     }
 
-    pub fn update(&mut self, arr: &[bool]) {
-        for i in 0..arr.len() {
-            // TODO UNSAFE
-            self.buffer[i] = match arr[i] {
-                true => 0xFFe6FFFF,
-                false => 0xFF32321e,
+    /// Draw the last screen with its colors or the current palette.
+    /// Only the `lines` which changed are converted, the window still
+    /// receives the whole buffer.
+    fn render(&mut self, lines: &[Range<usize>]) {
+        match &self.colors {
+            Some(colors) => self.buffer.copy_from_slice(colors),
+            None => {
+                let palette = self.themes.current();
+                for lines in lines {
+                    let shade = |intensity| palette.shade(intensity);
+                    let intensity = self.phosphor.intensity();
+                    intensity.lines_to_argb(lines.clone(), shade, &mut self.buffer);
+                }
             }
         }
 
@@ -54,6 +117,26 @@ impl Gfx {
         self.current_time = Instant::now();
     }
 
+    /// save the current screen in a png file
+    fn screenshot(&self) {
+        let palette = self.themes.current();
+        let pixels = match &self.colors {
+            Some(colors) => colors.iter().map(|c| Color::from_argb(*c)).collect(),
+            None => self
+                .phosphor
+                .intensity()
+                .to_colors(|intensity| palette.shade(intensity)),
+        };
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let file = format!("chip8-{}.png", time.as_secs());
+
+        let (width, height) = (self.width, self.height);
+        match chip8_cpu::screenshot::save_png(&file, width, height, &pixels, 10) {
+            Ok(()) => println!("Screenshot saved in {}", file),
+            Err(e) => println!("Can't save screenshot : {}", e),
+        }
+    }
+
     /// do some sound
     pub fn sound(&self) {
         // TODO better sound
@@ -98,12 +181,45 @@ impl Gfx {
     }
 
     /// update all related gfx event (window is closed, resized, whatevered)
+    /// and the controls of the emulation (pause, reset, speed)
     /// if the program need to exit then this function return `true`
-    pub fn handle_event(&self) -> bool {
-        if !self.window.is_open() {
-            return true;
-        } else if self.window.is_key_down(Key::Escape) {
+    pub fn handle_event(&mut self, control: &mut Control) -> bool {
+        if !self.window.is_open() || self.window.is_key_down(Key::Escape) {
             return true;
+        } else if self.window.is_key_pressed(Key::F2, KeyRepeat::No) {
+            self.themes.cycle();
+            let screen = self.phosphor.intensity().bounds();
+            self.render(&[screen.lines()]);
+        } else if self.window.is_key_pressed(Key::F12, KeyRepeat::No) {
+            self.screenshot();
+        }
+
+        if self.window.is_key_pressed(Key::F1, KeyRepeat::No) {
+            control.toggle_pause();
+        }
+        if self.window.is_key_pressed(Key::F5, KeyRepeat::No) {
+            control.reset();
+        }
+        if self.window.is_key_pressed(Key::F6, KeyRepeat::Yes) {
+            control.frame_advance();
+        }
+        if self.window.is_key_pressed(Key::F7, KeyRepeat::No) {
+            control.toggle_slow_motion();
+        }
+        control.set_turbo(self.window.is_key_down(Key::Tab));
+
+        if self.status != control.status() {
+            self.status = control.status();
+            match self.status {
+                Some(status) => self
+                    .window
+                    .set_title(&format!("chip-8 Emulator - {}", status)),
+                None => self.window.set_title("chip-8 Emulator"),
+            }
+        }
+        // the window only read its events when it's updated
+        if control.paused() {
+            self.window.update();
         }
         false
     }
@@ -1,45 +1,85 @@
+use chip8_cpu::control::Control;
+use chip8_cpu::filter::Persistence;
+use chip8_cpu::gdb::Gdb;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::options::Options;
 use std::env;
 use std::time::{Duration, Instant};
 
 mod gfx;
 use crate::gfx::Gfx;
 
+fn usage() -> String {
+    format!(
+        "Usage: chip8-minifb [--palette <name|colors>] [--palette-file <file>]
+    [--phosphor <off|frames:N|decay:RATIO>]
+    [--trace <file>] [--trace-format <text|binary>] [--trace-filter <filter>]
+    [--seed <n>] [--inputs <file>]
+    [--machine <{}>]
+    [--timing <instruction|vip>]
+    [--gdb <port>] [--symbols <file>] [--break <label|address|file:line>]...
+    [--vip <monitor rom> <interpreter>]
+    <game>
+
+Press escape to quit, F1 to pause, F2 to change the palette, F5 to reset the game,
+F6 to advance one frame, F7 for slow motion, hold tab for turbo and F12 for a screenshot.",
+        MachineConfig::PRESETS.join("|")
+    )
+}
+
 fn main() {
-    let filename = match env::args().nth(1) {
-        Some(f) => f,
-        None => {
-            println!("Need a game as argument");
-            return;
-        }
+    let options = match Options::parse(env::args().skip(1), &["--phosphor"]) {
+        Ok(options) => options,
+        Err(e) => return println!("{}\n\n{}", e, usage()),
+    };
+    let persistence = match options.extra("--phosphor").map(str::parse) {
+        Some(Ok(p)) => p,
+        Some(Err(e)) => return println!("{}", e),
+        None => Persistence::Off,
+    };
+    let (mut chip, breakpoints) = match options.machine() {
+        Ok(machine) => machine,
+        Err(e) => return println!("{}", e),
     };
 
-    let mut gfx = Gfx::new(64, 32).unwrap();
-    let mut chip = chip8_cpu::new();
+    let mut gfx = Gfx::new(chip.width(), chip.height(), options.themes, persistence).unwrap();
 
-    if let Err(e) = chip.load_game(&filename) {
-        println!("Can't load game : {}", e);
-        return;
+    let mut control = Control::default();
+    control.set_breakpoints(breakpoints);
+    if let Some(port) = options.gdb {
+        println!("Waiting for gdb on 127.0.0.1:{}", port);
+        match Gdb::listen(port) {
+            Ok(gdb) => control.set_gdb(gdb),
+            Err(e) => return println!("Can't wait for gdb : {}", e),
+        }
     }
 
     let mut last_instruction_run_time = Instant::now();
     let mut updated = 0;
     loop {
-        if gfx.handle_event() {
+        if gfx.handle_event(&mut control) {
             break;
         }
-        gfx.update_key(&mut chip.key);
+        gfx.update_key(chip.keys());
 
-        if last_instruction_run_time.elapsed() > Duration::from_millis(5) {
+        if last_instruction_run_time.elapsed() > control.throttle(Duration::from_millis(5)) {
             last_instruction_run_time = Instant::now();
-            chip.cycle();
+            control.run(chip.as_mut());
+            for message in control.take_messages() {
+                println!("{}", message);
+            }
 
-            if let Some(screen) = chip.update() {
+            if chip.drawing() {
+                gfx.set_colors(chip.argb());
+            }
+            let screen = chip.update();
+            if screen.is_some() {
                 updated += 1;
-                gfx.update(screen);
             }
+            gfx.update(screen);
             if updated > 3 {
                 updated = 0; // reset the keys every 3 frames
-                Gfx::clear_key(&mut chip.key);
+                Gfx::clear_key(chip.keys());
             }
             if chip.sound() {
                 gfx.sound();
@@ -0,0 +1,356 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// A key of the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Escape,
+    F(u8),
+}
+
+/// What happened to a key.
+/// The terminals which don't support the kitty keyboard protocol never send
+/// `Release`, when a key is held they repeat `Press` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Press(Key),
+    Release(Key),
+    /// the terminal answered to our query, it support the kitty protocol
+    KittySupported,
+}
+
+/// Decode the characters read from the terminal into key events.
+/// We don't let ncurses decode the escape sequences because it doesn't know
+/// the kitty keyboard protocol.
+#[derive(Default)]
+pub struct Parser {
+    sequence: String, // escape sequence being read
+}
+
+impl Parser {
+    /// feed a character to the parser, return an event if it was the end of
+    /// a key
+    pub fn push(&mut self, c: char) -> Option<Event> {
+        if self.sequence.is_empty() {
+            return match c {
+                '\x1b' => {
+                    self.sequence.push(c);
+                    None
+                }
+                c => Some(Event::Press(Key::Char(c))),
+            };
+        }
+
+        self.sequence.push(c);
+        let event = match self.sequence.as_bytes() {
+            [0x1b, b'[' | b'O'] => return None,
+            // F1 to F4 of the terminals in application mode
+            [0x1b, b'O', f @ b'P'..=b'S'] => Some(Event::Press(Key::F(f - b'O'))),
+            // the parameters of a control sequence
+            [0x1b, b'[', .., 0x30..=0x3F] => return None,
+            [0x1b, b'[', ..] => csi(&self.sequence[2..]),
+            // alt + key or a lone escape followed by a key, keep the key
+            _ => Some(Event::Press(Key::Char(c))),
+        };
+        self.sequence.clear();
+        event
+    }
+
+    /// there is nothing more to read, an escape alone is the escape key
+    pub fn flush(&mut self) -> Option<Event> {
+        let escape = self.sequence == "\x1b";
+        self.sequence.clear();
+        match escape {
+            true => Some(Event::Press(Key::Escape)),
+            false => None,
+        }
+    }
+}
+
+/// Decode a control sequence without its `ESC [` prefix, its format is
+/// `key[:alternates];modifiers[:event]final`
+fn csi(sequence: &str) -> Option<Event> {
+    if !sequence.ends_with(|c: char| c.is_ascii()) {
+        return None;
+    }
+    let (params, last) = sequence.split_at(sequence.len() - 1);
+    if params.starts_with('?') {
+        return match last {
+            "u" => Some(Event::KittySupported),
+            _ => None,
+        };
+    }
+
+    let mut params = params.split(';');
+    let code: u32 = params.next()?.split(':').next()?.parse().unwrap_or(1);
+    let mut modifiers = params.next().unwrap_or("1").split(':');
+    let state: u32 = modifiers.next()?.parse().unwrap_or(1);
+    // the event type is 1 for a press, 2 for a repeat and 3 for a release
+    let release = modifiers.next() == Some("3");
+    let ctrl = (state.saturating_sub(1) & 0x4) != 0;
+
+    let key = match (last, code) {
+        ("u", 27) => Key::Escape,
+        // control + letter is sent as the ascii control character
+        ("u", c @ 0x61..=0x7A) if ctrl => Key::Char((c as u8 & 0x1F) as char),
+        ("u", c) => Key::Char(std::char::from_u32(c)?),
+        ("P", _) => Key::F(1),
+        ("Q", _) => Key::F(2),
+        ("R", _) => Key::F(3),
+        ("S", _) => Key::F(4),
+        ("~", 15) => Key::F(5),
+        ("~", c @ 17..=21) => Key::F(c as u8 - 11),
+        ("~", c @ 23..=24) => Key::F(c as u8 - 12),
+        _ => return None,
+    };
+    match release {
+        true => Some(Event::Release(key)),
+        false => Some(Event::Press(key)),
+    }
+}
+
+/// Action of the emulator triggered by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Pause,
+    Reset,
+    NextPalette,
+    FrameAdvance,
+    SlowMotion,
+}
+
+/// Keep track of the chip8 keys held by the user
+pub struct Keyboard {
+    parser: Parser,
+    held: [Option<Instant>; 16], // the last time we saw the key pressed
+    turbo: Option<Instant>,      // same for the turbo key
+    hold: Duration,              // how long a key stay pressed without release event
+    release_events: bool,        // the terminal tell us when a key is released
+}
+
+impl Keyboard {
+    /// Ask the terminal to send us the press, repeat and release events with
+    /// the kitty keyboard protocol. The terminals which don't know the
+    /// protocol ignore the request and we fallback on the `hold` timeout.
+    pub fn new(hold: Duration) -> Self {
+        // query the support of the protocol then push our flags:
+        // disambiguate (1), report event types (2) and all keys as escape (8)
+        print!("\x1b[?u\x1b[>11u");
+        std::io::stdout().flush().unwrap();
+        Keyboard {
+            parser: Parser::default(),
+            held: [None; 16],
+            turbo: None,
+            hold,
+            release_events: false,
+        }
+    }
+
+    /// read all the pending input of the window, update the chip8 keys and
+    /// return the actions asked by the user
+    pub fn update(&mut self, window: &pancurses::Window, key: &mut [bool]) -> Vec<Action> {
+        let mut events = Vec::new();
+        while let Some(input) = window.getch() {
+            if let pancurses::Input::Character(c) = input {
+                events.extend(self.parser.push(c));
+            }
+        }
+        events.extend(self.parser.flush());
+        self.handle(events, Instant::now(), key)
+    }
+
+    /// update the chip8 keys with the events read at `now`
+    fn handle(&mut self, events: Vec<Event>, now: Instant, key: &mut [bool]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for event in events {
+            match event {
+                Event::KittySupported => self.release_events = true,
+                Event::Press(Key::Escape) | Event::Press(Key::Char('\x03')) => {
+                    actions.push(Action::Quit)
+                }
+                Event::Press(Key::F(1)) => actions.push(Action::Pause),
+                Event::Press(Key::F(2)) => actions.push(Action::NextPalette),
+                Event::Press(Key::F(5)) => actions.push(Action::Reset),
+                Event::Press(Key::F(6)) => actions.push(Action::FrameAdvance),
+                Event::Press(Key::F(7)) => actions.push(Action::SlowMotion),
+                Event::Press(Key::Char('\t')) => self.turbo = Some(now),
+                Event::Release(Key::Char('\t')) => {
+                    self.release_events = true;
+                    self.turbo = None;
+                }
+                Event::Press(Key::Char(c)) => {
+                    if let Some(k) = chip8_key(c) {
+                        self.held[k] = Some(now);
+                    }
+                }
+                Event::Release(Key::Char(c)) => {
+                    // a release can only come from the kitty protocol
+                    self.release_events = true;
+                    if let Some(k) = chip8_key(c) {
+                        self.held[k] = None;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let (hold, timeout) = (self.hold, !self.release_events);
+        for (k, held) in key.iter_mut().zip(self.held.iter_mut()) {
+            if timeout && held.is_some_and(|t| now - t > hold) {
+                *held = None;
+            }
+            *k = held.is_some();
+        }
+        if timeout && self.turbo.is_some_and(|t| now - t > hold) {
+            self.turbo = None;
+        }
+        actions
+    }
+
+    /// the turbo key is held
+    pub fn turbo(&self) -> bool {
+        self.turbo.is_some()
+    }
+}
+
+impl Drop for Keyboard {
+    /// give back its previous keyboard mode to the terminal
+    fn drop(&mut self) {
+        print!("\x1b[<u");
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+/// the key of the chip8 associated to a character of the terminal
+fn chip8_key(c: char) -> Option<usize> {
+    let key = match c {
+        '&' => 0,
+        'é' => 1,
+        '"' => 2,
+        '\'' => 3,
+        'a' => 4,
+        'z' => 5,
+        'e' => 6,
+        'r' => 7,
+        'q' => 8,
+        's' => 9,
+        'd' => 10,
+        'f' => 11,
+        'w' => 12,
+        'x' => 13,
+        'c' => 14,
+        'v' => 15,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Vec<Event> {
+        let mut parser = Parser::default();
+        let mut events = input
+            .chars()
+            .filter_map(|c| parser.push(c))
+            .collect::<Vec<_>>();
+        events.extend(parser.flush());
+        events
+    }
+
+    #[test]
+    fn legacy() {
+        use Event::*;
+        assert_eq!(parse("a&"), [Press(Key::Char('a')), Press(Key::Char('&'))]);
+        assert_eq!(parse("\x1b"), [Press(Key::Escape)]);
+        assert_eq!(
+            parse("\x1bOP\x1b[15~"),
+            [Press(Key::F(1)), Press(Key::F(5))]
+        );
+        // alt + z
+        assert_eq!(parse("\x1bz"), [Press(Key::Char('z'))]);
+    }
+
+    #[test]
+    fn kitty() {
+        use Event::*;
+        let a = Key::Char('a');
+        assert_eq!(parse("\x1b[?11u"), [KittySupported]);
+        assert_eq!(parse("\x1b[97u"), [Press(a)]);
+        assert_eq!(parse("\x1b[97;1:1u"), [Press(a)]);
+        // the repeats are presses, only the releases are different
+        assert_eq!(parse("\x1b[97;1:2u"), [Press(a)]);
+        assert_eq!(parse("\x1b[97;1:3u"), [Release(a)]);
+        assert_eq!(parse("\x1b[97:65;2:3u"), [Release(a)]);
+        assert_eq!(parse("\x1b[99;5u"), [Press(Key::Char('\x03'))]);
+        assert_eq!(parse("\x1b[27u"), [Press(Key::Escape)]);
+        assert_eq!(
+            parse("\x1b[1;1:3P\x1b[24~"),
+            [Release(Key::F(1)), Press(Key::F(12))]
+        );
+    }
+
+    fn keyboard() -> Keyboard {
+        Keyboard {
+            parser: Parser::default(),
+            held: [None; 16],
+            turbo: None,
+            hold: Duration::from_millis(500),
+            release_events: false,
+        }
+    }
+
+    /// the chip8 keys held after these events
+    fn held(keyboard: &mut Keyboard, input: &str, now: Instant) -> Vec<usize> {
+        let mut key = [false; 16];
+        keyboard.handle(parse(input), now, &mut key);
+        (0..16).filter(|k| key[*k]).collect()
+    }
+
+    #[test]
+    fn held_keys() {
+        let mut keyboard = keyboard();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(10);
+        assert_eq!(held(&mut keyboard, "\x1b[?11u", now), []);
+        // a, z and e pressed together then released one by one
+        let pressed = "\x1b[97u\x1b[122u\x1b[101u";
+        assert_eq!(held(&mut keyboard, pressed, now), [4, 5, 6]);
+        assert_eq!(held(&mut keyboard, "\x1b[122;1:3u", now), [4, 6]);
+        // the terminal sends the releases, the keys stay held without repeat
+        assert_eq!(held(&mut keyboard, "\x1b[97;1:2u", later), [4, 6]);
+        assert_eq!(held(&mut keyboard, "\x1b[97;1:3u\x1b[101;1:3u", later), []);
+        assert!(keyboard.release_events);
+    }
+
+    #[test]
+    fn hold_timeout() {
+        let mut keyboard = keyboard();
+        let now = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(held(&mut keyboard, "az\t", now), [4, 5]);
+        assert!(keyboard.turbo());
+        // the terminal repeats a while the key is held
+        assert_eq!(held(&mut keyboard, "a", now + ms(400)), [4, 5]);
+        assert_eq!(held(&mut keyboard, "", now + ms(600)), [4]);
+        assert!(!keyboard.turbo());
+        assert_eq!(held(&mut keyboard, "", now + ms(1000)), []);
+        assert!(!keyboard.release_events);
+    }
+
+    #[test]
+    fn actions() {
+        let mut keyboard = keyboard();
+        let mut key = [false; 16];
+        let events = parse("\x1bOP\x1b[15~\x1b[17~\x1b[18~\x1bOQ\x03");
+        let actions = keyboard.handle(events, Instant::now(), &mut key);
+        use Action::*;
+        assert_eq!(
+            actions,
+            [Pause, Reset, FrameAdvance, SlowMotion, NextPalette, Quit]
+        );
+    }
+}
@@ -1,34 +1,98 @@
+use chip8_cpu::control::Control;
+use chip8_cpu::gdb::Gdb;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::options::Options;
 use std::env;
+use std::thread::sleep;
+use std::time::Duration;
 
+mod input;
 mod ncurses;
+mod render;
+use crate::input::Action;
 use crate::ncurses::Gfx;
+use crate::render::Mode;
+
+fn usage() -> String {
+    format!(
+        "Usage: chip8-ncurses [--mode <ascii|half|braille>] [--palette <name|colors>]
+    [--palette-file <file>] [--hold <ms>]
+    [--trace <file>] [--trace-format <text|binary>] [--trace-filter <filter>]
+    [--seed <n>] [--inputs <file>]
+    [--machine <{}>]
+    [--timing <instruction|vip>]
+    [--gdb <port>] [--symbols <file>] [--break <label|address|file:line>]...
+    [--vip <monitor rom> <interpreter>]
+    <game>
+
+Press escape to quit, F1 to pause, F2 to change the palette, F5 to reset the game,
+F6 to advance one frame, F7 for slow motion and hold tab for turbo.",
+        MachineConfig::PRESETS.join("|")
+    )
+}
 
 fn main() {
-    let filename = match env::args().nth(1) {
-        Some(f) => f,
-        None => {
-            println!("Need a game as argument");
-            return;
-        }
+    let options = match Options::parse(env::args().skip(1), &["--mode", "--hold"]) {
+        Ok(options) => options,
+        Err(e) => return println!("{}\n\n{}", e, usage()),
     };
+    let mode = match options.extra("--mode").map(str::parse) {
+        Some(Ok(m)) => m,
+        Some(Err(e)) => return println!("{}", e),
+        None => Mode::HalfBlock,
+    };
+    // most terminals start repeating a held key after 250ms to 500ms
+    let hold = match options.extra("--hold").map(str::parse) {
+        Some(Ok(h)) => Duration::from_millis(h),
+        Some(Err(e)) => return println!("Invalid hold duration : {}", e),
+        None => Duration::from_millis(500),
+    };
+    let (mut chip, breakpoints) = match options.machine() {
+        Ok(machine) => machine,
+        Err(e) => return println!("{}", e),
+    };
+    let mut themes = options.themes;
 
-    let mut gfx = Gfx::new(64, 32).unwrap();
-    let mut chip = chip8_cpu::new();
-
-    if let Err(e) = chip.load_game(&filename) {
-        println!("Can't load game : {}", e);
-        return;
+    let mut control = Control::default();
+    control.set_breakpoints(breakpoints);
+    if let Some(port) = options.gdb {
+        println!("Waiting for gdb on 127.0.0.1:{}", port);
+        match Gdb::listen(port) {
+            Ok(gdb) => control.set_gdb(gdb),
+            Err(e) => return println!("Can't wait for gdb : {}", e),
+        }
     }
 
+    let mut gfx = Gfx::new(chip.width(), chip.height(), mode, themes.current(), hold).unwrap();
+
+    // printed once the terminal is given back, they would mess the screen
+    let mut messages = Vec::new();
     loop {
-        if gfx.handle_event() {
-            break;
+        for action in gfx.handle_event(chip.keys(), &mut control) {
+            match action {
+                Action::Quit => {
+                    drop(gfx);
+                    for message in messages {
+                        println!("{}", message);
+                    }
+                    return;
+                }
+                Action::Pause => control.toggle_pause(),
+                Action::NextPalette => gfx.set_palette(themes.cycle()),
+                Action::Reset => control.reset(),
+                Action::FrameAdvance => control.frame_advance(),
+                Action::SlowMotion => control.toggle_slow_motion(),
+            }
+        }
+        gfx.set_status(control.status());
+        if control.paused() {
+            sleep(Duration::from_millis(10));
         }
-        gfx.update_key(&mut chip.key);
-        chip.cycle();
+        control.run(chip.as_mut());
+        messages.extend(control.take_messages());
 
         if let Some(screen) = chip.update() {
-            gfx.update(screen);
+            gfx.update(screen, &control);
         }
         if chip.sound() {
             gfx.sound();
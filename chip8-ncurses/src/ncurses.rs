@@ -1,47 +1,119 @@
+use crate::input::{Action, Keyboard};
+use crate::render::Mode;
+use chip8_cpu::control::Control;
+use chip8_cpu::framebuffer::Framebuffer;
+use chip8_cpu::palette::{Color, Palette};
 use std::time::{Duration, Instant};
 
+/// the color pair used to draw the chip8 screen
+const SCREEN_PAIR: i16 = 1;
+
 pub struct Gfx {
     window: pancurses::Window,
     frequency: Duration,
     current_time: Instant,
     width: usize,
     height: usize,
+    mode: Mode,
+    cells: Vec<char>, // what is currently displayed in the terminal
+    keyboard: Keyboard,
+    status: Option<&'static str>, // state of the emulation displayed on the border
 }
 
 impl Gfx {
-    pub fn new(width: usize, height: usize) -> Result<Self, String> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        mode: Mode,
+        palette: &Palette,
+        hold: Duration,
+    ) -> Result<Self, String> {
         pancurses::noecho();
         pancurses::raw();
         let window = pancurses::initscr();
         window.nodelay(true);
+        pancurses::curs_set(0);
+        let (columns, lines) = mode.cells(width, height);
         let window = window
-            .subwin(height as i32 + 2, width as i32 + 2, 0, 0)
+            .subwin(lines as i32 + 2, columns as i32 + 2, 0, 0)
             .map_err(|e| e.to_string())?; // no idea of what it is
         window.nodelay(true);
-        Ok(Gfx {
+
+        let gfx = Gfx {
             window,
             // we want 30 frames per seconds
             frequency: Duration::from_secs(1).checked_div(30).unwrap(),
             current_time: Instant::now(),
             width,
             height,
-        })
+            mode,
+            cells: vec!['\0'; columns * lines],
+            keyboard: Keyboard::new(hold),
+            status: None,
+        };
+        gfx.set_palette(palette);
+        gfx.window.draw_box('|', '-');
+        Ok(gfx)
+    }
+
+    /// use the background and foreground of the palette to draw the screen.
+    /// If the terminal can't redefine its colors we take the closest of the
+    /// 8 basic colors.
+    pub fn set_palette(&self, palette: &Palette) {
+        if !pancurses::has_colors() {
+            return;
+        }
+        pancurses::start_color();
+
+        let (foreground, background) = (palette.foreground(), palette.background());
+        if pancurses::can_change_color() && pancurses::COLORS() > 17 {
+            let init_color = |number: i16, c: Color| {
+                let scale = |channel: u8| (channel as i32 * 1000 / 255) as i16;
+                pancurses::init_color(number, scale(c.r), scale(c.g), scale(c.b));
+            };
+            init_color(16, foreground);
+            init_color(17, background);
+            pancurses::init_pair(SCREEN_PAIR, 16, 17);
+        } else {
+            // the basic colors are ordered as a 3 bits BGR value
+            let basic = |c: Color| {
+                (c.r > 127) as i16 | ((c.g > 127) as i16) << 1 | ((c.b > 127) as i16) << 2
+            };
+            pancurses::init_pair(SCREEN_PAIR, basic(foreground), basic(background));
+        }
+        self.window.color_set(SCREEN_PAIR);
+        self.window
+            .bkgd(pancurses::COLOR_PAIR(SCREEN_PAIR as pancurses::chtype));
     }
 
-    pub fn update(&mut self, arr: &[bool]) {
-        match self.frequency.checked_sub(self.current_time.elapsed()) {
+    /// redraw only the cells of the regions which changed since the last update
+    pub fn update(&mut self, screen: &Framebuffer, control: &Control) {
+        let frequency = control.throttle(self.frequency);
+        match frequency.checked_sub(self.current_time.elapsed()) {
             None => 42, // we are slow
             Some(t) => pancurses::napms(t.as_millis() as i32),
         };
 
-        self.window.clear();
-        self.window.draw_box('|', '-');
-        for y in 0..self.height {
-            for x in 0..self.width {
-                match arr[x + y * self.width] {
-                    true => self.window.mvaddch(y as i32 + 1, x as i32 + 1, 'X'),
-                    false => self.window.mvaddch(y as i32 + 1, x as i32 + 1, ' '),
-                };
+        if (screen.width(), screen.height()) != (self.width, self.height) {
+            self.resize(screen.width(), screen.height());
+        }
+        let (columns, lines) = self.mode.cells(self.width, self.height);
+        let (w, h) = self.mode.cell_size();
+        for region in screen.regions() {
+            // the cells containing a pixel of the region
+            let (left, right) = (region.x / w, (region.x + region.width).div_ceil(w));
+            let (top, bottom) = (region.y / h, (region.y + region.height).div_ceil(h));
+            for y in top..bottom.min(lines) {
+                for x in left..right.min(columns) {
+                    let glyph = self.mode.glyph(screen, x, y);
+                    let cell = &mut self.cells[x + y * columns];
+                    if *cell != glyph {
+                        *cell = glyph;
+                        let mut buf = [0; 4];
+                        let glyph = glyph.encode_utf8(&mut buf);
+                        self.window.mvaddstr(y as i32 + 1, x as i32 + 1, glyph);
+                    }
+                }
             }
         }
         self.window.refresh();
@@ -54,36 +126,47 @@ impl Gfx {
         pancurses::beep();
     }
 
-    /// update the array with the key currently pressed
-    pub fn update_key(&mut self, key: &mut [bool]) {
-        for k in key.iter_mut() {
-            *k = false;
+    /// update the chip8 keys with the keys held in the terminal and return
+    /// the actions asked by the user (quit, pause, whatevered)
+    pub fn handle_event(&mut self, key: &mut [bool], control: &mut Control) -> Vec<Action> {
+        let actions = self.keyboard.update(&self.window, key);
+        control.set_turbo(self.keyboard.turbo());
+        actions
+    }
+
+    /// write the state of the emulation on the top border
+    pub fn set_status(&mut self, status: Option<&'static str>) {
+        if self.status == status {
+            return;
         }
+        self.status = status;
+        self.draw_border();
+    }
 
-        match self.window.getch() {
-            Some(pancurses::Input::Character('&')) => key[0] = true,
-            Some(pancurses::Input::Character('é')) => key[1] = true,
-            Some(pancurses::Input::Character('"')) => key[2] = true,
-            Some(pancurses::Input::Character('\'')) => key[3] = true,
-            Some(pancurses::Input::Character('a')) => key[4] = true,
-            Some(pancurses::Input::Character('z')) => key[5] = true,
-            Some(pancurses::Input::Character('e')) => key[6] = true,
-            Some(pancurses::Input::Character('r')) => key[7] = true,
-            Some(pancurses::Input::Character('q')) => key[8] = true,
-            Some(pancurses::Input::Character('s')) => key[9] = true,
-            Some(pancurses::Input::Character('d')) => key[10] = true,
-            Some(pancurses::Input::Character('f')) => key[11] = true,
-            Some(pancurses::Input::Character('w')) => key[12] = true,
-            Some(pancurses::Input::Character('x')) => key[13] = true,
-            Some(pancurses::Input::Character('c')) => key[14] = true,
-            Some(pancurses::Input::Character('v')) => key[15] = true,
-            _ => (),
+    fn draw_border(&self) {
+        self.window.draw_box('|', '-');
+        if let Some(status) = self.status {
+            self.window.mvaddstr(0, 2, format!(" {} ", status));
         }
+        self.window.refresh();
     }
 
-    /// update all related gfx event (window is closed, resized, whatevered)
-    /// if the program need to exit then this function return `true`
-    pub fn handle_event(&self) -> bool {
-        false
+    /// the box around the screen follows its size, every cell is drawn again
+    fn resize(&mut self, width: usize, height: usize) {
+        self.window.erase();
+        self.window.refresh();
+        let (columns, lines) = self.mode.cells(width, height);
+        self.window.resize(lines as i32 + 2, columns as i32 + 2);
+        self.width = width;
+        self.height = height;
+        self.cells = vec!['\0'; columns * lines];
+        self.draw_border();
+    }
+}
+
+impl Drop for Gfx {
+    /// give back the terminal in the state we found it
+    fn drop(&mut self) {
+        pancurses::endwin();
     }
 }
@@ -0,0 +1,155 @@
+use chip8_cpu::framebuffer::Framebuffer;
+use std::str::FromStr;
+
+/// How the pixels of the chip8 are packed in the cells of the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// one cell per pixel, a lit pixel is a `X`
+    Ascii,
+    /// one cell for two pixels stacked vertically with `▀`, `▄` and `█`
+    HalfBlock,
+    /// one cell for a block of 2x4 pixels with the braille patterns
+    Braille,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Mode::Ascii),
+            "half" => Ok(Mode::HalfBlock),
+            "braille" => Ok(Mode::Braille),
+            _ => Err(format!(
+                "Unknown mode `{}`, expected ascii, half or braille",
+                s
+            )),
+        }
+    }
+}
+
+impl Mode {
+    /// number of pixels (horizontally, vertically) in one cell
+    pub fn cell_size(self) -> (usize, usize) {
+        match self {
+            Mode::Ascii => (1, 1),
+            Mode::HalfBlock => (1, 2),
+            Mode::Braille => (2, 4),
+        }
+    }
+
+    /// number of cells needed to display a screen of this size
+    pub fn cells(self, width: usize, height: usize) -> (usize, usize) {
+        let (w, h) = self.cell_size();
+        (width.div_ceil(w), height.div_ceil(h))
+    }
+
+    /// return the character representing the cell at `(x, y)`
+    pub fn glyph(self, screen: &Framebuffer, x: usize, y: usize) -> char {
+        let (w, h) = self.cell_size();
+        // the pixels outside of the screen are switched off
+        let pixel = |dx: usize, dy: usize| {
+            let (px, py) = (x * w + dx, y * h + dy);
+            px < screen.width() && py < screen.height() && screen.lit(px, py)
+        };
+
+        match self {
+            Mode::Ascii => match pixel(0, 0) {
+                true => 'X',
+                false => ' ',
+            },
+            Mode::HalfBlock => match (pixel(0, 0), pixel(0, 1)) {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            },
+            Mode::Braille => {
+                // the braille dots are numbered by column and not by line
+                // and the last line was added after the 6 first dots
+                const DOTS: [[u32; 2]; 4] =
+                    [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+                let mut code = 0x2800;
+                for (dy, line) in DOTS.iter().enumerate() {
+                    for (dx, dot) in line.iter().enumerate() {
+                        if pixel(dx, dy) {
+                            code |= dot;
+                        }
+                    }
+                }
+                std::char::from_u32(code).unwrap()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a screen with the pixels lit at these coordinates
+    fn screen(width: usize, height: usize, lit: &[(usize, usize)]) -> Framebuffer {
+        let mut screen = Framebuffer::new(width, height, 1);
+        lit.iter().for_each(|&(x, y)| screen.set(x, y, 1));
+        screen
+    }
+
+    #[test]
+    fn ascii() {
+        let screen = screen(2, 1, &[(1, 0)]);
+        assert_eq!(Mode::Ascii.cells(2, 1), (2, 1));
+        assert_eq!(Mode::Ascii.glyph(&screen, 0, 0), ' ');
+        assert_eq!(Mode::Ascii.glyph(&screen, 1, 0), 'X');
+    }
+
+    #[test]
+    fn half_block() {
+        let screen = screen(4, 2, &[(1, 0), (2, 1), (3, 0), (3, 1)]);
+        let glyphs = (0..4)
+            .map(|x| Mode::HalfBlock.glyph(&screen, x, 0))
+            .collect::<String>();
+        assert_eq!(glyphs, " ▀▄█");
+    }
+
+    #[test]
+    fn braille() {
+        // the dots 1 to 8 of the braille patterns
+        let dots = [
+            (0, 0),
+            (0, 1),
+            (0, 2),
+            (1, 0),
+            (1, 1),
+            (1, 2),
+            (0, 3),
+            (1, 3),
+        ];
+        for pattern in 0..=0xFFu32 {
+            let lit = (0..8)
+                .filter(|bit| pattern & 1 << bit != 0)
+                .map(|bit| dots[bit])
+                .collect::<Vec<_>>();
+            let screen = screen(2, 4, &lit);
+            let glyph = std::char::from_u32(0x2800 + pattern).unwrap();
+            assert_eq!(Mode::Braille.glyph(&screen, 0, 0), glyph, "{:08b}", pattern);
+        }
+    }
+
+    #[test]
+    fn edges() {
+        // the last cells are only partly on the screen, the rest is off
+        let some = screen(5, 3, &[(4, 0), (4, 2), (2, 2)]);
+        assert_eq!(Mode::HalfBlock.cells(5, 3), (5, 2));
+        assert_eq!(Mode::HalfBlock.glyph(&some, 4, 1), '▀');
+        assert_eq!(Mode::HalfBlock.glyph(&some, 2, 1), '▀');
+        assert_eq!(Mode::Braille.cells(5, 3), (3, 1));
+        assert_eq!(Mode::Braille.glyph(&some, 2, 0), '⠅');
+        assert_eq!(Mode::Braille.glyph(&some, 1, 0), '⠄');
+
+        let all = (0..5).flat_map(|x| (0..3).map(move |y| (x, y)));
+        let full = screen(5, 3, &all.collect::<Vec<_>>());
+        assert_eq!(Mode::HalfBlock.glyph(&full, 4, 1), '▀');
+        assert_eq!(Mode::Braille.glyph(&full, 2, 0), '⠇');
+        assert_eq!(Mode::Braille.glyph(&full, 0, 0), '⠿');
+    }
+}
@@ -0,0 +1,190 @@
+use crate::font;
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::disasm;
+use chip8_cpu::palette::Palette;
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video;
+
+/// width of the debugger panel in pixels of the window
+pub const PANEL_WIDTH: u32 = 256;
+/// every pixel of the font is a square of this size
+const SCALE: u32 = 2;
+/// size of a character with the space around it, in pixels of the font
+const CELL_WIDTH: i32 = font::WIDTH + 1;
+const CELL_HEIGHT: i32 = font::HEIGHT + 1;
+/// number of instructions displayed before and after the PC
+const DISASM_AROUND: usize = 5;
+/// the memory view display 8 lines of 8 bytes
+const MEMORY_LINES: usize = 8;
+const MEMORY_COLUMNS: usize = 8;
+
+/// Panel displayed at the right of the game with the state of the cpu:
+/// the registers, the stack, the timers, the keypad, the instructions
+/// around the PC and the memory around I.
+pub struct Debugger<'a> {
+    canvas: &'a mut Canvas<video::Window>,
+    palette: &'a Palette,
+    x: i32, // where the panel start, in pixels of the font
+}
+
+fn sdl_color(color: chip8_cpu::palette::Color) -> Color {
+    Color::RGB(color.r, color.g, color.b)
+}
+
+/// the instruction stored at this address, nothing after the end of the memory
+fn opcode(chip: &Cpu, address: usize) -> u16 {
+    let memory = chip.memory();
+    let byte = |i: usize| memory.get(i).copied().unwrap_or(0) as u16;
+    (byte(address) << 8) | byte(address + 1)
+}
+
+impl<'a> Debugger<'a> {
+    /// the panel is drawn on the right of a game area of `game_width` pixels
+    pub fn new(
+        canvas: &'a mut Canvas<video::Window>,
+        palette: &'a Palette,
+        game_width: u32,
+    ) -> Self {
+        Debugger {
+            canvas,
+            palette,
+            x: (game_width / SCALE) as i32,
+        }
+    }
+
+    pub fn render(&mut self, chip: &Cpu) {
+        self.canvas.set_scale(SCALE as f32, SCALE as f32).unwrap();
+        // the panel is slightly lighter than the game
+        let (background, foreground) = (self.palette.background(), self.palette.foreground());
+        self.canvas
+            .set_draw_color(sdl_color(background.blend(foreground, 32)));
+        let (_, height) = self.canvas.output_size().unwrap();
+        let panel = Rect::new(self.x, 0, PANEL_WIDTH / SCALE, height / SCALE);
+        self.canvas.fill_rect(panel).unwrap();
+
+        let mut line = 0;
+        self.registers(chip, &mut line);
+        line += 1;
+        self.stack(chip, &mut line);
+        line += 1;
+        self.keypad(chip, &mut line);
+        line += 1;
+        self.disassembly(chip, &mut line);
+        line += 1;
+        self.memory(chip, &mut line);
+    }
+
+    fn registers(&mut self, chip: &Cpu, line: &mut i32) {
+        let text = format!(
+            "PC {:04X}  I {:04X}  SP {:X}",
+            chip.pc(),
+            chip.index(),
+            chip.stack().len()
+        );
+        self.text(0, *line, &text, false);
+        *line += 1;
+        for (i, registers) in chip.registers().chunks(4).enumerate() {
+            for (j, v) in registers.iter().enumerate() {
+                let text = format!("V{:X} {:02X}", i * 4 + j, v);
+                self.text(j as i32 * 7, *line, &text, false);
+            }
+            *line += 1;
+        }
+        let (delay, sound) = chip.timers();
+        let mut text = format!("DT {:02X}  ST {:02X}", delay, sound);
+        // the cycles left in the frame with the VIP timing
+        if let Some(budget) = chip.frame_budget() {
+            text += &format!("  CYCLES {}", budget);
+        }
+        self.text(0, *line, &text, false);
+        *line += 1;
+    }
+
+    fn stack(&mut self, chip: &Cpu, line: &mut i32) {
+        self.text(0, *line, "STACK", false);
+        *line += 1;
+        for (i, address) in chip.stack().iter().enumerate() {
+            let (column, row) = ((i % 4) as i32 * 5, (i / 4) as i32);
+            self.text(column, *line + row, &format!("{:04X}", address), false);
+        }
+        *line += 4;
+    }
+
+    fn keypad(&mut self, chip: &Cpu, line: &mut i32) {
+        self.text(0, *line, "KEYS", false);
+        for (i, held) in chip.key.iter().enumerate() {
+            self.text(5 + i as i32, *line, &format!("{:X}", i), *held);
+        }
+        *line += 1;
+    }
+
+    fn disassembly(&mut self, chip: &Cpu, line: &mut i32) {
+        let pc = chip.pc();
+        let start = pc.saturating_sub(DISASM_AROUND * 2);
+        let end = (pc + DISASM_AROUND * 2).min(chip.memory().len() - 2);
+        for address in (start..=end).step_by(2) {
+            let opcode = opcode(chip, address);
+            let instruction = disasm::listing(chip.memory(), address, chip.symbols());
+            let text = format!("{:04X} {:04X} {}", address, opcode, instruction);
+            self.text(0, *line, &text, address == pc);
+            *line += 1;
+        }
+    }
+
+    /// display the memory around I, the bytes read or written by the
+    /// instruction at the PC are highlighted
+    fn memory(&mut self, chip: &Cpu, line: &mut i32) {
+        let memory = chip.memory();
+        let index = chip.index() as usize;
+        let opcode = opcode(chip, chip.pc());
+        let highlighted = index..index + disasm::index_access(opcode).max(1);
+
+        // I is on the third line of the view
+        let last = memory.len() - MEMORY_LINES * MEMORY_COLUMNS;
+        let start = (index - index % MEMORY_COLUMNS)
+            .saturating_sub(MEMORY_COLUMNS * 2)
+            .min(last);
+        for row in 0..MEMORY_LINES {
+            let address = start + row * MEMORY_COLUMNS;
+            self.text(0, *line, &format!("{:04X}", address), false);
+            for column in 0..MEMORY_COLUMNS {
+                let address = address + column;
+                let text = format!("{:02X}", memory[address]);
+                let highlight = highlighted.contains(&address);
+                self.text(5 + column as i32 * 3, *line, &text, highlight);
+            }
+            *line += 1;
+        }
+    }
+
+    /// draw a text starting at the character `column` of the line `line`,
+    /// a highlighted text is drawn with the colors inverted
+    fn text(&mut self, column: i32, line: i32, text: &str, highlight: bool) {
+        let (x, y) = (self.x + 2 + column * CELL_WIDTH, 2 + line * CELL_HEIGHT);
+        let (mut background, mut foreground) =
+            (self.palette.background(), self.palette.foreground());
+        if highlight {
+            std::mem::swap(&mut background, &mut foreground);
+            let width = text.chars().count() as i32 * CELL_WIDTH + 1;
+            let rect = Rect::new(x - 1, y - 1, width as u32, CELL_HEIGHT as u32 + 1);
+            self.canvas.set_draw_color(sdl_color(background));
+            self.canvas.fill_rect(rect).unwrap();
+        }
+
+        let mut points = Vec::new();
+        for (i, c) in text.chars().enumerate() {
+            let x = x + i as i32 * CELL_WIDTH;
+            for (dy, bits) in font::glyph(c).iter().enumerate() {
+                for dx in 0..font::WIDTH {
+                    if bits & (0b100 >> dx) != 0 {
+                        points.push(Point::new(x + dx, y + dy as i32));
+                    }
+                }
+            }
+        }
+        self.canvas.set_draw_color(sdl_color(foreground));
+        self.canvas.draw_points(&points[..]).unwrap();
+    }
+}
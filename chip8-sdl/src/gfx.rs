@@ -1,20 +1,62 @@
+use crate::debugger::{Debugger, PANEL_WIDTH};
+use chip8_cpu::control::Control;
+use chip8_cpu::filter::{Persistence, Phosphor};
+use chip8_cpu::framebuffer::Framebuffer;
+use chip8_cpu::machine::Machine;
+use chip8_cpu::palette::{self, Themes};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
 use sdl2::render::Canvas;
-use sdl2::{rect::Point, video, EventPump, Sdl};
+use sdl2::render::Texture;
+use sdl2::{video, EventPump, Sdl};
+use std::ops::Range;
 use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 pub struct Gfx {
     frequency: Duration,
     current_time: Instant,
-    pub context: Sdl,
+    _context: Sdl, // SDL is shut down when it is dropped
     pub canvas: Canvas<video::Window>,
     event_pump: EventPump,
+    themes: Themes,
+    phosphor: Phosphor,
+    screen: Framebuffer,            // the last screen drawn by the game
+    faded: Instant,                 // the last frame given to the phosphor
+    colors: Option<Vec<u32>>,       // the last screen when the machine has its own colors
+    texture: Texture, // the screen of the game, only the lines which changed are updated
+    pixels: Vec<u8>,  // the screen converted to RGBA before being uploaded
+    audio: Option<AudioQueue<f32>>, // the sounds of the machine, the buzzer is not played
+    redraw: bool,     // the screen must be redrawn even if the chip8 didn't draw
+    status: Option<&'static str>, // state of the emulation displayed in the title
+    width: u32,       // size of the game area in the window
+    height: u32,
+    columns: usize, // size of the screen of the game in pixels
+    lines: usize,
+    debugger: bool,         // display the state of the cpu beside the game
+    debugger_time: Instant, // the last time the debugger was drawn
 }
 
-pub fn init_sdl(width: u32, height: u32) -> Gfx {
+/// convert a color of the palette to a sdl color
+fn sdl_color(color: palette::Color) -> Color {
+    Color::RGB(color.r, color.g, color.b)
+}
+
+/// the rate of the sounds played by the machine
+const AUDIO_RATE: i32 = 44100;
+
+/// a window of `width` x `height` showing a screen of `columns` x `lines` pixels
+pub fn init_sdl(
+    width: u32,
+    height: u32,
+    (columns, lines): (usize, usize),
+    themes: Themes,
+    persistence: Persistence,
+) -> Gfx {
     let context = sdl2::init().unwrap();
     let video_subsystem = context.video().unwrap();
 
@@ -26,66 +68,242 @@ pub fn init_sdl(width: u32, height: u32) -> Gfx {
 
     let mut canvas = window.into_canvas().build().unwrap();
     canvas
-        .set_scale(width as f32 / 64.0, height as f32 / 32.0)
+        .set_scale(width as f32 / columns as f32, height as f32 / lines as f32)
         .unwrap();
-    canvas.set_draw_color(Color::RGB(50, 50, 30));
+    canvas.set_draw_color(sdl_color(themes.current().background()));
     canvas.clear();
     canvas.present();
 
     let event_pump = context.event_pump().unwrap();
 
+    // the texture keeps the last screen so only the lines which changed are updated
+    let texture = canvas
+        .texture_creator()
+        .create_texture_streaming(PixelFormatEnum::RGBA32, columns as u32, lines as u32)
+        .unwrap();
+
+    // the game can run without sound
+    let desired = AudioSpecDesired {
+        freq: Some(AUDIO_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio = context
+        .audio()
+        .and_then(|audio| audio.open_queue::<f32, _>(None, &desired))
+        .map_err(|e| println!("Can't open the audio device : {}", e))
+        .ok();
+    if let Some(audio) = &audio {
+        audio.resume();
+    }
+
     Gfx {
         frequency: Duration::from_secs(1).checked_div(60).unwrap(),
         current_time: Instant::now(),
-        context,
+        _context: context,
         canvas,
         event_pump,
+        themes,
+        phosphor: Phosphor::new(persistence, columns, lines),
+        screen: Framebuffer::new(columns, lines, 1),
+        faded: Instant::now(),
+        colors: None,
+        texture,
+        pixels: vec![0; columns * lines * 4],
+        audio,
+        redraw: false,
+        status: None,
+        width,
+        height,
+        columns,
+        lines,
+        debugger: false,
+        debugger_time: Instant::now(),
     }
 }
 
 impl Gfx {
     /// update the screen with the data in the chip8
-    pub fn update_screen(&mut self, chip: &mut chip8_cpu::cpu::Cpu) {
-        if let Some(screen) = chip.update() {
-            self.canvas.set_draw_color(Color::RGB(50, 50, 30));
-            self.canvas.clear();
+    /// the debugger is refreshed every frame even if the chip8 didn't draw
+    pub fn update_screen(&mut self, chip: &mut dyn Machine, control: &Control) {
+        let drawn = match chip.update() {
+            Some(screen) => {
+                self.screen.clone_from(screen);
+                true
+            }
+            None => false,
+        };
+        if drawn {
+            self.colors = chip.argb().map(|colors| colors.to_vec());
+        }
+        // the phosphor gets the last screen every frame, so the pixels keep
+        // fading when the game doesn't draw
+        let mut lines = Vec::new();
+        if drawn || self.faded.elapsed() >= self.frequency {
+            lines = self.phosphor.apply(&self.screen).changed_lines();
+            self.faded = Instant::now();
+            self.resize(self.screen.width(), self.screen.height());
+        }
+        let debugger = self.debugger && self.debugger_time.elapsed() >= self.frequency;
+        if !drawn && lines.is_empty() && !self.redraw && !debugger {
+            return;
+        }
+        // the colors of the machine aren't tracked, they are all uploaded
+        if self.redraw || self.colors.is_some() {
+            lines = vec![self.phosphor.intensity().bounds().lines()];
+        }
+        self.upload(&lines);
+        self.redraw = false;
 
-            self.canvas.set_draw_color(Color::RGB(230, 255, 255));
-            self.render_game_screen(screen);
+        let background = self.themes.current().background();
+        self.canvas.set_draw_color(sdl_color(background));
+        self.canvas.clear();
 
-            self.canvas.present();
+        self.canvas
+            .set_scale(
+                self.width as f32 / self.columns as f32,
+                self.height as f32 / self.lines as f32,
+            )
+            .unwrap();
+        let screen = Rect::new(0, 0, self.columns as u32, self.lines as u32);
+        self.canvas.copy(&self.texture, None, screen).unwrap();
+        // only the chip 8 of the emulator can be debugged
+        if let Some(chip) = chip.cpu().filter(|_| self.debugger) {
+            Debugger::new(&mut self.canvas, self.themes.current(), self.width).render(chip);
+            self.debugger_time = Instant::now();
+        }
+
+        self.canvas.present();
+
+        // only the frames of the game are throttled
+        if !drawn {
+            return;
+        }
+        let frequency = control.throttle(self.frequency);
+        match frequency.checked_sub(self.current_time.elapsed()) {
+            None if control.turbo() => (),
+            None => println!("We are SLOW!"),
+            Some(t) => sleep(t),
+        }
+        self.current_time = Instant::now();
+    }
+
+    /// queue the sound of the next frames, a little ahead to not run out
+    pub fn play(&mut self, chip: &mut dyn Machine) {
+        let audio = match &self.audio {
+            Some(audio) => audio,
+            None => return,
+        };
+        let frame = (AUDIO_RATE / 60) as usize;
+        let queued = audio.size() as usize / std::mem::size_of::<f32>();
+        if queued < 2 * frame {
+            let mut samples = vec![0.0; frame];
+            chip.audio(AUDIO_RATE as u32, &mut samples);
+            if let Err(e) = audio.queue_audio(&samples) {
+                println!("Can't play the sound, stop playing : {}", e);
+                self.audio = None;
+            }
+        }
+    }
+
+    /// follow the size of the screen of the game, it's stretched over the
+    /// same area of the window
+    fn resize(&mut self, columns: usize, lines: usize) {
+        if (columns, lines) == (self.columns, self.lines) {
+            return;
+        }
+        let texture = self
+            .canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGBA32, columns as u32, lines as u32)
+            .unwrap();
+        // with `unsafe_textures` the textures aren't freed when dropped, the
+        // renderer which created the old one is still alive to destroy it
+        let old = std::mem::replace(&mut self.texture, texture);
+        unsafe { old.destroy() };
+        self.pixels = vec![0; columns * lines * 4];
+        self.columns = columns;
+        self.lines = lines;
+        self.redraw = true;
+    }
 
-            match self.frequency.checked_sub(self.current_time.elapsed()) {
-                None => println!("We are SLOW!"),
-                Some(t) => sleep(t),
+    /// Convert the `lines` of the screen and upload them in the texture,
+    /// the other lines keep what was drawn before
+    fn upload(&mut self, lines: &[Range<usize>]) {
+        let palette = self.themes.current();
+        let columns = self.columns;
+        for lines in lines {
+            let pixels = lines.start * columns..lines.end * columns;
+            match &self.colors {
+                Some(colors) => {
+                    let rgba = self.pixels[pixels.start * 4..pixels.end * 4].chunks_mut(4);
+                    for (rgba, argb) in rgba.zip(colors[pixels.clone()].iter()) {
+                        rgba.copy_from_slice(&palette::Color::from_argb(*argb).to_rgba());
+                    }
+                }
+                None => self.phosphor.intensity().lines_to_rgba(
+                    lines.clone(),
+                    |intensity| palette.shade(intensity),
+                    &mut self.pixels,
+                ),
             }
-            self.current_time = Instant::now();
+            let rect = Rect::new(0, lines.start as i32, columns as u32, lines.len() as u32);
+            self.texture
+                .update(
+                    rect,
+                    &self.pixels[pixels.start * 4..pixels.end * 4],
+                    columns * 4,
+                )
+                .unwrap();
         }
     }
 
-    /// create a bunch of point to represent the chip8 screen
-    fn render_game_screen(&mut self, arr: &[bool]) {
-        // EXTRA UNSAFE
-        let (width, height) = (64, 32);
-        let points = (0..height)
-            .flat_map(|y| {
-                (0..width).filter_map(move |x| match arr[x + y * width] {
-                    true => Some(Point::new(x as i32, y as i32)),
-                    false => None,
-                })
-            })
-            .collect::<Vec<Point>>();
-        self.canvas.draw_points(&points[..]).unwrap();
+    /// show or hide the debugger, the window is enlarged to make room for it
+    fn toggle_debugger(&mut self) {
+        self.debugger = !self.debugger;
+        let width = match self.debugger {
+            true => self.width + PANEL_WIDTH,
+            false => self.width,
+        };
+        self.canvas
+            .window_mut()
+            .set_size(width, self.height)
+            .unwrap();
+        self.redraw = true;
     }
 
-    /// check which keys were pressed
+    /// save the current screen in a png file
+    fn screenshot(&self) {
+        let palette = self.themes.current();
+        let pixels = match &self.colors {
+            Some(colors) => colors
+                .iter()
+                .map(|c| palette::Color::from_argb(*c))
+                .collect(),
+            None => self
+                .phosphor
+                .intensity()
+                .to_colors(|intensity| palette.shade(intensity)),
+        };
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let file = format!("chip8-{}.png", time.as_secs());
+
+        match chip8_cpu::screenshot::save_png(&file, self.columns, self.lines, &pixels, 10) {
+            Ok(()) => println!("Screenshot saved in {}", file),
+            Err(e) => println!("Can't save screenshot : {}", e),
+        }
+    }
+
+    /// check which keys were pressed and update the controls of the emulation
     /// return true if the user want to exit
-    pub fn handle_event(&mut self, keys: &mut [bool]) -> bool {
+    pub fn handle_event(&mut self, keys: &mut [bool], control: &mut Control) -> bool {
         // clear all keys
         for k in keys.iter_mut() {
             *k = false;
         }
 
+        // the event pump is borrowed while we read the events
+        let (mut screenshot, mut debugger) = (false, false);
         for event in self.event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -93,6 +311,48 @@ impl Gfx {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => return true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    self.themes.cycle();
+                    self.redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    repeat: false,
+                    ..
+                } => control.toggle_pause(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => control.reset(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    ..
+                } => control.frame_advance(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => control.toggle_slow_motion(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => control.set_turbo(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => control.set_turbo(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => debugger = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => screenshot = true,
                 Event::KeyDown {
                     keycode: Some(Keycode::Num1),
                     ..
@@ -160,6 +420,21 @@ impl Gfx {
                 _ => {}
             }
         }
+
+        if screenshot {
+            self.screenshot();
+        }
+        if debugger {
+            self.toggle_debugger();
+        }
+        if self.status != control.status() {
+            self.status = control.status();
+            let title = match self.status {
+                Some(status) => format!("chip 8 - {}", status),
+                None => String::from("chip 8"),
+            };
+            self.canvas.window_mut().set_title(&title).unwrap();
+        }
         false
     }
 }
@@ -1,31 +1,83 @@
+use chip8_cpu::control::Control;
+use chip8_cpu::filter::Persistence;
+use chip8_cpu::gdb::Gdb;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::options::Options;
 use std::env;
+use std::thread::sleep;
+use std::time::Duration;
 
+mod debugger;
+mod font;
 mod gfx;
 
+fn usage() -> String {
+    format!(
+        "Usage: chip8-sdl [--palette <name|colors>] [--palette-file <file>]
+    [--phosphor <off|frames:N|decay:RATIO>]
+    [--trace <file>] [--trace-format <text|binary>] [--trace-filter <filter>]
+    [--seed <n>] [--inputs <file>]
+    [--machine <{}>]
+    [--timing <instruction|vip>]
+    [--gdb <port>] [--symbols <file>] [--break <label|address|file:line>]...
+    [--vip <monitor rom> <interpreter>]
+    <game>
+
+Press escape to quit, F1 to pause, F2 to change the palette, F5 to reset the game,
+F6 to advance one frame, F7 for slow motion, F9 to show the debugger, hold tab for turbo
+and F12 for a screenshot.",
+        MachineConfig::PRESETS.join("|")
+    )
+}
+
 pub fn main() {
-    let filename = match env::args().nth(1) {
-        Some(f) => f,
-        None => {
-            println!("Need a game as argument");
-            return;
-        }
+    let options = match Options::parse(env::args().skip(1), &["--phosphor"]) {
+        Ok(options) => options,
+        Err(e) => return println!("{}\n\n{}", e, usage()),
+    };
+    let persistence = match options.extra("--phosphor").map(str::parse) {
+        Some(Ok(p)) => p,
+        Some(Err(e)) => return println!("{}", e),
+        None => Persistence::Off,
+    };
+    let (mut chip, breakpoints) = match options.machine() {
+        Ok(machine) => machine,
+        Err(e) => return println!("{}", e),
     };
 
-    let mut chip = chip8_cpu::new();
-
-    if let Err(e) = chip.load_game(&filename) {
-        println!("Can't load game : {}", e);
-        return;
+    let mut control = Control::default();
+    control.set_breakpoints(breakpoints);
+    if let Some(port) = options.gdb {
+        println!("Waiting for gdb on 127.0.0.1:{}", port);
+        match Gdb::listen(port) {
+            Ok(gdb) => control.set_gdb(gdb),
+            Err(e) => return println!("Can't wait for gdb : {}", e),
+        }
     }
 
-    let mut gfx = gfx::init_sdl(960, 480);
+    // the window keeps the proportions of the screen of the machine
+    let height = 960 * chip.height() as u32 / chip.width() as u32;
+    let mut gfx = gfx::init_sdl(
+        960,
+        height,
+        (chip.width(), chip.height()),
+        options.themes,
+        persistence,
+    );
 
     loop {
-        gfx.update_screen(&mut chip);
-        if gfx.handle_event(&mut chip.key) {
+        gfx.update_screen(chip.as_mut(), &control);
+        if gfx.handle_event(chip.keys(), &mut control) {
             break;
         }
 
-        chip.cycle();
+        if control.paused() {
+            sleep(Duration::from_millis(10));
+        }
+        control.run(chip.as_mut());
+        for message in control.take_messages() {
+            println!("{}", message);
+        }
+        gfx.play(chip.as_mut());
     }
 }
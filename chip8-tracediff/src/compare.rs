@@ -0,0 +1,122 @@
+use crate::side::Side;
+use chip8_cpu::trace::Entry;
+use std::collections::VecDeque;
+
+/// How the two runs compared
+#[derive(Debug)]
+pub enum Outcome {
+    /// no divergence in all the instructions asked
+    Same,
+    /// one of the traces ended after this number of instructions
+    End(usize),
+    /// the first instruction which didn't behave the same way
+    Divergence {
+        instruction: usize, // how many were executed before
+        left: Result<Option<Entry>, String>,
+        right: Result<Option<Entry>, String>,
+        history: Vec<Entry>, // the last ones executed by both sides
+    },
+}
+
+/// Step both sides until they diverge, at most `cycles` instructions.
+/// The `context` last instructions before the divergence are kept.
+pub fn compare(left: &mut Side, right: &mut Side, cycles: usize, context: usize) -> Outcome {
+    let mut history = VecDeque::with_capacity(context);
+    for instruction in 0..cycles {
+        let (a, b) = (left.step(), right.step());
+        match (&a, &b) {
+            (Ok(Some(a)), Ok(Some(b))) if a == b => {
+                // without context nothing is kept
+                if context > 0 {
+                    while history.len() >= context {
+                        history.pop_front();
+                    }
+                    history.push_back(a.clone());
+                }
+                continue;
+            }
+            (Ok(None), _) | (_, Ok(None)) => return Outcome::End(instruction),
+            _ => (),
+        }
+        return Outcome::Divergence {
+            instruction,
+            left: a,
+            right: b,
+            history: history.into(),
+        };
+    }
+    Outcome::Same
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_cpu::inputs::Inputs;
+    use chip8_cpu::machine::MachineConfig;
+
+    fn side(config: &str, rom: &[u8]) -> Side {
+        Side::new(config, MachineConfig::default(), rom, 0, &Inputs::default()).unwrap()
+    }
+
+    #[test]
+    fn first_divergence() {
+        let rom = [
+            0x60, 0x05, // V0 = 5
+            0x61, 0x03, // V1 = 3
+            0x62, 0x01, // V2 = 1
+            0x80, 0x16, // V0 = V0 >> 1, or V1 >> 1 with the cosmac quirks
+            0x12, 0x06, // loop
+        ];
+        let (mut left, mut right) = (side("default", &rom), side("cosmac", &rom));
+        let (instruction, left, right, history) = match compare(&mut left, &mut right, 100, 2) {
+            Outcome::Divergence {
+                instruction,
+                left,
+                right,
+                history,
+            } => (instruction, left, right, history),
+            outcome => panic!("no divergence: {:?}", outcome),
+        };
+        assert_eq!(instruction, 3);
+        let (left, right) = (left.unwrap().unwrap(), right.unwrap().unwrap());
+        assert_eq!((left.pc, left.opcode), (0x206, 0x8016));
+        assert_eq!(left.to_string(), "0206 8016 SHR V0, V1       V0=02 VF=01");
+        assert_eq!(right.to_string(), "0206 8016 SHR V0, V1       V0=01 VF=01");
+        // only the last two of the three instructions before
+        let pcs = history.iter().map(|entry| entry.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, [0x202, 0x204]);
+    }
+
+    #[test]
+    fn without_context() {
+        let rom = [0x60, 0x05, 0x80, 0x16, 0x12, 0x00];
+        let (mut left, mut right) = (side("default", &rom), side("cosmac", &rom));
+        match compare(&mut left, &mut right, 100, 0) {
+            Outcome::Divergence { history, .. } => assert!(history.is_empty()),
+            outcome => panic!("no divergence: {:?}", outcome),
+        }
+
+        let (mut left, mut right) = (side("default", &rom), side("default", &rom));
+        assert!(matches!(
+            compare(&mut left, &mut right, 100, 0),
+            Outcome::Same
+        ));
+    }
+
+    #[test]
+    fn fault() {
+        // the PC leaves the memory after the jump
+        let rom = [0x1F, 0xFF];
+        let (mut left, mut right) = (side("default", &rom), side("schip", &rom));
+        match compare(&mut left, &mut right, 100, 0) {
+            Outcome::Divergence {
+                instruction, left, ..
+            } => {
+                assert_eq!(instruction, 1);
+                let error = left.unwrap_err();
+                assert!(error.starts_with("Access outside of the memory at 1000\n"));
+            }
+            outcome => panic!("no divergence: {:?}", outcome),
+        }
+    }
+}
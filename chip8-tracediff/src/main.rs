@@ -0,0 +1,212 @@
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::inputs::Inputs;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::quirks::Quirks;
+use chip8_cpu::trace::Entry;
+use std::env;
+use std::fs;
+use std::process;
+
+mod compare;
+mod side;
+use crate::compare::{compare, Outcome};
+use crate::side::Side;
+
+fn usage() -> String {
+    format!(
+        "Usage: chip8-tracediff [--seed <n>] [--inputs <file>] [--cycles <n>]
+    [--context <n>] [--machine <{}>]
+    <game> <config> <config>
+
+Run the game with the two configurations and stop at the first instruction
+which doesn't behave the same way. A configuration is either a quirks preset
+({}) or a trace file recorded by a frontend with --trace
+and the same --seed and --inputs as given here.
+The inputs file contains one `CYCLE down|up KEY` per line.",
+        MachineConfig::PRESETS.join("|"),
+        Quirks::PRESETS.join(", ")
+    )
+}
+
+/// number of bytes per line in the memory dumps
+const DUMP_WIDTH: usize = 16;
+
+fn main() {
+    let mut seed = 0;
+    let mut inputs = Inputs::default();
+    let mut cycles = 100_000;
+    let mut context = 8;
+    let mut machine = MachineConfig::default();
+    let mut positional = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => match args.next().map(|s| s.parse()) {
+                Some(Ok(s)) => seed = s,
+                Some(Err(e)) => return println!("Invalid seed : {}", e),
+                None => return println!("{}", usage()),
+            },
+            "--inputs" => match args.next().map(|f| Inputs::load(&f)) {
+                Some(Ok(i)) => inputs = i,
+                Some(Err(e)) => return println!("Can't load inputs : {}", e),
+                None => return println!("{}", usage()),
+            },
+            "--cycles" => match args.next().map(|c| c.parse()) {
+                Some(Ok(c)) => cycles = c,
+                Some(Err(e)) => return println!("Invalid number of cycles : {}", e),
+                None => return println!("{}", usage()),
+            },
+            "--context" => match args.next().map(|c| c.parse()) {
+                Some(Ok(c)) => context = c,
+                Some(Err(e)) => return println!("Invalid context : {}", e),
+                None => return println!("{}", usage()),
+            },
+            "--machine" => match args.next().map(|m| m.parse()) {
+                Some(Ok(m)) => machine = m,
+                Some(Err(e)) => return println!("{}", e),
+                None => return println!("{}", usage()),
+            },
+            _ => positional.push(arg),
+        }
+    }
+
+    let (game, left, right) = match positional.as_slice() {
+        [game, left, right] => (game, left, right),
+        _ => return println!("{}", usage()),
+    };
+    let game = match fs::read(game) {
+        Ok(game) => game,
+        Err(e) => return println!("Can't load game : {}", e),
+    };
+    let mut sides = Vec::new();
+    for config in [left, right].iter() {
+        match Side::new(config, machine, &game, seed, &inputs) {
+            Ok(side) => sides.push(side),
+            Err(e) => return println!("{}", e),
+        }
+    }
+    let (mut left, mut right) = (sides.remove(0), sides.remove(0));
+
+    let (instruction, a, b, history) = match compare(&mut left, &mut right, cycles, context) {
+        Outcome::Same => return println!("No divergence in {} instructions", cycles),
+        Outcome::End(instruction) => {
+            return println!(
+                "The traces end after {} instructions without divergence",
+                instruction
+            )
+        }
+        Outcome::Divergence {
+            instruction,
+            left,
+            right,
+            history,
+        } => (instruction, left, right, history),
+    };
+
+    println!("First divergence after {} instructions:", instruction);
+    let width = left.name().len().max(right.name().len());
+    for (side, result) in [(&left, &a), (&right, &b)].iter() {
+        match result {
+            Ok(Some(entry)) => println!("  {:w$}  {}", side.name(), entry, w = width),
+            Ok(None) => (),
+            Err(e) => println!("  {:w$}  crashed : {}", side.name(), e, w = width),
+        }
+    }
+    println!();
+    println!("Previous instructions:");
+    for entry in &history {
+        println!("  {}", entry);
+    }
+    for side in [&left, &right].iter() {
+        if let Some(chip) = side.chip() {
+            println!();
+            println!("State of {} after the instruction:", side.name());
+            dump_state(chip, [&a, &b].iter().find_map(|r| pc_of(r)));
+        }
+    }
+    if let (Some(l), Some(r)) = (left.chip(), right.chip()) {
+        println!();
+        dump_memory_diff(l, r, left.name(), right.name());
+    }
+    process::exit(1);
+}
+
+/// the address of the instruction which diverged
+fn pc_of(result: &Result<Option<Entry>, String>) -> Option<usize> {
+    match result {
+        Ok(Some(entry)) => Some(entry.pc as usize),
+        _ => None,
+    }
+}
+
+fn dump_state(chip: &Cpu, diverged: Option<usize>) {
+    let (delay, sound) = chip.timers();
+    println!(
+        "  PC {:04X}  I {:04X}  DT {:02X}  ST {:02X}",
+        chip.pc(),
+        chip.index(),
+        delay,
+        sound
+    );
+    for (line, registers) in chip.registers().chunks(8).enumerate() {
+        let text = registers
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X} {:02X}", line * 8 + i, v))
+            .collect::<Vec<_>>();
+        println!("  {}", text.join("  "));
+    }
+    let stack = chip
+        .stack()
+        .iter()
+        .map(|a| format!("{:04X}", a))
+        .collect::<Vec<_>>();
+    println!("  stack [{}]", stack.join(" "));
+
+    if let Some(pc) = diverged {
+        println!("  memory around the instruction:");
+        dump_memory(chip.memory(), pc);
+    }
+    println!("  memory around I:");
+    dump_memory(chip.memory(), chip.index() as usize);
+}
+
+/// three lines of memory with `address` on the second one
+fn dump_memory(memory: &[u8], address: usize) {
+    let last = memory.len() - DUMP_WIDTH * 3;
+    let start = (address - address % DUMP_WIDTH)
+        .saturating_sub(DUMP_WIDTH)
+        .min(last);
+    for (i, bytes) in memory[start..start + DUMP_WIDTH * 3]
+        .chunks(DUMP_WIDTH)
+        .enumerate()
+    {
+        let bytes = bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>();
+        println!("    {:04X}  {}", start + i * DUMP_WIDTH, bytes.join(" "));
+    }
+}
+
+/// list the bytes which are not the same in the two memories
+fn dump_memory_diff(left: &Cpu, right: &Cpu, left_name: &str, right_name: &str) {
+    let differences = left
+        .memory()
+        .iter()
+        .zip(right.memory().iter())
+        .enumerate()
+        .filter(|(_, (l, r))| l != r)
+        .collect::<Vec<_>>();
+    if differences.is_empty() {
+        return println!("The memories are identical");
+    }
+    println!("Memory differences ({} / {}):", left_name, right_name);
+    for (address, (l, r)) in differences.iter().take(16) {
+        println!("  {:04X}  {:02X} / {:02X}", address, l, r);
+    }
+    if differences.len() > 16 {
+        println!("  and {} more", differences.len() - 16);
+    }
+}
@@ -0,0 +1,84 @@
+use chip8_cpu::cpu::Cpu;
+use chip8_cpu::inputs::Inputs;
+use chip8_cpu::machine::MachineConfig;
+use chip8_cpu::quirks::Quirks;
+use chip8_cpu::trace::{self, Entry};
+use std::fs::File;
+
+/// One of the two runs we compare
+pub enum Side {
+    /// the game running in the emulator with a quirks preset
+    Emulator { name: String, chip: Box<Cpu> },
+    /// a trace recorded earlier, by this emulator or another one
+    Reference {
+        name: String,
+        entries: std::vec::IntoIter<Entry>,
+    },
+}
+
+impl Side {
+    /// `config` is the name of a quirks preset or the path of a trace file
+    pub fn new(
+        config: &str,
+        machine: MachineConfig,
+        game: &[u8],
+        seed: u64,
+        inputs: &Inputs,
+    ) -> Result<Self, String> {
+        if let Some(quirks) = Quirks::preset(config) {
+            let mut chip = Box::new(Cpu::with_config(machine)?);
+            chip.load_rom(game);
+            chip.set_quirks(quirks);
+            chip.set_seed(seed);
+            chip.set_inputs(Some(inputs.clone()));
+            return Ok(Side::Emulator {
+                name: String::from(config),
+                chip,
+            });
+        }
+
+        let file = File::open(config).map_err(|e| format!("Can't open {} : {}", config, e))?;
+        let entries = trace::read(file).map_err(|e| format!("Can't read {} : {}", config, e))?;
+        Ok(Side::Reference {
+            name: String::from(config),
+            entries: entries.into_iter(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Side::Emulator { name, .. } | Side::Reference { name, .. } => name,
+        }
+    }
+
+    /// the cpu, if this side is running in the emulator
+    pub fn chip(&self) -> Option<&Cpu> {
+        match self {
+            Side::Emulator { chip, .. } => Some(chip),
+            Side::Reference { .. } => None,
+        }
+    }
+
+    /// Execute the next instruction.
+    /// Return `None` at the end of a reference trace and an error if the
+    /// emulator crashed.
+    pub fn step(&mut self) -> Result<Option<Entry>, String> {
+        let chip = match self {
+            Side::Emulator { chip, .. } => chip,
+            Side::Reference { entries, .. } => return Ok(entries.next()),
+        };
+
+        let (pc, registers, index) = (chip.pc(), *chip.registers(), chip.index());
+        // out of the memory the cpu faults, the opcode isn't reported
+        let opcode = match chip.memory().get(pc..pc + 2) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]),
+            _ => 0,
+        };
+
+        chip.cycle()
+            .map_err(|fault| format!("{}\n{}", fault, chip.stack_trace()))?;
+
+        let after = (chip.registers(), chip.index());
+        Ok(Some(Entry::new(pc, opcode, (&registers, index), after)))
+    }
+}